@@ -13,7 +13,7 @@ import Element.Font as Font
 import Element.Input as Input
 import Html
 import Http
-import Json.Decode exposing (Decoder, field, list, string)
+import Json.Decode exposing (Decoder, bool, field, list, nullable, string)
 import Spinner
 import Styles exposing (colors, defaultAttributes, fontSizes)
 
@@ -50,8 +50,36 @@ mailboxesDecoder =
     list (list (field "name" (list string)))
 
 
+type alias Summary =
+    { subject : Maybe String
+    , snippet : Maybe String
+    , list : Maybe MailingList
+    }
+
+
+type alias MailingList =
+    { id : String
+    , name : Maybe String
+    , unsubscribe : Bool
+    }
+
+
+mailingListDecoder : Decoder MailingList
+mailingListDecoder =
+    Json.Decode.map3 MailingList
+        (field "id" string)
+        (field "name" (nullable string))
+        (field "unsubscribe" bool)
+
+
+subjectsDecoder : Decoder (List Summary)
 subjectsDecoder =
-    list string
+    list
+        (Json.Decode.map3 Summary
+            (field "subject" (nullable string))
+            (field "snippet" (nullable string))
+            (field "list" (nullable mailingListDecoder))
+        )
 
 
 
@@ -176,7 +204,7 @@ type PortalForm
 
 type HomePanel
     = HomePanelEmpty
-    | HomePanelSubjects (List String)
+    | HomePanelSubjects (List Summary)
     | HomePanelAddImapAccountForm
 
 
@@ -213,7 +241,7 @@ type Msg
     | GoToRegisterFormMsg
     | GoToPanelAddImapAccount
     | MailboxesMsg (Result Http.Error (List (List (List String))))
-    | SubjectsMsg (Result Http.Error (List String))
+    | SubjectsMsg (Result Http.Error (List Summary))
     | SpinnerMsg Spinner.Msg
 
 
@@ -540,7 +568,7 @@ homePanel content spinner =
                     ( [ Element.html (Spinner.view Spinner.defaultConfig spinner) ], False )
 
                 HomePanelSubjects subjects ->
-                    ( List.map Element.text subjects, True )
+                    ( List.map summaryView subjects, True )
 
                 HomePanelAddImapAccountForm ->
                     ( [ homePanelAddImapAccountForm content.addImapAccountForm ], True )
@@ -711,6 +739,28 @@ menuItem message linkText =
 
 
 
+summaryView : Summary -> Element Msg
+summaryView summary =
+    let
+        listName =
+            case summary.list of
+                Just mailingList ->
+                    [ Element.paragraph [ Font.size fontSizes.small, Font.italic ]
+                        [ Element.text (Maybe.withDefault mailingList.id mailingList.name) ]
+                    ]
+
+                Nothing ->
+                    []
+    in
+    Element.column [ Element.width Element.fill, Element.spacing 5, Element.paddingXY 0 10 ]
+        (listName
+            ++ [ Element.paragraph [ Font.bold ] [ Element.text (Maybe.withDefault "(no subject)" summary.subject) ]
+               , Element.paragraph [ Font.size fontSizes.small ] [ Element.text (Maybe.withDefault "" summary.snippet) ]
+               ]
+        )
+
+
+
 -- PORTAL VIEWS ---------------------------------------------------------------
 
 
@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nom_mail_parser::{parse, parse_lenient};
+
+fuzz_target!(|data: &[u8]| {
+    // Parsing must never panic, and the lenient parser must accept what the strict one accepts.
+    let strict = parse(data);
+    let lenient = parse_lenient(data);
+
+    if let Ok(mail) = strict {
+        assert_eq!(mail.subject(), lenient.subject());
+        let _ = mail.text();
+        let _ = mail.attachments().count();
+    }
+});
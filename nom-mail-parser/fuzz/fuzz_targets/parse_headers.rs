@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nom_mail_parser::{parse_headers, parse_headers_lenient};
+
+fuzz_target!(|data: &[u8]| {
+    // Parsing must never panic, and the errors must point inside the input.
+    if let Err(e) = parse_headers(data) {
+        assert!(e.offset <= data.len());
+    }
+
+    let _ = parse_headers_lenient(data);
+});
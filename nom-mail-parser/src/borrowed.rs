@@ -0,0 +1,338 @@
+//! This module contains a borrowed representation of mails, that keeps slices of the input and
+//! only decodes the headers and the bodies when they are accessed.
+//!
+//! It is useful when only a few headers of many mails are needed, for example to list the subjects
+//! of a mailbox.
+
+use std::borrow::Cow;
+
+use crate::{Address, Recipient, Date, MessageId, ListId, ContentType, ContentDisposition, ContentTransferEncoding, Header};
+use crate::{Result, charset, parser, pgp};
+
+/// A header of a mail, that is not decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field<'a> {
+    /// The name of the header, as it appears in the mail.
+    ///
+    /// The lines that are not headers, which are only kept by the lenient parsers, have an empty
+    /// name.
+    pub name: &'a str,
+
+    /// The raw value of the header, which may be folded on several lines.
+    pub value: &'a [u8],
+}
+
+impl<'a> Field<'a> {
+    /// Returns the value of the header without its line breaks.
+    pub fn unfolded(&self) -> Cow<'a, [u8]> {
+        if self.value.contains(&b'\n') {
+            Cow::Owned(parser::unfold(self.value))
+        } else {
+            Cow::Borrowed(self.value)
+        }
+    }
+
+    /// Decodes the header.
+    ///
+    /// Returns `None` if the header is known but its value is invalid.
+    pub fn decode(&self) -> Option<Header> {
+        parser::header(self.name, &self.unfolded())
+    }
+}
+
+/// The headers of a mail, that are not decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Headers<'a>(pub Vec<Field<'a>>);
+
+impl<'a> Headers<'a> {
+    /// Returns the first header with this name, if any.
+    ///
+    /// The names of the headers are not case sensitive.
+    pub fn get(&self, name: &str) -> Option<&Field<'a>> {
+        self.0.iter().find(|x| x.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns all the headers with this name, in the order in which they appear.
+    pub fn get_all(&self, name: &str) -> Vec<&Field<'a>> {
+        self.0.iter().filter(|x| x.name.eq_ignore_ascii_case(name)).collect()
+    }
+
+    /// Decodes the first header with this name, if any.
+    fn decode(&self, name: &str) -> Option<Header> {
+        self.get(name).and_then(Field::decode)
+    }
+
+    /// Returns the subject of the mail, if any.
+    pub fn subject(&self) -> Option<String> {
+        match self.decode("subject") {
+            Some(Header::Subject(subject)) => Some(subject),
+            _ => None,
+        }
+    }
+
+    /// Returns the date of the mail, if any.
+    pub fn date(&self) -> Option<Date> {
+        match self.decode("date") {
+            Some(Header::Date(date)) => Some(date),
+            _ => None,
+        }
+    }
+
+    /// Returns the authors of the mail, if any.
+    pub fn from(&self) -> Option<Vec<Address>> {
+        match self.decode("from") {
+            Some(Header::From(from)) => Some(from),
+            _ => None,
+        }
+    }
+
+    /// Returns the sender of the mail, if any.
+    pub fn sender(&self) -> Option<Address> {
+        match self.decode("sender") {
+            Some(Header::Sender(sender)) => Some(sender),
+            _ => None,
+        }
+    }
+
+    /// Returns the reply to addresses of the mail, if any.
+    pub fn reply_to(&self) -> Option<Vec<Recipient>> {
+        match self.decode("reply-to") {
+            Some(Header::ReplyTo(reply_to)) => Some(reply_to),
+            _ => None,
+        }
+    }
+
+    /// Returns the primary recipients of the mail, if any.
+    pub fn to(&self) -> Option<Vec<Recipient>> {
+        match self.decode("to") {
+            Some(Header::To(to)) => Some(to),
+            _ => None,
+        }
+    }
+
+    /// Returns the carbon copy recipients of the mail, if any.
+    pub fn cc(&self) -> Option<Vec<Recipient>> {
+        match self.decode("cc") {
+            Some(Header::Cc(cc)) => Some(cc),
+            _ => None,
+        }
+    }
+
+    /// Returns the blind carbon copy recipients of the mail, if any.
+    pub fn bcc(&self) -> Option<Vec<Recipient>> {
+        match self.decode("bcc") {
+            Some(Header::Bcc(bcc)) => Some(bcc),
+            _ => None,
+        }
+    }
+
+    /// Returns the identifier of the mail, if any.
+    pub fn message_id(&self) -> Option<MessageId> {
+        match self.decode("message-id") {
+            Some(Header::MessageId(message_id)) => Some(message_id),
+            _ => None,
+        }
+    }
+
+    /// Returns the identifiers of the mails to which the mail replies, if any.
+    pub fn in_reply_to(&self) -> Option<Vec<MessageId>> {
+        match self.decode("in-reply-to") {
+            Some(Header::InReplyTo(in_reply_to)) => Some(in_reply_to),
+            _ => None,
+        }
+    }
+
+    /// Returns the identifiers of the mails of the conversation of the mail, if any.
+    pub fn references(&self) -> Option<Vec<MessageId>> {
+        match self.decode("references") {
+            Some(Header::References(references)) => Some(references),
+            _ => None,
+        }
+    }
+
+    /// Returns the content type of the mail, if any.
+    pub fn content_type(&self) -> Option<ContentType> {
+        match self.decode("content-type") {
+            Some(Header::ContentType(content_type)) => Some(content_type),
+            _ => None,
+        }
+    }
+
+    /// Returns the content disposition of the mail, if any.
+    pub fn content_disposition(&self) -> Option<ContentDisposition> {
+        match self.decode("content-disposition") {
+            Some(Header::ContentDisposition(content_disposition)) => Some(content_disposition),
+            _ => None,
+        }
+    }
+
+    /// Returns the content transfer encoding of the mail, if any.
+    pub fn content_transfer_encoding(&self) -> Option<ContentTransferEncoding> {
+        match self.decode("content-transfer-encoding") {
+            Some(Header::ContentTransferEncoding(encoding)) => Some(encoding),
+            _ => None,
+        }
+    }
+
+    /// Returns the identifier of the mailing list that sent the mail, if any.
+    pub fn list_id(&self) -> Option<ListId> {
+        match self.decode("list-id") {
+            Some(Header::ListId(list_id)) => Some(list_id),
+            _ => None,
+        }
+    }
+
+    /// Returns the URLs to unsubscribe from the mailing list that sent the mail, if any.
+    pub fn list_unsubscribe(&self) -> Option<Vec<String>> {
+        match self.decode("list-unsubscribe") {
+            Some(Header::ListUnsubscribe(urls)) => Some(urls),
+            _ => None,
+        }
+    }
+
+    /// Returns the URLs to post to the mailing list that sent the mail, if any.
+    pub fn list_post(&self) -> Option<Vec<String>> {
+        match self.decode("list-post") {
+            Some(Header::ListPost(urls)) => Some(urls),
+            _ => None,
+        }
+    }
+
+    /// Returns the URLs of the archives of the mailing list that sent the mail, if any.
+    pub fn list_archive(&self) -> Option<Vec<String>> {
+        match self.decode("list-archive") {
+            Some(Header::ListArchive(urls)) => Some(urls),
+            _ => None,
+        }
+    }
+
+    /// Parses the headers of a mail without decoding them.
+    pub fn parse(bytes: &'a [u8]) -> Result<Headers<'a>> {
+        parser::parse_headers_borrowed(bytes)
+    }
+
+    /// Parses the headers of a mail without decoding them, recovering from the errors.
+    pub fn parse_lenient(bytes: &'a [u8]) -> Headers<'a> {
+        parser::parse_headers_borrowed_lenient(bytes)
+    }
+
+    /// Decodes all the headers, keeping the invalid ones as unknown headers.
+    pub fn into_owned(self) -> crate::Headers {
+        parser::headers_into_owned(&self)
+    }
+}
+
+/// The body of a mail, that is not decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Body<'a> {
+    /// It can be some content, that is still encoded with its content transfer encoding.
+    Content(&'a [u8]),
+
+    /// It can be a multipart mail, containing some sub mails.
+    Multi {
+        /// The subtype of the multipart content, e.g. `mixed` or `alternative`.
+        subtype: String,
+
+        /// The parts of the mail.
+        parts: Vec<Mail<'a>>,
+    },
+
+    /// It can be a whole mail that is not encoded, such as a forwarded mail.
+    ///
+    /// The embedded mails that are encoded with quoted printable or base64 are kept as content,
+    /// since they have to be decoded before being parsed.
+    Message(Box<Mail<'a>>),
+}
+
+/// A mail that is not decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mail<'a> {
+    /// The headers of the mail.
+    pub(crate) headers: Headers<'a>,
+
+    /// The body of the mail.
+    pub(crate) body: Body<'a>,
+
+    /// The raw bytes of the mail, with its headers.
+    pub(crate) raw: &'a [u8],
+}
+
+impl<'a> Mail<'a> {
+    /// Parses a mail without decoding it.
+    ///
+    /// Only the structure of the mail is checked, and the values of the headers are decoded by
+    /// the accessors.
+    pub fn parse(bytes: &'a [u8]) -> Result<Mail<'a>> {
+        parser::parse_borrowed(bytes)
+    }
+
+    /// Parses a mail without decoding it, recovering from the errors.
+    pub fn parse_lenient(bytes: &'a [u8]) -> Mail<'a> {
+        parser::parse_borrowed_lenient(bytes)
+    }
+
+    /// Returns the headers of the mail.
+    pub fn headers(&self) -> &Headers<'a> {
+        &self.headers
+    }
+
+    /// Returns the body of the mail.
+    pub fn body(&self) -> &Body<'a> {
+        &self.body
+    }
+
+    /// Returns the raw bytes of the mail, with its headers.
+    ///
+    /// The raw bytes of a part don't contain the line break that precedes the next boundary, since
+    /// it belongs to the boundary.
+    pub fn raw(&self) -> &'a [u8] {
+        self.raw
+    }
+
+    /// Returns the subject of the mail, if any.
+    pub fn subject(&self) -> Option<String> {
+        self.headers.subject()
+    }
+
+    /// Returns the content of the mail, decoded according to its content transfer encoding.
+    ///
+    /// Returns none if the mail is a multipart mail or an embedded mail.
+    pub fn content(&self) -> Option<Cow<'a, [u8]>> {
+        let content = match self.body {
+            Body::Content(content) => content,
+            Body::Multi { .. } | Body::Message(_) => return None,
+        };
+
+        Some(match self.headers.content_transfer_encoding() {
+            Some(encoding @ ContentTransferEncoding::QuotedPrintable) |
+            Some(encoding @ ContentTransferEncoding::Base64) => Cow::Owned(encoding.decode(content)),
+            _ => Cow::Borrowed(content),
+        })
+    }
+
+    /// Returns the text of the mail, converted from its charset to UTF-8.
+    ///
+    /// Returns none if the mail is not a text mail.
+    pub fn text(&self) -> Option<String> {
+        let content_type = self.headers.content_type();
+
+        if !content_type.as_ref().map(ContentType::is_text).unwrap_or(true) {
+            return None;
+        }
+
+        let content = self.content()?;
+        let encoding = charset::detect(content_type.as_ref().and_then(ContentType::charset), &content);
+        Some(charset::decode_with(encoding, &content))
+    }
+
+    /// Returns the OpenPGP protection of the mail, if it is signed or encrypted as specified by
+    /// RFC 3156.
+    pub fn protection(&self) -> Option<pgp::Protection> {
+        pgp::protection(self)
+    }
+
+    /// Decodes the whole mail, keeping the invalid headers as unknown headers.
+    pub fn into_owned(self) -> crate::Mail {
+        parser::into_owned(self)
+    }
+}
@@ -0,0 +1,248 @@
+//! This module contains a builder that composes mails from their headers, their text, their HTML
+//! and their attachments.
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::{Address, Recipient, Date, MessageId, ContentType, ContentDisposition, DispositionType};
+use crate::{Header, Headers, Mail, Body, flowed};
+
+/// Normalizes the line breaks of some text to CRLF, as required by MIME for text content.
+fn normalize_line_breaks(text: &str) -> Vec<u8> {
+    text.replace("\r\n", "\n").replace('\n', "\r\n").into_bytes()
+}
+
+/// Creates a text part, such as `text/plain` or `text/html`, encoded in UTF-8.
+fn text_part(subtype: &str, text: &str, parameters: Vec<(String, String)>) -> Mail {
+    let content_type = ContentType {
+        main_type: String::from("text"),
+        subtype: subtype.to_string(),
+        parameters: vec![(String::from("charset"), String::from("utf-8"))].into_iter().chain(parameters).collect(),
+    };
+
+    Mail::new(Headers(vec![Header::ContentType(content_type)]), Body::Content(normalize_line_breaks(text)))
+}
+
+/// Creates a plain text part in the flowed format, so that it can be reflowed by the clients.
+fn flowed_part(text: &str) -> Mail {
+    text_part("plain", &flowed::encode(text), vec![(String::from("format"), String::from("flowed"))])
+}
+
+/// Creates a multipart part.
+///
+/// Its boundary is chosen when it is serialized, so that it doesn't appear in its parts.
+fn multipart(subtype: &str, parts: Vec<Mail>) -> Mail {
+    let content_type = ContentType {
+        main_type: String::from("multipart"),
+        subtype: subtype.to_string(),
+        parameters: vec![],
+    };
+
+    let body = Body::Multi {
+        subtype: subtype.to_string(),
+        parts,
+    };
+
+    Mail::new(Headers(vec![Header::ContentType(content_type)]), body)
+}
+
+/// A builder that composes a mail.
+///
+/// The text, the HTML and the calendar of the mail become a `multipart/alternative` part, and the attachments and
+/// the other parts are added along them in a `multipart/mixed` mail.
+#[derive(Default)]
+pub struct MailBuilder {
+    /// The headers of the mail, except for the recipients.
+    headers: Vec<Header>,
+
+    /// The primary recipients of the mail.
+    to: Vec<Recipient>,
+
+    /// The carbon copy recipients of the mail.
+    cc: Vec<Recipient>,
+
+    /// The blind carbon copy recipients of the mail.
+    bcc: Vec<Recipient>,
+
+    /// The plain text version of the mail, if any.
+    text: Option<String>,
+
+    /// The HTML version of the mail, if any.
+    html: Option<String>,
+
+    /// The calendar version of the mail with its method, if any, such as an invitation or a reply
+    /// to an invitation.
+    calendar: Option<(String, String)>,
+
+    /// The attachments and the other parts of the mail.
+    parts: Vec<Mail>,
+}
+
+impl MailBuilder {
+    /// Creates a builder for an empty mail.
+    pub fn new() -> MailBuilder {
+        MailBuilder::default()
+    }
+
+    /// Adds a header to the mail.
+    pub fn header(mut self, header: Header) -> MailBuilder {
+        self.headers.push(header);
+        self
+    }
+
+    /// Sets the subject of the mail.
+    pub fn subject(self, subject: &str) -> MailBuilder {
+        self.header(Header::Subject(subject.to_string()))
+    }
+
+    /// Sets the date of the mail.
+    pub fn date(self, date: DateTime<FixedOffset>) -> MailBuilder {
+        self.header(Header::Date(Date::Valid(date)))
+    }
+
+    /// Sets the author of the mail.
+    pub fn from(self, from: Address) -> MailBuilder {
+        self.header(Header::From(vec![from]))
+    }
+
+    /// Adds a primary recipient to the mail.
+    pub fn to(mut self, to: Address) -> MailBuilder {
+        self.to.push(Recipient::Address(to));
+        self
+    }
+
+    /// Adds a carbon copy recipient to the mail.
+    pub fn cc(mut self, cc: Address) -> MailBuilder {
+        self.cc.push(Recipient::Address(cc));
+        self
+    }
+
+    /// Adds a blind carbon copy recipient to the mail.
+    ///
+    /// The blind carbon copy recipients are kept in the headers of the mail, so that it can be
+    /// sent to them, but they are not serialized.
+    pub fn bcc(mut self, bcc: Address) -> MailBuilder {
+        self.bcc.push(Recipient::Address(bcc));
+        self
+    }
+
+    /// Sets the identifier of the mail.
+    pub fn message_id(self, id: MessageId) -> MailBuilder {
+        self.header(Header::MessageId(id))
+    }
+
+    /// Sets the identifiers of the mails to which the mail replies.
+    pub fn in_reply_to(self, ids: Vec<MessageId>) -> MailBuilder {
+        self.header(Header::InReplyTo(ids))
+    }
+
+    /// Sets the identifiers of the mails of the conversation to which the mail belongs.
+    pub fn references(self, ids: Vec<MessageId>) -> MailBuilder {
+        self.header(Header::References(ids))
+    }
+
+    /// Sets the plain text version of the mail.
+    ///
+    /// The lines of the text are paragraphs, that are wrapped in the flowed format of RFC 3676.
+    pub fn text(mut self, text: &str) -> MailBuilder {
+        self.text = Some(text.to_string());
+        self
+    }
+
+    /// Sets the HTML version of the mail.
+    pub fn html(mut self, html: &str) -> MailBuilder {
+        self.html = Some(html.to_string());
+        self
+    }
+
+    /// Sets the calendar version of the mail, with the method of the calendar, e.g. `REPLY`.
+    ///
+    /// The calendar is not parsed, so it should be a valid iCalendar object with the same method.
+    pub fn calendar(mut self, method: &str, calendar: &str) -> MailBuilder {
+        self.calendar = Some((method.to_string(), calendar.to_string()));
+        self
+    }
+
+    /// Adds an attachment to the mail, with its filename and its MIME type, e.g.
+    /// `application/pdf`.
+    ///
+    /// Invalid MIME types are replaced by `application/octet-stream`.
+    pub fn attachment(self, filename: &str, mime_type: &str, content: Vec<u8>) -> MailBuilder {
+        let mut split = mime_type.splitn(2, '/');
+
+        let (main_type, subtype) = match (split.next(), split.next()) {
+            (Some(main_type), Some(subtype)) if !main_type.is_empty() && !subtype.is_empty() => (main_type, subtype),
+            _ => ("application", "octet-stream"),
+        };
+
+        let content_type = ContentType {
+            main_type: main_type.to_ascii_lowercase(),
+            subtype: subtype.to_ascii_lowercase(),
+            parameters: vec![(String::from("name"), filename.to_string())],
+        };
+
+        let content_disposition = ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![(String::from("filename"), filename.to_string())],
+        };
+
+        let headers = Headers(vec![
+            Header::ContentType(content_type),
+            Header::ContentDisposition(content_disposition),
+        ]);
+
+        self.part(Mail::new(headers, Body::Content(content)))
+    }
+
+    /// Adds a part to the mail, such as a multipart part built with `Mail::new`.
+    pub fn part(mut self, part: Mail) -> MailBuilder {
+        self.parts.push(part);
+        self
+    }
+
+    /// Builds the mail, which can then be serialized with `Mail::to_bytes`.
+    pub fn build(self) -> Mail {
+        let text = self.text.as_ref().map(|x| flowed_part(x));
+        let html = self.html.as_ref().map(|x| text_part("html", x, vec![]));
+
+        let calendar = self
+            .calendar
+            .as_ref()
+            .map(|(method, x)| text_part("calendar", x, vec![(String::from("method"), method.clone())]));
+
+        let mut alternatives = text.into_iter().chain(html).chain(calendar).collect::<Vec<_>>();
+
+        let alternative = match alternatives.len() {
+            0 | 1 => alternatives.pop(),
+            _ => Some(multipart("alternative", alternatives)),
+        };
+
+        let mut parts = alternative.into_iter().chain(self.parts).collect::<Vec<_>>();
+
+        let root = match parts.len() {
+            0 => flowed_part(""),
+            1 => parts.remove(0),
+            _ => multipart("mixed", parts),
+        };
+
+        let mut headers = self.headers;
+
+        if !self.to.is_empty() {
+            headers.push(Header::To(self.to));
+        }
+
+        if !self.cc.is_empty() {
+            headers.push(Header::Cc(self.cc));
+        }
+
+        if !self.bcc.is_empty() {
+            headers.push(Header::Bcc(self.bcc));
+        }
+
+        headers.push(Header::Unknown { name: String::from("MIME-Version"), value: String::from("1.0") });
+
+        let Mail { headers: Headers(root_headers), body, .. } = root;
+        headers.extend(root_headers);
+
+        Mail::new(Headers(headers), body)
+    }
+}
@@ -0,0 +1,500 @@
+//! This module contains the parsing of the calendar parts of the mails (RFC 5545), such as the
+//! meeting invitations, and the composition of the replies to these invitations (RFC 5546 and
+//! RFC 6047, also called iMIP).
+//!
+//! A calendar is made of components, such as events, that start with a `BEGIN` line and end with
+//! an `END` line, and whose other lines are properties, such as `SUMMARY:Meeting`. Only the events
+//! are kept, and the properties that are not understood are ignored.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+/// The maximum length of the lines of the calendars that are produced, in bytes, as specified by
+/// RFC 5545.
+const LINE_LENGTH: usize = 75;
+
+/// What the sender of a calendar expects from its recipients.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Method {
+    /// The calendar is informative, and needs no reply.
+    Publish,
+
+    /// The calendar is an invitation, to which the attendees reply.
+    Request,
+
+    /// The calendar is the reply of an attendee to an invitation.
+    Reply,
+
+    /// The events of the calendar are cancelled.
+    Cancel,
+
+    /// Some other method, in uppercase.
+    Other(String),
+}
+
+impl Method {
+    /// Parses the value of a `METHOD` property, which is case insensitive.
+    pub fn parse(value: &str) -> Method {
+        let value = value.trim().to_uppercase();
+
+        match value.as_str() {
+            "PUBLISH" => Method::Publish,
+            "REQUEST" => Method::Request,
+            "REPLY" => Method::Reply,
+            "CANCEL" => Method::Cancel,
+            _ => Method::Other(value),
+        }
+    }
+
+    /// Returns the name of the method, as it appears in the calendars, e.g. `REQUEST`.
+    pub fn name(&self) -> &str {
+        match self {
+            Method::Publish => "PUBLISH",
+            Method::Request => "REQUEST",
+            Method::Reply => "REPLY",
+            Method::Cancel => "CANCEL",
+            Method::Other(name) => name,
+        }
+    }
+}
+
+/// The answer of an attendee to an invitation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParticipationStatus {
+    /// The attendee has not answered yet.
+    NeedsAction,
+
+    /// The attendee will attend the event.
+    Accepted,
+
+    /// The attendee will not attend the event.
+    Declined,
+
+    /// The attendee may attend the event.
+    Tentative,
+
+    /// The attendee has delegated the event to someone else.
+    Delegated,
+
+    /// Some other status, in uppercase.
+    Other(String),
+}
+
+impl ParticipationStatus {
+    /// Parses the value of a `PARTSTAT` parameter, which is case insensitive.
+    pub fn parse(value: &str) -> ParticipationStatus {
+        let value = value.trim().to_uppercase();
+
+        match value.as_str() {
+            "NEEDS-ACTION" => ParticipationStatus::NeedsAction,
+            "ACCEPTED" => ParticipationStatus::Accepted,
+            "DECLINED" => ParticipationStatus::Declined,
+            "TENTATIVE" => ParticipationStatus::Tentative,
+            "DELEGATED" => ParticipationStatus::Delegated,
+            _ => ParticipationStatus::Other(value),
+        }
+    }
+
+    /// Returns the name of the status, as it appears in the calendars, e.g. `ACCEPTED`.
+    pub fn name(&self) -> &str {
+        match self {
+            ParticipationStatus::NeedsAction => "NEEDS-ACTION",
+            ParticipationStatus::Accepted => "ACCEPTED",
+            ParticipationStatus::Declined => "DECLINED",
+            ParticipationStatus::Tentative => "TENTATIVE",
+            ParticipationStatus::Delegated => "DELEGATED",
+            ParticipationStatus::Other(name) => name,
+        }
+    }
+}
+
+/// The start or the end of an event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Time {
+    /// A day, for the events that last whole days.
+    Date(NaiveDate),
+
+    /// A time in UTC.
+    Utc(DateTime<Utc>),
+
+    /// A time in the time zone with this identifier, e.g. `Europe/Paris`, or in the time zone of
+    /// the attendees if there is none.
+    Local {
+        /// The time, in its time zone.
+        time: NaiveDateTime,
+
+        /// The identifier of the time zone, if any.
+        timezone: Option<String>,
+    },
+}
+
+impl Time {
+    /// Parses the value of a date or time property, with its `TZID` parameter if any.
+    fn parse(value: &str, timezone: Option<&str>) -> Option<Time> {
+        let value = value.trim();
+
+        if value.len() == 8 {
+            return NaiveDate::parse_from_str(value, "%Y%m%d").ok().map(Time::Date);
+        }
+
+        match value.strip_suffix('Z') {
+            Some(value) => {
+                let time = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+                Some(Time::Utc(Utc.from_utc_datetime(&time)))
+            },
+
+            None => Some(Time::Local {
+                time: NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?,
+                timezone: timezone.map(String::from),
+            }),
+        }
+    }
+
+    /// Writes a property whose value is this time, with the parameters it needs.
+    fn property(&self, name: &str) -> String {
+        match self {
+            Time::Date(date) => format!("{};VALUE=DATE:{}", name, date.format("%Y%m%d")),
+            Time::Utc(time) => format!("{}:{}", name, time.format("%Y%m%dT%H%M%SZ")),
+            Time::Local { time, timezone: Some(timezone) } => {
+                format!("{};TZID={}:{}", name, parameter_value(timezone), time.format("%Y%m%dT%H%M%S"))
+            },
+            Time::Local { time, timezone: None } => format!("{}:{}", name, time.format("%Y%m%dT%H%M%S")),
+        }
+    }
+}
+
+/// An attendee or the organizer of an event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attendee {
+    /// The address of the attendee, without its `mailto:` prefix.
+    pub address: String,
+
+    /// The name of the attendee, if any.
+    pub name: Option<String>,
+
+    /// The answer of the attendee to the invitation, which is irrelevant for the organizer.
+    pub status: ParticipationStatus,
+}
+
+/// An event of a calendar, such as a meeting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// The unique identifier of the event, which is the same in the invitation and the replies.
+    pub uid: String,
+
+    /// The revision of the event, which is increased when the organizer changes the event.
+    pub sequence: u32,
+
+    /// The title of the event, if any.
+    pub summary: Option<String>,
+
+    /// The description of the event, if any.
+    pub description: Option<String>,
+
+    /// The location of the event, if any.
+    pub location: Option<String>,
+
+    /// The start of the event, if it is valid.
+    pub start: Option<Time>,
+
+    /// The end of the event, if it is valid.
+    pub end: Option<Time>,
+
+    /// The organizer of the event, if any.
+    pub organizer: Option<Attendee>,
+
+    /// The attendees of the event.
+    pub attendees: Vec<Attendee>,
+}
+
+impl Event {
+    /// Returns the attendee with this address, whose case is ignored.
+    pub fn attendee(&self, address: &str) -> Option<&Attendee> {
+        self.attendees.iter().find(|x| x.address.eq_ignore_ascii_case(address))
+    }
+}
+
+/// A calendar, with its method and its events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Calendar {
+    /// What the sender of the calendar expects from its recipients, if specified.
+    pub method: Option<Method>,
+
+    /// The events of the calendar.
+    pub events: Vec<Event>,
+}
+
+/// A property of a calendar, such as `DTSTART;TZID=Europe/Paris:20190201T120000`.
+struct Property {
+    /// The name of the property, in uppercase.
+    name: String,
+
+    /// The parameters of the property, with their names in uppercase and their values unquoted.
+    parameters: Vec<(String, String)>,
+
+    /// The value of the property, which is still escaped.
+    value: String,
+}
+
+impl Property {
+    /// Parses an unfolded line of a calendar.
+    fn parse(line: &str) -> Option<Property> {
+        let end = line.find([';', ':'])?;
+        let name = line[..end].trim().to_uppercase();
+        let mut rest = &line[end..];
+        let mut parameters = vec![];
+
+        // The colons may appear in the quoted values of the parameters.
+        while let Some(parameter) = rest.strip_prefix(';') {
+            let equal = parameter.find('=')?;
+            let parameter_name = parameter[..equal].trim().to_uppercase();
+            let value = &parameter[equal + 1..];
+
+            let (value, next) = match value.strip_prefix('"') {
+                Some(quoted) => {
+                    let end = quoted.find('"')?;
+                    (&quoted[..end], &quoted[end + 1..])
+                },
+                None => {
+                    let end = value.find([';', ':'])?;
+                    (&value[..end], &value[end..])
+                },
+            };
+
+            parameters.push((parameter_name, value.to_string()));
+            rest = next;
+        }
+
+        let value = rest.strip_prefix(':')?;
+
+        Some(Property { name, parameters, value: value.to_string() })
+    }
+
+    /// Returns the value of a parameter of the property, if any.
+    fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    /// Returns the value of the property as text, with its escaped chars decoded.
+    fn text(&self) -> String {
+        let mut text = String::with_capacity(self.value.len());
+        let mut chars = self.value.chars();
+
+        while let Some(c) = chars.next() {
+            match (c, chars.clone().next()) {
+                ('\\', Some('n')) | ('\\', Some('N')) => {
+                    chars.next();
+                    text.push('\n');
+                },
+                ('\\', Some(escaped)) => {
+                    chars.next();
+                    text.push(escaped);
+                },
+                (c, _) => text.push(c),
+            }
+        }
+
+        text
+    }
+
+    /// Returns the attendee described by the property.
+    fn attendee(&self) -> Attendee {
+        let value = self.value.trim();
+
+        let address = match value.get(..7) {
+            Some(scheme) if scheme.eq_ignore_ascii_case("mailto:") => &value[7..],
+            _ => value,
+        };
+
+        Attendee {
+            address: address.to_string(),
+            name: self.parameter("CN").map(String::from),
+            status: self.parameter("PARTSTAT").map(ParticipationStatus::parse).unwrap_or(ParticipationStatus::NeedsAction),
+        }
+    }
+}
+
+/// Splits a calendar into its unfolded lines.
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+
+    for line in text.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+
+        match lines.last_mut() {
+            Some(last) if line.starts_with(' ') || line.starts_with('\t') => last.push_str(&line[1..]),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    lines
+}
+
+/// Parses a calendar, such as the content of a `text/calendar` part.
+///
+/// The events that have no unique identifier are ignored, since they can't be answered, and so are
+/// the components that are nested in the events, such as the alarms.
+pub fn parse_calendar(text: &str) -> Calendar {
+    let mut method = None;
+    let mut events = vec![];
+    let mut components: Vec<String> = vec![];
+    let mut event: Option<(Option<String>, Event)> = None;
+
+    for property in unfold(text).iter().filter_map(|x| Property::parse(x)) {
+        match property.name.as_str() {
+            "BEGIN" => {
+                let component = property.value.trim().to_uppercase();
+
+                if component == "VEVENT" && components.last().map(String::as_str) == Some("VCALENDAR") {
+                    event = Some((None, Event {
+                        uid: String::new(),
+                        sequence: 0,
+                        summary: None,
+                        description: None,
+                        location: None,
+                        start: None,
+                        end: None,
+                        organizer: None,
+                        attendees: vec![],
+                    }));
+                }
+
+                components.push(component);
+                continue;
+            },
+
+            "END" => {
+                if components.pop().as_deref() == Some("VEVENT") {
+                    if let Some((Some(uid), event)) = event.take() {
+                        events.push(Event { uid, ..event });
+                    }
+                }
+
+                continue;
+            },
+
+            _ => (),
+        }
+
+        match (components.last().map(String::as_str), &mut event) {
+            (Some("VCALENDAR"), _) if property.name == "METHOD" => method = Some(Method::parse(&property.value)),
+
+            (Some("VEVENT"), Some((uid, event))) => match property.name.as_str() {
+                "UID" => *uid = Some(property.text()).filter(|x| !x.is_empty()),
+                "SEQUENCE" => event.sequence = property.value.trim().parse().unwrap_or(0),
+                "SUMMARY" => event.summary = Some(property.text()),
+                "DESCRIPTION" => event.description = Some(property.text()),
+                "LOCATION" => event.location = Some(property.text()),
+                "DTSTART" => event.start = Time::parse(&property.value, property.parameter("TZID")),
+                "DTEND" => event.end = Time::parse(&property.value, property.parameter("TZID")),
+                "ORGANIZER" => event.organizer = Some(property.attendee()),
+                "ATTENDEE" => event.attendees.push(property.attendee()),
+                _ => (),
+            },
+
+            _ => (),
+        }
+    }
+
+    Calendar { method, events }
+}
+
+/// Escapes some text so that it can be the value of a property.
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Quotes the value of a parameter if needed, removing the chars that can't appear in it.
+fn parameter_value(value: &str) -> String {
+    let value = value.chars().filter(|c| *c != '"' && !c.is_control()).collect::<String>();
+
+    if value.contains([':', ';', ',']) {
+        format!("\"{}\"", value)
+    } else {
+        value
+    }
+}
+
+/// Writes an attendee or an organizer property.
+fn attendee_property(name: &str, attendee: &Attendee, status: Option<&ParticipationStatus>) -> String {
+    let mut property = name.to_string();
+
+    if let Some(status) = status {
+        property.push_str(&format!(";PARTSTAT={}", status.name()));
+    }
+
+    if let Some(name) = &attendee.name {
+        property.push_str(&format!(";CN={}", parameter_value(name)));
+    }
+
+    format!("{}:mailto:{}", property, attendee.address)
+}
+
+/// Folds a line so that its lines are not longer than allowed, and ends it with a line break.
+fn fold(line: &str, output: &mut String) {
+    let mut length = 0;
+
+    for c in line.chars() {
+        // The lines that continue the previous one start with a space, which is counted.
+        if length + c.len_utf8() > LINE_LENGTH {
+            output.push_str("\r\n ");
+            length = 1;
+        }
+
+        output.push(c);
+        length += c.len_utf8();
+    }
+
+    output.push_str("\r\n");
+}
+
+/// Composes the reply of an attendee to an invitation, as a calendar with the `REPLY` method.
+///
+/// The reply tells the organizer of the event the status of the attendee, whose name is taken from
+/// the invitation if the attendee was invited. `now` is the time at which the reply is written.
+///
+/// Returns `None` if the address of the attendee or of the organizer contains control characters,
+/// such as line breaks, which would add properties to the reply.
+pub fn reply(event: &Event, address: &str, status: &ParticipationStatus, now: DateTime<Utc>) -> Option<String> {
+    let organizer = event.organizer.as_ref().map(|x| x.address.as_str());
+
+    if address.chars().chain(organizer.unwrap_or_default().chars()).any(char::is_control) {
+        return None;
+    }
+
+    let attendee = event.attendee(address).cloned().unwrap_or(Attendee {
+        address: address.to_string(),
+        name: None,
+        status: ParticipationStatus::NeedsAction,
+    });
+
+    let mut lines = vec![
+        String::from("BEGIN:VCALENDAR"),
+        String::from("VERSION:2.0"),
+        String::from("PRODID:-//nom-mail-parser//EN"),
+        String::from("METHOD:REPLY"),
+        String::from("BEGIN:VEVENT"),
+        format!("UID:{}", escape_text(&event.uid)),
+        format!("SEQUENCE:{}", event.sequence),
+        format!("DTSTAMP:{}", now.format("%Y%m%dT%H%M%SZ")),
+    ];
+
+    lines.extend(event.start.as_ref().map(|x| x.property("DTSTART")));
+    lines.extend(event.end.as_ref().map(|x| x.property("DTEND")));
+    lines.extend(event.summary.as_ref().map(|x| format!("SUMMARY:{}", escape_text(x))));
+    lines.extend(event.organizer.as_ref().map(|x| attendee_property("ORGANIZER", x, None)));
+    lines.push(attendee_property("ATTENDEE", &attendee, Some(status)));
+    lines.push(String::from("END:VEVENT"));
+    lines.push(String::from("END:VCALENDAR"));
+
+    let mut output = String::new();
+
+    for line in lines {
+        fold(&line, &mut output);
+    }
+
+    Some(output)
+}
@@ -0,0 +1,107 @@
+//! This module contains the functions to convert text from the different charsets to UTF-8.
+
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+
+/// Some charset labels that are used in mails but that are not known to encoding_rs, with the
+/// labels of the corresponding encodings.
+const ALIASES: &[(&str, &str)] = &[
+    ("shift-jis", "shift_jis"),
+    ("cp932", "shift_jis"),
+    ("x-ms-cp932", "shift_jis"),
+    ("cp936", "gbk"),
+    ("ms936", "gbk"),
+    ("euc-cn", "gbk"),
+    ("x-euc-cn", "gbk"),
+    ("cp949", "euc-kr"),
+    ("ks_c_5601", "euc-kr"),
+    ("ks_c_5601-1989", "euc-kr"),
+    ("uhc", "euc-kr"),
+    ("big5hkscs", "big5-hkscs"),
+    ("cp950", "big5"),
+    ("euc_jp", "euc-jp"),
+    ("x-euc", "euc-jp"),
+    ("tis620", "tis-620"),
+    ("utf-8y", "utf-8"),
+];
+
+/// Finds the encoding corresponding to a charset label, such as `iso-8859-1` or `UTF-8`.
+///
+/// On top of the labels of the encoding standard, this accepts a few variants that mailers
+/// commonly use, such as `latin-1`, `cp-1252`, `windows1252` or `iso_8859_1`.
+pub fn encoding(label: &str) -> Option<&'static Encoding> {
+    let label = label.trim().trim_matches('"').to_ascii_lowercase();
+
+    if let Some(encoding) = Encoding::for_label(label.as_bytes()) {
+        return Some(encoding);
+    }
+
+    if let Some((_, alias)) = ALIASES.iter().find(|(x, _)| *x == label) {
+        return Encoding::for_label(alias.as_bytes());
+    }
+
+    let label = label.replace('_', "-");
+
+    let normalized = if let Some(number) = label.strip_prefix("latin-") {
+        format!("latin{}", number)
+    } else if let Some(number) = label.strip_prefix("cp-").or_else(|| label.strip_prefix("win-")) {
+        format!("windows-{}", number)
+    } else if let Some(number) = label.strip_prefix("windows").filter(|x| !x.starts_with('-')) {
+        format!("windows-{}", number)
+    } else if let Some(rest) = label.strip_prefix("iso8859").filter(|x| !x.starts_with('-')) {
+        format!("iso-8859-{}", rest)
+    } else {
+        label
+    };
+
+    Encoding::for_label(normalized.as_bytes())
+}
+
+/// Finds the encoding that should be used to decode some text.
+///
+/// The declared charset is used if it is known. Text that declares US-ASCII but that contains valid
+/// UTF-8 is considered to be UTF-8, since a lot of mailers declare US-ASCII by default. Text whose
+/// charset is missing or unknown is considered to be UTF-8 if it is valid UTF-8, and windows-1252
+/// otherwise, since it is the most common charset for 8-bit text that doesn't declare its charset.
+pub fn detect(label: Option<&str>, bytes: &[u8]) -> &'static Encoding {
+    let is_ascii = bytes.is_ascii();
+    let is_utf8 = || std::str::from_utf8(bytes).is_ok();
+
+    match label.and_then(encoding) {
+        Some(encoding) if encoding == WINDOWS_1252 && !is_ascii && is_utf8() && is_ascii_label(label) => UTF_8,
+        Some(encoding) => encoding,
+        None if is_ascii || is_utf8() => UTF_8,
+        None => WINDOWS_1252,
+    }
+}
+
+/// Checks if a charset label designates US-ASCII, that encoding_rs handles as windows-1252.
+fn is_ascii_label(label: Option<&str>) -> bool {
+    match label {
+        Some(label) => {
+            let label = label.trim().trim_matches('"').to_ascii_lowercase();
+            label == "us-ascii" || label == "ascii" || label == "ansi_x3.4-1968" || label == "us"
+        },
+        None => false,
+    }
+}
+
+/// Converts some bytes from an encoding to a string.
+///
+/// Invalid sequences are replaced by the replacement character, and a byte order mark that matches
+/// the encoding is removed.
+pub fn decode_with(encoding: &'static Encoding, bytes: &[u8]) -> String {
+    encoding.decode_with_bom_removal(bytes).0.into_owned()
+}
+
+/// Converts some bytes from a charset to a string.
+///
+/// Invalid sequences are replaced by the replacement character, and unknown charsets are handled
+/// like text whose charset was not specified.
+pub fn decode(label: &str, bytes: &[u8]) -> String {
+    decode_with(detect(Some(label), bytes), bytes)
+}
+
+/// Converts some bytes whose charset is not known to a string.
+pub fn decode_unknown(bytes: &[u8]) -> String {
+    decode_with(detect(None, bytes), bytes)
+}
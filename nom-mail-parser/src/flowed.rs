@@ -0,0 +1,129 @@
+//! This module contains the decoding and the encoding of the flowed format of plain text mails
+//! (RFC 3676), in which the lines that end with a space continue on the next line, so that the text
+//! can be reflowed to any width.
+
+/// The maximum length of the lines of the flowed text that is produced, as recommended by RFC 3676.
+const LINE_LENGTH: usize = 72;
+
+/// A paragraph of plain text, that may be quoted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paragraph {
+    /// The number of quote markers before the paragraph, which is zero for text that is not quoted.
+    pub quote_depth: usize,
+
+    /// The text of the paragraph, without its quote markers and its line breaks.
+    pub text: String,
+}
+
+/// Splits a line into its quote depth and its text, removing the space that may follow the quote
+/// markers.
+fn split_quotes(line: &str) -> (usize, &str) {
+    let quote_depth = line.chars().take_while(|c| *c == '>').count();
+    let text = &line[quote_depth..];
+    (quote_depth, text.strip_prefix(' ').unwrap_or(text))
+}
+
+/// Splits some text into paragraphs, joining the flowed lines if `flowed` is true.
+fn paragraphs(text: &str, flowed: bool, delsp: bool) -> Vec<Paragraph> {
+    let text = text.strip_suffix('\n').unwrap_or(text);
+    let text = text.strip_suffix('\r').unwrap_or(text);
+
+    let mut paragraphs: Vec<Paragraph> = vec![];
+    let mut flowing = false;
+
+    for line in text.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        // The space that starts the lines that are not quoted is only removed from flowed text,
+        // whose lines are stuffed with it, and the other lines keep their indentation.
+        let (quote_depth, content) = match split_quotes(line) {
+            (0, _) if !flowed => (0, line),
+            split => split,
+        };
+
+        // The signature separator is never flowed.
+        let is_flowed = flowed && content.ends_with(' ') && content != "-- ";
+        let content = if is_flowed && delsp { &content[..content.len() - 1] } else { content };
+
+        match paragraphs.last_mut() {
+            // A change of quote depth ends the paragraph, even if the line is flowed.
+            Some(last) if flowing && last.quote_depth == quote_depth => last.text.push_str(content),
+            _ => paragraphs.push(Paragraph { quote_depth, text: content.to_string() }),
+        }
+
+        flowing = is_flowed;
+    }
+
+    paragraphs
+}
+
+/// Decodes some flowed text into paragraphs.
+///
+/// If `delsp` is true, as specified by the `delsp=yes` parameter of the content type, the spaces
+/// that end the flowed lines are removed.
+pub fn decode(text: &str, delsp: bool) -> Vec<Paragraph> {
+    paragraphs(text, true, delsp)
+}
+
+/// Splits some text that is not flowed into paragraphs, each line being a paragraph.
+pub fn decode_fixed(text: &str) -> Vec<Paragraph> {
+    paragraphs(text, false, false)
+}
+
+/// Encodes some text in the flowed format, without `delsp`.
+///
+/// Each line of the text is a paragraph, and its quote depth is given by the quote markers that
+/// start it. The paragraphs are wrapped at the spaces, and the lines that could be mistaken for
+/// quotes are stuffed with a space. Every line of the flowed text ends with a line break.
+pub fn encode(text: &str) -> String {
+    let text = text.strip_suffix('\n').unwrap_or(text);
+    let text = text.strip_suffix('\r').unwrap_or(text);
+    let mut encoded = String::with_capacity(text.len());
+
+    for line in text.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+
+        // The lines that are not quoted keep their leading spaces.
+        let (quote_depth, content) = match split_quotes(line) {
+            (0, _) => (0, line),
+            split => split,
+        };
+
+        // The spaces at the end of the paragraph would make it flow into the next one.
+        let content = if content == "-- " { content } else { content.trim_end_matches(' ') };
+
+        let quote = ">".repeat(quote_depth);
+        let width = LINE_LENGTH.saturating_sub(quote_depth + 1);
+        let mut wrapped = vec![];
+        let mut current = String::new();
+
+        for (index, word) in content.split(' ').enumerate() {
+            if index > 0 {
+                if !current.is_empty() && !word.is_empty() && current.len() + 1 + word.len() > width {
+                    // The line ends with a space, so that it flows into the next one.
+                    current.push(' ');
+                    wrapped.push(std::mem::take(&mut current));
+                } else {
+                    current.push(' ');
+                }
+            }
+
+            current.push_str(word);
+        }
+
+        wrapped.push(current);
+
+        for line in wrapped {
+            let needs_stuffing = quote_depth > 0 || line.starts_with(' ') || line.starts_with('>') || line.starts_with("From ");
+
+            if needs_stuffing {
+                encoded.push_str(&quote);
+                encoded.push(' ');
+            }
+
+            encoded.push_str(&line);
+            encoded.push_str("\r\n");
+        }
+    }
+
+    encoded
+}
@@ -0,0 +1,1098 @@
+//! This module contains the sanitization of the HTML parts of the mails, so that they can be
+//! displayed in a browser without running scripts or leaking out of their container.
+//!
+//! The HTML is tokenized and serialized again, keeping only an allow list of tags, attributes and
+//! CSS properties, so that nothing that was not understood reaches the browser. The remote content
+//! can be blocked as well, so that the senders can't track when their mails are read.
+//!
+//! The HTML can also be converted to plain text, for the mails that have no plain text version.
+
+use std::borrow::Cow;
+
+/// The tags that are kept.
+const ALLOWED_TAGS: &[&str] = &[
+    "a", "abbr", "acronym", "address", "b", "bdi", "bdo", "big", "blockquote", "br", "caption",
+    "center", "cite", "code", "col", "colgroup", "dd", "del", "dfn", "div", "dl", "dt", "em",
+    "font", "h1", "h2", "h3", "h4", "h5", "h6", "hr", "i", "img", "ins", "kbd", "li", "mark", "ol",
+    "p", "pre", "q", "s", "samp", "small", "span", "strike", "strong", "sub", "sup", "table",
+    "tbody", "td", "tfoot", "th", "thead", "tr", "tt", "u", "ul", "var", "wbr",
+];
+
+/// The tags that have no content and no end tag.
+const VOID_TAGS: &[&str] = &["br", "col", "hr", "img", "wbr"];
+
+/// The tags that are removed with their content, instead of only their tags.
+const DROPPED_TAGS: &[&str] = &[
+    "applet", "button", "frame", "frameset", "iframe", "math", "noembed", "noframes", "noscript",
+    "object", "plaintext", "script", "select", "style", "svg", "template", "textarea", "title",
+    "xmp",
+];
+
+/// The tags whose content is text that is not parsed, until their end tag.
+const RAW_TEXT_TAGS: &[&str] = &[
+    "iframe", "noembed", "noframes", "noscript", "plaintext", "script", "style", "textarea",
+    "title", "xmp",
+];
+
+/// The attributes that are kept on every allowed tag, since they only change the presentation.
+const ALLOWED_ATTRIBUTES: &[&str] = &[
+    "align", "alt", "bgcolor", "border", "cellpadding", "cellspacing", "color", "colspan", "dir",
+    "face", "height", "hspace", "lang", "rowspan", "size", "span", "start", "style", "summary",
+    "title", "type", "valign", "vspace", "width",
+];
+
+/// The CSS properties that are kept in the style attributes.
+const ALLOWED_PROPERTIES: &[&str] = &[
+    "background", "background-color", "background-image", "background-position",
+    "background-repeat", "background-size", "border", "border-bottom", "border-bottom-color",
+    "border-bottom-style", "border-bottom-width", "border-collapse", "border-color", "border-left",
+    "border-left-color", "border-left-style", "border-left-width", "border-radius", "border-right",
+    "border-right-color", "border-right-style", "border-right-width", "border-spacing",
+    "border-style", "border-top", "border-top-color", "border-top-style", "border-top-width",
+    "border-width", "clear", "color", "direction", "display", "float", "font", "font-family",
+    "font-size", "font-style", "font-variant", "font-weight", "height", "letter-spacing",
+    "line-height", "list-style", "list-style-position", "list-style-type", "margin",
+    "margin-bottom", "margin-left", "margin-right", "margin-top", "max-height", "max-width",
+    "min-height", "min-width", "overflow-wrap", "padding", "padding-bottom", "padding-left",
+    "padding-right", "padding-top", "table-layout", "text-align", "text-decoration",
+    "text-indent", "text-transform", "vertical-align", "white-space", "width", "word-break",
+    "word-spacing", "word-wrap",
+];
+
+/// The CSS functions that are allowed in the values of the properties.
+const ALLOWED_FUNCTIONS: &[&str] = &["hsl", "hsla", "rgb", "rgba", "url"];
+
+/// The domains of known trackers, whose remote images are tracking pixels whatever their size.
+const TRACKER_DOMAINS: &[&str] = &[
+    "bananatag.com", "exct.net", "getnotify.com", "hubspotlinks.com", "list-manage.com",
+    "mailfoogae.appspot.com", "mailtrack.io", "mandrillapp.com", "mixmax.com", "mktoresp.com",
+    "pardot.com", "sendgrid.net", "sendibt3.com", "yesware.com",
+];
+
+/// The named character references that are decoded, which are the most common ones along with
+/// the ones that can hide the scheme of a URL.
+const ENTITIES: &[(&str, &str)] = &[
+    ("amp", "&"), ("apos", "'"), ("bull", "\u{2022}"), ("colon", ":"), ("comma", ","),
+    ("copy", "\u{a9}"), ("euro", "\u{20ac}"), ("gt", ">"), ("hellip", "\u{2026}"),
+    ("laquo", "\u{ab}"), ("ldquo", "\u{201c}"), ("lpar", "("), ("lsquo", "\u{2018}"), ("lt", "<"),
+    ("mdash", "\u{2014}"), ("middot", "\u{b7}"), ("nbsp", "\u{a0}"), ("ndash", "\u{2013}"),
+    ("NewLine", "\n"), ("period", "."), ("quot", "\""), ("raquo", "\u{bb}"), ("rdquo", "\u{201d}"),
+    ("reg", "\u{ae}"), ("rpar", ")"), ("rsquo", "\u{2019}"), ("semi", ";"), ("sol", "/"),
+    ("Tab", "\t"), ("times", "\u{d7}"), ("trade", "\u{2122}"),
+];
+
+/// A token of HTML.
+///
+/// The comments, the doctypes and the processing instructions are not tokens, since they are
+/// always removed.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Token<'a> {
+    /// Some text, whose character references are not decoded.
+    Text(&'a str),
+
+    /// A start tag, with its lowercase name and its attributes, whose values are decoded.
+    StartTag {
+        /// The name of the tag.
+        name: String,
+
+        /// The attributes of the tag, with their lowercase names.
+        attributes: Vec<(String, String)>,
+    },
+
+    /// An end tag, with its lowercase name.
+    EndTag(String),
+}
+
+/// A tokenizer of HTML, following the tokenization of HTML5 closely enough that browsers see the
+/// same tags.
+pub(crate) struct Tokenizer<'a> {
+    /// The HTML that is tokenized.
+    input: &'a str,
+
+    /// The position of the next token in the input.
+    position: usize,
+
+    /// The name of the tag whose content is raw text, if the next token is such text.
+    raw_text: Option<String>,
+}
+
+/// Checks if a byte is a white space in HTML.
+fn is_space(c: u8) -> bool {
+    b" \t\n\r\x0c".contains(&c)
+}
+
+/// Finds the end tag of an element whose content is raw text, and returns its position.
+fn find_end_tag(input: &str, name: &str) -> Option<usize> {
+    let bytes = input.as_bytes();
+
+    (0..bytes.len()).find(|&i| {
+        let end = i + 2 + name.len();
+
+        bytes[i..].starts_with(b"</")
+            && bytes.len() >= end
+            && bytes[i + 2 .. end].eq_ignore_ascii_case(name.as_bytes())
+            && bytes.get(end).map(|c| is_space(*c) || *c == b'/' || *c == b'>').unwrap_or(true)
+    })
+}
+
+impl<'a> Tokenizer<'a> {
+    /// Creates a tokenizer for some HTML.
+    pub(crate) fn new(input: &'a str) -> Tokenizer<'a> {
+        Tokenizer { input, position: 0, raw_text: None }
+    }
+
+    /// Skips the input until after a pattern, or until its end.
+    fn skip_after(&mut self, pattern: &str) {
+        self.position = match self.input[self.position..].find(pattern) {
+            Some(index) => self.position + index + pattern.len(),
+            None => self.input.len(),
+        };
+    }
+
+    /// Reads a tag, starting after its `<` or its `</`, and returns its name and its attributes.
+    ///
+    /// Returns none if the input ends before the end of the tag, in which case the tag is ignored.
+    fn tag(&mut self) -> Option<(String, Vec<(String, String)>)> {
+        let bytes = self.input.as_bytes();
+        let mut i = self.position;
+
+        let start = i;
+        while i < bytes.len() && !is_space(bytes[i]) && bytes[i] != b'/' && bytes[i] != b'>' {
+            i += 1;
+        }
+
+        let name = self.input[start..i].to_ascii_lowercase();
+        let mut attributes: Vec<(String, String)> = vec![];
+
+        loop {
+            while i < bytes.len() && (is_space(bytes[i]) || bytes[i] == b'/') {
+                i += 1;
+            }
+
+            match bytes.get(i) {
+                None => {
+                    self.position = bytes.len();
+                    return None;
+                },
+                Some(b'>') => break,
+                _ => (),
+            }
+
+            // The first char of a name may be `=`.
+            let start = i;
+            i += 1;
+            while i < bytes.len() && !is_space(bytes[i]) && !b"/>=".contains(&bytes[i]) {
+                i += 1;
+            }
+
+            let attribute = self.input[start..i].to_ascii_lowercase();
+
+            while i < bytes.len() && is_space(bytes[i]) {
+                i += 1;
+            }
+
+            let mut value = "";
+
+            if bytes.get(i) == Some(&b'=') {
+                i += 1;
+                while i < bytes.len() && is_space(bytes[i]) {
+                    i += 1;
+                }
+
+                match bytes.get(i) {
+                    Some(quote) if *quote == b'"' || *quote == b'\'' => {
+                        let end = match bytes[i + 1 ..].iter().position(|c| c == quote) {
+                            Some(end) => i + 1 + end,
+                            None => {
+                                self.position = bytes.len();
+                                return None;
+                            },
+                        };
+
+                        value = &self.input[i + 1 .. end];
+                        i = end + 1;
+                    },
+                    _ => {
+                        let start = i;
+                        while i < bytes.len() && !is_space(bytes[i]) && bytes[i] != b'>' {
+                            i += 1;
+                        }
+
+                        value = &self.input[start..i];
+                    },
+                }
+            }
+
+            // Only the first of the attributes with the same name is used by browsers.
+            if attributes.iter().all(|(x, _)| *x != attribute) {
+                attributes.push((attribute, decode_entities(value).into_owned()));
+            }
+        }
+
+        self.position = i + 1;
+        Some((name, attributes))
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        loop {
+            let rest = &self.input[self.position..];
+
+            if rest.is_empty() {
+                return None;
+            }
+
+            if let Some(name) = self.raw_text.take() {
+                let end = find_end_tag(rest, &name).unwrap_or(rest.len());
+                self.position += end;
+
+                if end > 0 {
+                    return Some(Token::Text(&rest[..end]));
+                }
+
+                continue;
+            }
+
+            let bytes = rest.as_bytes();
+
+            match rest.find('<') {
+                Some(0) => (),
+                Some(end) => {
+                    self.position += end;
+                    return Some(Token::Text(&rest[..end]));
+                },
+                None => {
+                    self.position = self.input.len();
+                    return Some(Token::Text(rest));
+                },
+            }
+
+            match bytes.get(1) {
+                Some(b'!') if rest.starts_with("<!--") => {
+                    // The end of the comment may overlap its beginning, as in `<!-->`.
+                    self.position += 2;
+                    self.skip_after("-->");
+                },
+
+                Some(b'!') | Some(b'?') => self.skip_after(">"),
+
+                Some(b'/') => match bytes.get(2) {
+                    Some(c) if c.is_ascii_alphabetic() => {
+                        self.position += 2;
+
+                        if let Some((name, _)) = self.tag() {
+                            return Some(Token::EndTag(name));
+                        }
+                    },
+                    _ => self.skip_after(">"),
+                },
+
+                Some(c) if c.is_ascii_alphabetic() => {
+                    self.position += 1;
+
+                    if let Some((name, attributes)) = self.tag() {
+                        if RAW_TEXT_TAGS.contains(&name.as_str()) {
+                            self.raw_text = Some(name.clone());
+                        }
+
+                        return Some(Token::StartTag { name, attributes });
+                    }
+                },
+
+                _ => {
+                    self.position += 1;
+                    return Some(Token::Text("<"));
+                },
+            }
+        }
+    }
+}
+
+/// Decodes the character references of some text, such as `&amp;` or `&#106;`.
+///
+/// The numeric references don't need to end with a semicolon, as in browsers, and the unknown
+/// references are kept as they are.
+pub(crate) fn decode_entities(text: &str) -> Cow<'_, str> {
+    if !text.contains('&') {
+        return Cow::Borrowed(text);
+    }
+
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(index) = rest.find('&') {
+        decoded.push_str(&rest[..index]);
+        rest = &rest[index + 1 ..];
+
+        if let Some(reference) = rest.strip_prefix('#') {
+            let (digits, radix) = match reference.strip_prefix(|c| c == 'x' || c == 'X') {
+                Some(digits) => (digits, 16),
+                None => (reference, 10),
+            };
+
+            let length = digits.find(|c: char| !c.is_digit(radix)).unwrap_or(digits.len());
+
+            if length > 0 {
+                let c = u32::from_str_radix(&digits[..length], radix)
+                    .ok()
+                    .filter(|x| *x != 0)
+                    .and_then(std::char::from_u32)
+                    .unwrap_or('\u{fffd}');
+
+                decoded.push(c);
+                rest = &digits[length..];
+                rest = rest.strip_prefix(';').unwrap_or(rest);
+                continue;
+            }
+        }
+
+        let entity = ENTITIES.iter().find(|(name, _)| {
+            rest.starts_with(name) && rest[name.len()..].starts_with(';')
+        });
+
+        match entity {
+            Some((name, value)) => {
+                decoded.push_str(value);
+                rest = &rest[name.len() + 1 ..];
+            },
+            None => decoded.push('&'),
+        }
+    }
+
+    decoded.push_str(rest);
+    Cow::Owned(decoded)
+}
+
+/// Escapes some text so that it can appear in HTML, keeping its character references.
+fn escape_text(text: &str, output: &mut String) {
+    for (index, c) in text.char_indices() {
+        match c {
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '&' => {
+                let rest = &text[index + 1 ..];
+                let length = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '#').unwrap_or(rest.len());
+
+                if length > 0 && rest[length..].starts_with(';') {
+                    output.push('&');
+                } else {
+                    output.push_str("&amp;");
+                }
+            },
+            c => output.push(c),
+        }
+    }
+}
+
+/// Escapes the value of an attribute, so that it can appear between double quotes.
+fn escape_attribute(value: &str, output: &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '"' => output.push_str("&quot;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            c => output.push(c),
+        }
+    }
+}
+
+/// Decodes the percent encoded bytes of a URL, such as the content ids of `cid:` URLs.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let byte = match (bytes[i], text.get(i + 1 .. i + 3)) {
+            (b'%', Some(hex)) if hex.bytes().all(|c| c.is_ascii_hexdigit()) => u8::from_str_radix(hex, 16).ok(),
+            _ => None,
+        };
+
+        match byte {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            },
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Removes the white spaces around a URL, and the tabs and line breaks inside it, which are ignored
+/// by browsers.
+fn clean_url(url: &str) -> String {
+    url.trim_matches(|c| c <= ' ').chars().filter(|c| !"\t\n\r".contains(*c)).collect()
+}
+
+/// Checks if a URL refers to remote content, that would be loaded from another server.
+fn is_remote(url: &str) -> bool {
+    let url = clean_url(url).to_ascii_lowercase();
+    url.starts_with("http:") || url.starts_with("https:")
+}
+
+/// Returns the lowercase host of a URL, if it has one.
+fn host(url: &str) -> Option<String> {
+    let rest = &url[url.find("://")? + 3 ..];
+    let authority = &rest[..rest.find(|c| "/?#".contains(c)).unwrap_or(rest.len())];
+    let host = authority.rsplit('@').next()?.split(':').next()?;
+    Some(host.to_ascii_lowercase())
+}
+
+/// Parses a length in pixels, such as `1` or `1px`.
+fn pixels(value: &str) -> Option<f64> {
+    let value = value.trim();
+    value.strip_suffix("px").unwrap_or(value).trim().parse().ok()
+}
+
+/// Checks if a remote image is a tracking pixel, which is an image of a known tracker or an image
+/// that is not larger than one pixel.
+fn is_tracking_pixel(url: &str, attributes: &[(String, String)]) -> bool {
+    let is_tracker = host(&clean_url(url))
+        .map(|host| TRACKER_DOMAINS.iter().any(|x| host == *x || host.ends_with(&format!(".{}", x))))
+        .unwrap_or(false);
+
+    // The size may be given by the attributes or by the style, which takes precedence.
+    let size = |name: &str| {
+        let style = attributes
+            .iter()
+            .filter(|(x, _)| x == "style")
+            .flat_map(|(_, style)| style.split(';'))
+            .filter_map(|declaration| {
+                let mut split = declaration.splitn(2, ':');
+
+                match (split.next(), split.next()) {
+                    (Some(property), Some(value)) if property.trim().eq_ignore_ascii_case(name) => pixels(value),
+                    _ => None,
+                }
+            })
+            .next_back();
+
+        style.or_else(|| attributes.iter().find(|(x, _)| x == name).and_then(|(_, x)| pixels(x)))
+    };
+
+    let is_tiny = |x: Option<f64>| x.is_some_and(|x| x <= 1.0);
+    is_tracker || (is_tiny(size("width")) && is_tiny(size("height")))
+}
+
+/// The sanitizer of some HTML, which keeps the elements that are open so that the HTML can't close
+/// the elements that contain it.
+struct Sanitizer<'a> {
+    /// The function that returns the URL of an inline attachment, given its content id.
+    inline_url: &'a dyn Fn(&str) -> String,
+
+    /// The sanitized HTML.
+    output: String,
+
+    /// The allowed elements that are open, from the outermost.
+    open: Vec<String>,
+
+    /// The element that is removed with its content, if any, with the number of its nested
+    /// elements with the same name that are open.
+    dropped: Option<(String, usize)>,
+
+    /// Whether the remote content is loaded.
+    allow_remote: bool,
+
+    /// The number of remote images and CSS references that were blocked.
+    blocked: usize,
+
+    /// The number of tracking pixels that were removed.
+    trackers: usize,
+}
+
+impl<'a> Sanitizer<'a> {
+    /// Sanitizes a URL, and returns none if its scheme is not allowed.
+    ///
+    /// The allowed schemes are `http`, `https` and `mailto`, and the `cid` URLs, which refer to
+    /// the inline attachments of the mail, are replaced by the URLs of these attachments. Relative
+    /// URLs have no meaning in a mail, so they are not allowed either.
+    fn url(&self, url: &str) -> Option<String> {
+        let url = clean_url(url);
+        let scheme = url.find(':').map(|x| url[..x].to_ascii_lowercase());
+
+        match scheme.as_deref() {
+            Some("http") | Some("https") | Some("mailto") => Some(url),
+            Some("cid") => Some((self.inline_url)(&percent_decode(&url[4..]))),
+            _ => None,
+        }
+    }
+
+    /// Sanitizes the value of a CSS property, and returns none if it is not allowed.
+    ///
+    /// Only a few functions are allowed, and the URLs are sanitized like the ones of the
+    /// attributes. The remote URLs are blocked unless the remote content is allowed.
+    fn css_value(&mut self, value: &str) -> Option<String> {
+        let lowercase = value.to_ascii_lowercase();
+
+        // The escapes and the comments could hide anything from the checks.
+        if value.contains('\\') || value.contains("/*") || lowercase.contains("expression") {
+            return None;
+        }
+
+        let mut sanitized = String::new();
+        let mut rest = value;
+
+        while let Some(index) = rest.find('(') {
+            let function_start = rest[..index]
+                .rfind(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+                .map(|x| x + 1)
+                .unwrap_or(0);
+
+            let function = rest[function_start..index].to_ascii_lowercase();
+
+            if !ALLOWED_FUNCTIONS.contains(&function.as_str()) {
+                return None;
+            }
+
+            sanitized.push_str(&rest[..=index]);
+            rest = &rest[index + 1 ..];
+
+            if function == "url" {
+                let end = rest.find(')')?;
+                let url = rest[..end].trim().trim_matches(|c| c == '"' || c == '\'');
+
+                // The URLs that contain quotes or parentheses would end before the parenthesis.
+                if url.contains(|c| "\"'()".contains(c)) {
+                    return None;
+                }
+
+                if is_remote(url) && !self.allow_remote {
+                    self.blocked += 1;
+                    return None;
+                }
+
+                sanitized.push('"');
+                sanitized.push_str(&self.url(url)?);
+                sanitized.push('"');
+                rest = &rest[end..];
+            }
+        }
+
+        sanitized.push_str(rest);
+        Some(sanitized)
+    }
+
+    /// Sanitizes the declarations of a style attribute, keeping the allowed properties.
+    fn style(&mut self, style: &str) -> String {
+        let mut sanitized = vec![];
+
+        for declaration in style.split(';') {
+            let mut split = declaration.splitn(2, ':');
+
+            let (property, value) = match (split.next(), split.next()) {
+                (Some(property), Some(value)) => (property.trim().to_ascii_lowercase(), value.trim()),
+                _ => continue,
+            };
+
+            if !ALLOWED_PROPERTIES.contains(&property.as_str()) {
+                continue;
+            }
+
+            if let Some(value) = self.css_value(value) {
+                sanitized.push(format!("{}: {}", property, value));
+            }
+        }
+
+        sanitized.join("; ")
+    }
+
+    /// Writes a start tag with its allowed attributes.
+    ///
+    /// The tracking pixels are removed, and the remote images are replaced by placeholders unless
+    /// the remote content is allowed.
+    fn start_tag(&mut self, name: &str, attributes: &[(String, String)]) {
+        let remote_image = attributes
+            .iter()
+            .find(|(x, _)| name == "img" && x == "src")
+            .map(|(_, x)| x)
+            .filter(|x| is_remote(x));
+
+        if let Some(url) = remote_image {
+            if is_tracking_pixel(url, attributes) {
+                self.blocked += 1;
+                self.trackers += 1;
+                return;
+            }
+        }
+
+        let is_blocked = remote_image.is_some() && !self.allow_remote;
+        let mut sanitized = vec![];
+
+        for (attribute, value) in attributes {
+            let value = match (name, attribute.as_str()) {
+                ("img", "src") | ("img", "title") if is_blocked => None,
+                ("a", "href") | ("img", "src") => self.url(value),
+                (_, "style") => Some(self.style(value)).filter(|x| !x.is_empty()),
+                (_, attribute) if ALLOWED_ATTRIBUTES.contains(&attribute) => Some(value.clone()),
+                _ => None,
+            };
+
+            if let Some(value) = value {
+                sanitized.push((attribute.as_str(), value));
+            }
+        }
+
+        if is_blocked {
+            self.blocked += 1;
+            sanitized.push(("title", String::from("Remote image blocked")));
+        }
+
+        // The links are opened in new tabs, that can't access the webmail.
+        if name == "a" && sanitized.iter().any(|(x, _)| *x == "href") {
+            sanitized.push(("target", String::from("_blank")));
+            sanitized.push(("rel", String::from("noopener noreferrer")));
+        }
+
+        self.output.push('<');
+        self.output.push_str(name);
+
+        for (attribute, value) in sanitized {
+            self.output.push(' ');
+            self.output.push_str(attribute);
+            self.output.push_str("=\"");
+            escape_attribute(&value, &mut self.output);
+            self.output.push('"');
+        }
+
+        self.output.push('>');
+
+        if !VOID_TAGS.contains(&name) {
+            self.open.push(name.to_string());
+        }
+    }
+
+    /// Closes an open element, along with the elements that it contains.
+    ///
+    /// The end tags of the elements that are not open are ignored.
+    fn end_tag(&mut self, name: &str) {
+        if let Some(index) = self.open.iter().rposition(|x| x == name) {
+            for name in self.open.drain(index..).rev() {
+                self.output.push_str(&format!("</{}>", name));
+            }
+        }
+    }
+
+    /// Sanitizes a token.
+    fn token(&mut self, token: Token) {
+        if let Some((dropped, depth)) = &mut self.dropped {
+            match token {
+                Token::StartTag { ref name, .. } if name == dropped => *depth += 1,
+                Token::EndTag(ref name) if name == dropped && *depth > 0 => *depth -= 1,
+                Token::EndTag(ref name) if name == dropped => self.dropped = None,
+                _ => (),
+            }
+
+            return;
+        }
+
+        match token {
+            Token::Text(text) => escape_text(text, &mut self.output),
+
+            Token::StartTag { name, attributes } => {
+                if DROPPED_TAGS.contains(&name.as_str()) {
+                    self.dropped = Some((name, 0));
+                } else if ALLOWED_TAGS.contains(&name.as_str()) {
+                    self.start_tag(&name, &attributes);
+                }
+            },
+
+            Token::EndTag(name) => self.end_tag(&name),
+        }
+    }
+}
+
+/// Some sanitized HTML, with the remote content that was blocked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sanitized {
+    /// The sanitized HTML.
+    pub html: String,
+
+    /// The number of remote images and CSS references that were blocked, including the tracking
+    /// pixels.
+    pub blocked: usize,
+
+    /// The number of tracking pixels that were removed, which are removed even if the remote
+    /// content is allowed.
+    pub trackers: usize,
+}
+
+/// Sanitizes some HTML, so that it can be displayed in a webmail.
+///
+/// Only an allow list of tags, attributes and CSS properties is kept: the scripts, the event
+/// handlers, the forms and the frames are removed, and so are the URLs whose scheme is not `http`,
+/// `https` or `mailto`. The `cid` URLs, which refer to the inline attachments of the mail, are
+/// replaced by the URLs returned by `inline_url`, given the content id of the attachment. The
+/// elements that are left open are closed, and the end tags without start tags are removed.
+///
+/// Unless `allow_remote` is true, the remote images are replaced by placeholders and the remote
+/// CSS references are removed, so that the sender can't know when the mail is read. The tracking
+/// pixels, which are tiny images or images of known trackers, are always removed.
+pub fn sanitize<F: Fn(&str) -> String>(html: &str, inline_url: F, allow_remote: bool) -> Sanitized {
+    let mut sanitizer = Sanitizer {
+        inline_url: &inline_url,
+        output: String::with_capacity(html.len()),
+        open: vec![],
+        dropped: None,
+        allow_remote,
+        blocked: 0,
+        trackers: 0,
+    };
+
+    for token in Tokenizer::new(html) {
+        sanitizer.token(token);
+    }
+
+    while let Some(name) = sanitizer.open.pop() {
+        sanitizer.output.push_str(&format!("</{}>", name));
+    }
+
+    Sanitized {
+        html: sanitizer.output,
+        blocked: sanitizer.blocked,
+        trackers: sanitizer.trackers,
+    }
+}
+
+/// The tags that end the current paragraph, and whose content is a paragraph.
+const PARAGRAPH_TAGS: &[&str] = &[
+    "blockquote", "dl", "h1", "h2", "h3", "h4", "h5", "h6", "hr", "ol", "p", "pre", "table", "ul",
+];
+
+/// The tags that end the current line, and whose content starts on a new line.
+const LINE_TAGS: &[&str] = &[
+    "address", "article", "aside", "caption", "center", "dd", "div", "dt", "figure", "footer",
+    "form", "header", "li", "main", "nav", "section", "tr",
+];
+
+/// The number of nested blockquotes and lists that are shown by the quote markers and the
+/// indentation of the lines, so that the deeply nested elements don't make every line huge.
+const MAX_TEXT_DEPTH: usize = 32;
+
+/// The conversion of some HTML to plain text.
+struct TextRenderer {
+    /// The lines that are complete.
+    lines: Vec<String>,
+
+    /// The current line, which starts with its quote markers and its indentation.
+    line: String,
+
+    /// Whether the current line contains some text, besides its quote markers and its
+    /// indentation.
+    has_text: bool,
+
+    /// Whether a white space must be written before the next word.
+    space: bool,
+
+    /// The quote depth of the empty line that must be written before the next line, if any.
+    blank_line: Option<usize>,
+
+    /// The number of blockquotes that contain the current position.
+    quote_depth: usize,
+
+    /// The lists that contain the current position, from the outermost, with the number of the
+    /// next item of the ordered lists.
+    lists: Vec<Option<usize>>,
+
+    /// The marker of the list item that starts on the next line, if any.
+    marker: Option<String>,
+
+    /// The number of preformatted elements that contain the current position.
+    pre_depth: usize,
+
+    /// The URL and the text of the current link, if any.
+    link: Option<(String, String)>,
+
+    /// The URLs of the links, which are written as footnotes.
+    footnotes: Vec<String>,
+
+    /// The element whose content is ignored, if any, with the number of its nested elements with
+    /// the same name that are open.
+    ignored: Option<(String, usize)>,
+}
+
+impl TextRenderer {
+    /// Starts the current line if it is not started yet, writing its quote markers, its
+    /// indentation and its list marker.
+    fn start_line(&mut self) {
+        if self.has_text {
+            return;
+        }
+
+        if let Some(depth) = self.blank_line.take() {
+            if !self.lines.is_empty() {
+                self.lines.push(">".repeat(depth.min(MAX_TEXT_DEPTH)));
+            }
+        }
+
+        self.line.clear();
+
+        if self.quote_depth > 0 {
+            self.line.push_str(&">".repeat(self.quote_depth.min(MAX_TEXT_DEPTH)));
+            self.line.push(' ');
+        }
+
+        let list_depth = self.lists.len().min(MAX_TEXT_DEPTH);
+
+        match self.marker.take() {
+            Some(marker) => {
+                self.line.push_str(&"  ".repeat(list_depth.saturating_sub(1)));
+                self.line.push_str(&marker);
+            },
+            None => self.line.push_str(&"  ".repeat(list_depth)),
+        }
+
+        self.has_text = true;
+        self.space = false;
+    }
+
+    /// Writes some text as it is.
+    fn write_raw(&mut self, text: &str) {
+        self.start_line();
+        self.line.push_str(text);
+
+        if let Some((_, link_text)) = &mut self.link {
+            link_text.push_str(text);
+        }
+    }
+
+    /// Writes some text, whose white spaces are collapsed unless it is preformatted.
+    fn write(&mut self, text: &str) {
+        if self.pre_depth > 0 {
+            for (index, line) in text.split('\n').enumerate() {
+                if index > 0 {
+                    self.start_line();
+                    self.end_line();
+                }
+
+                if !line.is_empty() {
+                    self.write_raw(line.trim_end_matches('\r'));
+                }
+            }
+
+            return;
+        }
+
+        if text.starts_with(|c: char| c.is_ascii_whitespace()) {
+            self.space = true;
+        }
+
+        for (index, word) in text.split_ascii_whitespace().enumerate() {
+            if (index > 0 || self.space) && self.has_text {
+                self.write_raw(" ");
+            }
+
+            self.write_raw(word);
+            self.space = false;
+        }
+
+        if text.ends_with(|c: char| c.is_ascii_whitespace()) {
+            self.space = true;
+        }
+    }
+
+    /// Ends the current line, if it contains some text.
+    fn end_line(&mut self) {
+        if self.has_text {
+            self.lines.push(std::mem::take(&mut self.line));
+            self.has_text = false;
+        }
+
+        self.space = false;
+    }
+
+    /// Ends the current paragraph, so that the next line is preceded by an empty line.
+    fn end_paragraph(&mut self) {
+        self.end_line();
+
+        let depth = self.blank_line.map(|x| x.min(self.quote_depth)).unwrap_or(self.quote_depth);
+        self.blank_line = Some(depth);
+    }
+
+    /// Writes the footnote of a link that ends, unless its text is its URL.
+    fn end_link(&mut self) {
+        let (url, text) = match self.link.take() {
+            Some(link) => link,
+            None => return,
+        };
+
+        let address = url.split_once(':').map(|x| x.1).unwrap_or("");
+        let text = text.trim();
+
+        if text == url || text == address || text == address.trim_start_matches("//").trim_end_matches('/') {
+            return;
+        }
+
+        let index = match self.footnotes.iter().position(|x| *x == url) {
+            Some(index) => index,
+            None => {
+                self.footnotes.push(url);
+                self.footnotes.len() - 1
+            },
+        };
+
+        if text.is_empty() {
+            self.write(&format!("[{}]", index + 1));
+        } else {
+            self.write(&format!(" [{}]", index + 1));
+        }
+    }
+
+    /// Renders a start tag.
+    fn start_tag(&mut self, name: &str, attributes: &[(String, String)]) {
+        let attribute = |x: &str| attributes.iter().find(|(name, _)| name == x).map(|(_, value)| value.as_str());
+
+        // The nested lists are not paragraphs.
+        if (name == "ul" || name == "ol") && !self.lists.is_empty() {
+            self.end_line();
+        } else if PARAGRAPH_TAGS.contains(&name) {
+            self.end_paragraph();
+        } else if LINE_TAGS.contains(&name) {
+            self.end_line();
+        }
+
+        match name {
+            "blockquote" => self.quote_depth += 1,
+            "pre" => self.pre_depth += 1,
+            "ul" => self.lists.push(None),
+            "ol" => self.lists.push(Some(attribute("start").and_then(|x| x.trim().parse().ok()).unwrap_or(1))),
+
+            "li" => {
+                self.marker = match self.lists.last_mut() {
+                    // The start of the list comes from the mail, and may be the largest number.
+                    Some(Some(number)) => {
+                        let marker = format!("{}. ", number);
+                        *number = number.saturating_add(1);
+                        Some(marker)
+                    },
+                    _ => Some(String::from("* ")),
+                };
+            },
+
+            // Two line breaks in a row make an empty line.
+            "br" if self.has_text => self.end_line(),
+            "br" => self.end_paragraph(),
+
+            "hr" => {
+                self.write_raw("---");
+                self.end_paragraph();
+            },
+
+            "td" | "th" if self.has_text => self.write_raw(" | "),
+
+            "img" => match attribute("alt").map(str::trim) {
+                Some(alt) if !alt.is_empty() => self.write(&format!("[{}]", alt)),
+                _ => (),
+            },
+
+            "a" => {
+                self.end_link();
+                self.link = attribute("href").map(|x| (clean_url(x), String::new()));
+            },
+
+            _ => (),
+        }
+    }
+
+    /// Renders an end tag.
+    fn end_tag(&mut self, name: &str) {
+        match name {
+            "blockquote" => {
+                self.end_paragraph();
+                self.quote_depth = self.quote_depth.saturating_sub(1);
+            },
+            "pre" => self.pre_depth = self.pre_depth.saturating_sub(1),
+            "ul" | "ol" => {
+                self.lists.pop();
+            },
+            "a" => self.end_link(),
+            _ => (),
+        }
+
+        if (name == "ul" || name == "ol") && !self.lists.is_empty() {
+            self.end_line();
+        } else if PARAGRAPH_TAGS.contains(&name) {
+            self.end_paragraph();
+        } else if LINE_TAGS.contains(&name) {
+            self.end_line();
+        }
+    }
+
+    /// Renders a token.
+    fn token(&mut self, token: Token) {
+        if let Some((ignored, depth)) = &mut self.ignored {
+            match token {
+                Token::StartTag { ref name, .. } if name == ignored => *depth += 1,
+                Token::EndTag(ref name) if name == ignored && *depth > 0 => *depth -= 1,
+                Token::EndTag(ref name) if name == ignored => self.ignored = None,
+                _ => (),
+            }
+
+            return;
+        }
+
+        match token {
+            Token::Text(text) => self.write(&decode_entities(text)),
+
+            Token::StartTag { name, attributes } => {
+                if DROPPED_TAGS.contains(&name.as_str()) || name == "head" {
+                    self.ignored = Some((name, 0));
+                } else {
+                    self.start_tag(&name, &attributes);
+                }
+            },
+
+            Token::EndTag(name) => self.end_tag(&name),
+        }
+    }
+}
+
+/// Converts some HTML to plain text, such as the text of the mails that have no plain text
+/// version.
+///
+/// The white spaces are collapsed and each paragraph is a line, so that the text can be reflowed.
+/// The blockquotes are quoted with `>`, the items of the lists start with `*` or with their number,
+/// the cells of the tables are separated by `|`, and the URLs of the links are written as
+/// footnotes.
+pub fn to_text(html: &str) -> String {
+    let mut renderer = TextRenderer {
+        lines: vec![],
+        line: String::new(),
+        has_text: false,
+        space: false,
+        blank_line: None,
+        quote_depth: 0,
+        lists: vec![],
+        marker: None,
+        pre_depth: 0,
+        link: None,
+        footnotes: vec![],
+        ignored: None,
+    };
+
+    for token in Tokenizer::new(html) {
+        renderer.token(token);
+    }
+
+    renderer.end_link();
+    renderer.end_line();
+
+    if !renderer.footnotes.is_empty() {
+        renderer.lines.push(String::new());
+
+        for (index, url) in renderer.footnotes.iter().enumerate() {
+            renderer.lines.push(format!("[{}] {}", index + 1, url));
+        }
+    }
+
+    let lines = renderer.lines.iter().map(|x| x.trim_end().replace('\u{a0}', " ")).collect::<Vec<_>>();
+    lines.join("\n")
+}
@@ -5,44 +5,384 @@
 #[macro_use]
 extern crate nom;
 
-use std::result;
+use std::{fmt, result};
+use chrono::{DateTime, FixedOffset};
 
 pub mod parser;
-pub use parser::parse;
-pub use parser::parse_headers;
+pub mod borrowed;
+pub mod stream;
+pub mod thread;
+pub mod builder;
+pub mod flowed;
+pub mod html;
+pub mod report;
+pub mod calendar;
+pub mod pgp;
+mod charset;
+mod transfer_encoding;
+mod writer;
+pub use parser::{parse, parse_lenient};
+pub use parser::{parse_headers, parse_headers_lenient};
+pub use stream::StreamParser;
+pub use thread::{thread, Thread};
+pub use builder::MailBuilder;
+pub use flowed::Paragraph;
 
 #[cfg(test)]
 mod tests;
 
-/// Public type of error of this crate.
-pub type Error<'a> = nom::Err<&'a [u8]>;
+#[cfg(test)]
+mod proptests;
+
+/// The different kinds of errors that can occur while parsing a mail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A line of the headers is not a header, or the value of a header is invalid.
+    InvalidHeader,
+
+    /// The input ended in the middle of a header.
+    UnexpectedEnd,
+
+    /// The multipart mails are nested too deeply.
+    TooDeep,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::InvalidHeader => write!(f, "invalid header"),
+            ErrorKind::UnexpectedEnd => write!(f, "unexpected end of input"),
+            ErrorKind::TooDeep => write!(f, "multipart mails nested too deeply"),
+        }
+    }
+}
+
+/// An error that occured while parsing a mail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The kind of the error.
+    pub kind: ErrorKind,
+
+    /// The offset in bytes, from the beginning of the input, at which the error occured.
+    pub offset: usize,
+
+    /// The name of the header in which the error occured, if any.
+    pub header: Option<String>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at byte {}", self.kind, self.offset)?;
+
+        if let Some(header) = &self.header {
+            write!(f, " in header {}", header)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
 
 /// Public type of result of this crate.
-pub type Result<'a, T> = result::Result<T, Error<'a>>;
+pub type Result<T> = result::Result<T, ParseError>;
 
-/// The different content types a mail can have.
-#[derive(Debug)]
-pub enum ContentType {
-    /// A plain text mail.
-    TextPlain,
+/// The content type of a mail, such as `text/plain; charset=utf-8`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentType {
+    /// The main type of the content, in lowercase, e.g. `text` or `multipart`.
+    pub main_type: String,
 
-    /// An HTML formatted mail.
-    TextHtml,
+    /// The subtype of the content, in lowercase, e.g. `plain` or `alternative`.
+    pub subtype: String,
 
-    /// A multipart alternative, with sub mails.
-    ///
-    /// The Vec<u8> is the boundary to separate the mails.
-    MultipartAlternative(Vec<u8>),
+    /// The parameters of the content type, with their names in lowercase.
+    pub parameters: Vec<(String, String)>,
+}
+
+impl ContentType {
+    /// Returns the value of a parameter of the content type, if any.
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns the MIME type of the content, e.g. `text/plain`.
+    pub fn mime_type(&self) -> String {
+        format!("{}/{}", self.main_type, self.subtype)
+    }
+
+    /// Returns true if the content type has this main type and this subtype.
+    pub fn is(&self, main_type: &str, subtype: &str) -> bool {
+        self.main_type.eq_ignore_ascii_case(main_type) && self.subtype.eq_ignore_ascii_case(subtype)
+    }
+
+    /// Returns true if the content is text.
+    pub fn is_text(&self) -> bool {
+        self.main_type.eq_ignore_ascii_case("text")
+    }
+
+    /// Returns true if the content is a multipart content.
+    pub fn is_multipart(&self) -> bool {
+        self.main_type.eq_ignore_ascii_case("multipart")
+    }
+
+    /// Returns true if the content is a whole mail, such as a forwarded mail, whose type is
+    /// `message/rfc822` or `message/global`.
+    pub fn is_message(&self) -> bool {
+        self.is("message", "rfc822") || self.is("message", "global")
+    }
+
+    /// Returns the charset of the content, if specified.
+    pub fn charset(&self) -> Option<&str> {
+        self.parameter("charset")
+    }
+
+    /// Returns the boundary that separates the parts of a multipart content, if specified.
+    pub fn boundary(&self) -> Option<&str> {
+        self.parameter("boundary")
+    }
+}
+
+impl Default for ContentType {
+    /// Returns the content type of the mails that don't specify one, `text/plain; charset=us-ascii`.
+    fn default() -> ContentType {
+        ContentType {
+            main_type: String::from("text"),
+            subtype: String::from("plain"),
+            parameters: vec![(String::from("charset"), String::from("us-ascii"))],
+        }
+    }
+}
+
+/// The disposition type of a mail, that tells how it should be presented.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DispositionType {
+    /// The content should be displayed with the message.
+    Inline,
+
+    /// The content is an attachment, that should not be displayed automatically.
+    Attachment,
+
+    /// Some other disposition type, in lowercase.
+    Other(String),
+}
+
+/// The content disposition of a mail, such as `attachment; filename="foo.pdf"`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentDisposition {
+    /// The disposition type.
+    pub disposition: DispositionType,
+
+    /// The parameters of the content disposition, with their names in lowercase.
+    pub parameters: Vec<(String, String)>,
+}
+
+impl ContentDisposition {
+    /// Returns the value of a parameter of the content disposition, if any.
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns the suggested filename of the content, if any.
+    pub fn filename(&self) -> Option<&str> {
+        self.parameter("filename")
+    }
 }
 
 /// The content transfer encoding of a mail.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ContentTransferEncoding {
     /// A quoted printable content.
     QuotedPrintable,
 
     /// Some base64-encoded content.
     Base64,
+
+    /// Some 7-bit text, which is not encoded.
+    SevenBit,
+
+    /// Some 8-bit text, which is not encoded.
+    EightBit,
+
+    /// Some binary content, which is not encoded.
+    Binary,
+}
+
+impl ContentTransferEncoding {
+    /// Decodes some content that was encoded with this encoding.
+    pub fn decode(&self, content: &[u8]) -> Vec<u8> {
+        match self {
+            ContentTransferEncoding::QuotedPrintable => transfer_encoding::decode_quoted_printable(content),
+            ContentTransferEncoding::Base64 => transfer_encoding::decode_base64(content),
+            _ => content.to_vec(),
+        }
+    }
+
+    /// Encodes some content with this encoding.
+    pub fn encode(&self, content: &[u8]) -> Vec<u8> {
+        match self {
+            ContentTransferEncoding::QuotedPrintable => transfer_encoding::encode_quoted_printable(content),
+            ContentTransferEncoding::Base64 => transfer_encoding::encode_base64(content),
+            _ => content.to_vec(),
+        }
+    }
+
+    /// Returns the name of the encoding, as it appears in the headers, e.g. `quoted-printable`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContentTransferEncoding::QuotedPrintable => "quoted-printable",
+            ContentTransferEncoding::Base64 => "base64",
+            ContentTransferEncoding::SevenBit => "7bit",
+            ContentTransferEncoding::EightBit => "8bit",
+            ContentTransferEncoding::Binary => "binary",
+        }
+    }
+}
+
+/// A mail address, as found in the address headers of a mail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Address {
+    /// The name displayed for this address, if any.
+    pub display_name: Option<String>,
+
+    /// The part of the address before the `@`.
+    pub local_part: String,
+
+    /// The part of the address after the `@`.
+    pub domain: String,
+}
+
+impl Address {
+    /// Parses an address, such as `John Doe <john@example.com>` or `john@example.com`.
+    pub fn parse(input: &str) -> Option<Address> {
+        parser::parse_address(input)
+    }
+
+    /// Returns the address without its display name, e.g. `someone@example.com`.
+    ///
+    /// The control chars are removed, so that an address can't add lines to the headers.
+    pub fn address(&self) -> String {
+        let domain = self.domain.chars().filter(|c| !c.is_control()).collect::<String>();
+        format!("{}@{}", quote(&self.local_part, |c| is_atext(c) || c == '.'), domain)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.display_name {
+            Some(ref name) => write!(f, "{} <{}>", quote(name, |c| is_atext(c) || c == ' '), self.address()),
+            None => write!(f, "{}", self.address()),
+        }
+    }
+}
+
+/// Checks if a char can appear in an atom without being quoted.
+fn is_atext(c: char) -> bool {
+    c.is_alphanumeric() || "!#$%&'*+-/=?^_`{|}~".contains(c)
+}
+
+/// Quotes a string if it contains chars that are not allowed.
+///
+/// The control chars can't be quoted, and are removed.
+fn quote(s: &str, is_allowed: fn(char) -> bool) -> String {
+    if !s.is_empty() && s.chars().all(is_allowed) {
+        s.to_string()
+    } else {
+        let s = s.chars().filter(|c| !c.is_control()).collect::<String>();
+        format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+/// A named group of addresses, such as `Friends: a@example.com, b@example.com;`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    /// The name of the group.
+    pub name: String,
+
+    /// The addresses of the group, which may be empty.
+    pub addresses: Vec<Address>,
+}
+
+/// An element of an address list, which is either a single address or a group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recipient {
+    /// A single address.
+    Address(Address),
+
+    /// A group of addresses.
+    Group(Group),
+}
+
+impl Recipient {
+    /// Returns all the addresses of this recipient.
+    pub fn addresses(&self) -> &[Address] {
+        match self {
+            Recipient::Address(address) => std::slice::from_ref(address),
+            Recipient::Group(group) => &group.addresses,
+        }
+    }
+}
+
+/// The identifier of a mail, such as `1234@example.com`.
+///
+/// The angle brackets that surround message ids in the headers are not part of the identifier.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MessageId(pub String);
+
+impl fmt::Display for MessageId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<{}>", self.0)
+    }
+}
+
+/// The identifier of a mailing list (RFC 2919), such as `Announcements <announce.example.com>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListId {
+    /// The description of the list, if any.
+    pub description: Option<String>,
+
+    /// The identifier of the list, without its angle brackets, e.g. `announce.example.com`.
+    pub id: String,
+}
+
+/// The date of a mail.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Date {
+    /// A date that was successfully parsed, with the offset of its time zone.
+    Valid(DateTime<FixedOffset>),
+
+    /// A date that couldn't be parsed, with its raw value.
+    Invalid(String),
+}
+
+impl Date {
+    /// Returns the parsed date, if it is valid.
+    pub fn date_time(&self) -> Option<&DateTime<FixedOffset>> {
+        match self {
+            Date::Valid(date) => Some(date),
+            Date::Invalid(_) => None,
+        }
+    }
+
+    /// Returns the number of seconds since the epoch, if the date is valid.
+    pub fn timestamp(&self) -> Option<i64> {
+        self.date_time().map(DateTime::timestamp)
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Date::Valid(date) => write!(f, "{}", date.to_rfc2822()),
+            Date::Invalid(raw) => write!(f, "{}", raw),
+        }
+    }
 }
 
 /// The different headers that appear in a mail.
@@ -52,29 +392,116 @@ pub enum Header {
     Subject(String),
 
     /// The date of the mail.
-    Date(String),
+    Date(Date),
+
+    /// The authors of the mail.
+    From(Vec<Address>),
+
+    /// The agent responsible for the transmission of the mail, if it differs from the authors.
+    Sender(Address),
+
+    /// The addresses to which replies should be sent.
+    ReplyTo(Vec<Recipient>),
+
+    /// The primary recipients of the mail.
+    To(Vec<Recipient>),
+
+    /// The recipients that receive a carbon copy of the mail.
+    Cc(Vec<Recipient>),
 
-    /// The sender of the mail.
-    From(String),
+    /// The recipients that receive a blind carbon copy of the mail.
+    Bcc(Vec<Recipient>),
+
+    /// The unique identifier of the mail.
+    MessageId(MessageId),
+
+    /// The identifiers of the mails to which the mail replies.
+    InReplyTo(Vec<MessageId>),
+
+    /// The identifiers of the mails of the conversation to which the mail belongs.
+    References(Vec<MessageId>),
 
     /// The content type of the mail.
     ContentType(ContentType),
 
+    /// The content disposition of the mail.
+    ContentDisposition(ContentDisposition),
+
     /// The content transfer encoding of the mail.
     ContentTransferEncoding(ContentTransferEncoding),
 
-    /// Some unknown header.
-    Unknown(String),
+    /// The identifier of the mailing list that sent the mail.
+    ListId(ListId),
+
+    /// The URLs to unsubscribe from the mailing list, such as `mailto:` or `https:` URLs, in order
+    /// of preference.
+    ListUnsubscribe(Vec<String>),
+
+    /// The URLs to post to the mailing list, which are empty if posting is not allowed.
+    ListPost(Vec<String>),
+
+    /// The URLs of the archives of the mailing list.
+    ListArchive(Vec<String>),
+
+    /// Some unknown header, such as `X-Mailer` or `List-Help`.
+    Unknown {
+        /// The name of the header, as it appears in the mail.
+        ///
+        /// The lines that are not headers, which are only kept by the lenient parsers, have an
+        /// empty name.
+        name: String,
+
+        /// The unfolded value of the header, whose encoded words are decoded.
+        value: String,
+    },
+}
+
+impl Header {
+    /// Returns the name of the header, e.g. `Subject` or `X-Mailer`.
+    ///
+    /// The unknown headers keep their name as it appears in the mail.
+    pub fn name(&self) -> &str {
+        match self {
+            Header::Subject(_) => "Subject",
+            Header::Date(_) => "Date",
+            Header::From(_) => "From",
+            Header::Sender(_) => "Sender",
+            Header::ReplyTo(_) => "Reply-To",
+            Header::To(_) => "To",
+            Header::Cc(_) => "Cc",
+            Header::Bcc(_) => "Bcc",
+            Header::MessageId(_) => "Message-ID",
+            Header::InReplyTo(_) => "In-Reply-To",
+            Header::References(_) => "References",
+            Header::ContentType(_) => "Content-Type",
+            Header::ContentDisposition(_) => "Content-Disposition",
+            Header::ContentTransferEncoding(_) => "Content-Transfer-Encoding",
+            Header::ListId(_) => "List-Id",
+            Header::ListUnsubscribe(_) => "List-Unsubscribe",
+            Header::ListPost(_) => "List-Post",
+            Header::ListArchive(_) => "List-Archive",
+            Header::Unknown { name, .. } => name,
+        }
+    }
 }
 
 /// The different types of body a mail can have.
 #[derive(Debug)]
 pub enum Body {
-    /// It can be some raw content.
-    Content(String),
+    /// It can be some content, decoded according to its content transfer encoding.
+    Content(Vec<u8>),
 
     /// It can be a multipart mail, containing some sub mails.
-    Multi(Vec<Mail>),
+    Multi {
+        /// The subtype of the multipart content, e.g. `mixed` or `alternative`.
+        subtype: String,
+
+        /// The parts of the mail.
+        parts: Vec<Mail>,
+    },
+
+    /// It can be a whole mail, such as a forwarded mail, with its own headers and parts.
+    Message(Box<Mail>),
 }
 
 /// A collection of headers.
@@ -84,26 +511,276 @@ pub enum Body {
 pub struct Headers(pub Vec<Header>);
 
 impl Headers {
+    /// Returns the first header with this name, if any.
+    ///
+    /// The names of the headers are not case sensitive, and the unknown headers, such as
+    /// `X-Mailer` or `List-Id`, can be found as well.
+    pub fn get(&self, name: &str) -> Option<&Header> {
+        self.0.iter().find(|x| x.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Returns all the headers with this name, in the order in which they appear, such as the
+    /// `Received` headers.
+    pub fn get_all(&self, name: &str) -> Vec<&Header> {
+        self.0.iter().filter(|x| x.name().eq_ignore_ascii_case(name)).collect()
+    }
+
     /// Returns the subject of the mail, if any.
     pub fn subject(&self) -> Option<&String> {
         for header in &self.0 {
-            match header {
-                Header::Subject(s) => return Some(s),
-                _ => (),
+            if let Header::Subject(s) = header {
+                return Some(s);
             }
         }
 
         None
     }
 
-    /// Looks for the boundary in the header of a mail.
-    ///
-    /// Returns none if it is not a multipart mail.
-    fn boundary(&self) -> Option<&Vec<u8>> {
+    /// Returns the date of the mail, if any.
+    pub fn date(&self) -> Option<&Date> {
+        for header in &self.0 {
+            if let Header::Date(d) = header {
+                return Some(d);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the authors of the mail, if any.
+    pub fn from(&self) -> Option<&[Address]> {
+        for header in &self.0 {
+            if let Header::From(f) = header {
+                return Some(f);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the sender of the mail, if any.
+    pub fn sender(&self) -> Option<&Address> {
+        for header in &self.0 {
+            if let Header::Sender(s) = header {
+                return Some(s);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the reply to addresses of the mail, if any.
+    pub fn reply_to(&self) -> Option<&[Recipient]> {
+        for header in &self.0 {
+            if let Header::ReplyTo(r) = header {
+                return Some(r);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the primary recipients of the mail, if any.
+    pub fn to(&self) -> Option<&[Recipient]> {
+        for header in &self.0 {
+            if let Header::To(t) = header {
+                return Some(t);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the carbon copy recipients of the mail, if any.
+    pub fn cc(&self) -> Option<&[Recipient]> {
+        for header in &self.0 {
+            if let Header::Cc(c) = header {
+                return Some(c);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the blind carbon copy recipients of the mail, if any.
+    pub fn bcc(&self) -> Option<&[Recipient]> {
         for header in &self.0 {
-            match header {
-                Header::ContentType(ContentType::MultipartAlternative(b)) => return Some(b),
-                _ => (),
+            if let Header::Bcc(b) = header {
+                return Some(b);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the identifier of the mail, if any.
+    pub fn message_id(&self) -> Option<&MessageId> {
+        for header in &self.0 {
+            if let Header::MessageId(m) = header {
+                return Some(m);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the identifiers of the mails to which the mail replies, if any.
+    pub fn in_reply_to(&self) -> Option<&[MessageId]> {
+        for header in &self.0 {
+            if let Header::InReplyTo(i) = header {
+                return Some(i);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the identifiers of the mails of the conversation of the mail, if any.
+    pub fn references(&self) -> Option<&[MessageId]> {
+        for header in &self.0 {
+            if let Header::References(r) = header {
+                return Some(r);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the content type of the mail, if any.
+    pub fn content_type(&self) -> Option<&ContentType> {
+        for header in &self.0 {
+            if let Header::ContentType(c) = header {
+                return Some(c);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the content disposition of the mail, if any.
+    pub fn content_disposition(&self) -> Option<&ContentDisposition> {
+        for header in &self.0 {
+            if let Header::ContentDisposition(c) = header {
+                return Some(c);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the content transfer encoding of the mail, if any.
+    pub fn content_transfer_encoding(&self) -> Option<&ContentTransferEncoding> {
+        for header in &self.0 {
+            if let Header::ContentTransferEncoding(c) = header {
+                return Some(c);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the identifier of the mailing list that sent the mail, if any.
+    pub fn list_id(&self) -> Option<&ListId> {
+        for header in &self.0 {
+            if let Header::ListId(l) = header {
+                return Some(l);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the URLs to unsubscribe from the mailing list that sent the mail, if any.
+    pub fn list_unsubscribe(&self) -> Option<&[String]> {
+        for header in &self.0 {
+            if let Header::ListUnsubscribe(l) = header {
+                return Some(l);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the URLs to post to the mailing list that sent the mail, if any.
+    pub fn list_post(&self) -> Option<&[String]> {
+        for header in &self.0 {
+            if let Header::ListPost(l) = header {
+                return Some(l);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the URLs of the archives of the mailing list that sent the mail, if any.
+    pub fn list_archive(&self) -> Option<&[String]> {
+        for header in &self.0 {
+            if let Header::ListArchive(l) = header {
+                return Some(l);
+            }
+        }
+
+        None
+    }
+
+    /// Checks if the mailing list that sent the mail can be unsubscribed from with a single POST
+    /// request to its HTTPS unsubscribe URL, as specified by RFC 8058.
+    pub fn is_one_click_unsubscribe(&self) -> bool {
+        self.get_all("List-Unsubscribe-Post").into_iter().any(|header| match header {
+            Header::Unknown { value, .. } => value.trim().eq_ignore_ascii_case("List-Unsubscribe=One-Click"),
+            _ => false,
+        })
+    }
+}
+
+/// An attachment of a mail.
+#[derive(Debug)]
+pub struct Attachment<'a> {
+    /// The name of the file, if any.
+    pub filename: Option<String>,
+
+    /// The MIME type of the attachment, e.g. `application/pdf`.
+    pub mime_type: String,
+
+    /// The size of the decoded content, in bytes, which is zero for the embedded mails.
+    pub size: usize,
+
+    /// The decoded content of the attachment, which is empty for the embedded mails.
+    pub content: &'a [u8],
+
+    /// The embedded mail, if the attachment is a mail, such as a forwarded mail.
+    pub message: Option<&'a Mail>,
+}
+
+/// An iterator over the attachments of a mail.
+pub struct Attachments<'a> {
+    /// The parts that remain to be visited, the next one being at the end.
+    stack: Vec<&'a Mail>,
+}
+
+impl<'a> Iterator for Attachments<'a> {
+    type Item = Attachment<'a>;
+
+    fn next(&mut self) -> Option<Attachment<'a>> {
+        while let Some(mail) = self.stack.pop() {
+            match mail.body {
+                Body::Multi { ref parts, .. } => self.stack.extend(parts.iter().rev()),
+                Body::Content(ref content) => {
+                    if let Some(attachment) = mail.attachment(content) {
+                        return Some(attachment);
+                    }
+                },
+
+                // The attachments of the embedded mails belong to these mails.
+                Body::Message(ref message) => {
+                    return Some(Attachment {
+                        filename: mail.filename().map(String::from),
+                        mime_type: mail.headers.content_type().map(ContentType::mime_type).unwrap_or_default(),
+                        size: 0,
+                        content: &[],
+                        message: Some(message),
+                    });
+                },
             }
         }
 
@@ -119,12 +796,332 @@ pub struct Mail {
 
     /// The boy of the mail.
     body: Body,
+
+    /// The charset that is used to decode the text of the mail, if it is a text mail.
+    charset: Option<&'static encoding_rs::Encoding>,
 }
 
 impl Mail {
+    /// Creates a mail from its headers and its body.
+    ///
+    /// The content of the body is not encoded, and the charset of a text mail is detected from its
+    /// content type and its content.
+    pub fn new(headers: Headers, body: Body) -> Mail {
+        let charset = match (&body, headers.content_type()) {
+            (Body::Content(content), Some(content_type)) if content_type.is_text() => {
+                Some(charset::detect(content_type.charset(), content))
+            },
+            (Body::Content(content), None) => Some(charset::detect(None, content)),
+            _ => None,
+        };
+
+        Mail { headers, body, charset }
+    }
+
+    /// Returns the headers of the mail.
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Returns the body of the mail.
+    pub fn body(&self) -> &Body {
+        &self.body
+    }
+
     /// Returns the subject of the mail, if any.
     pub fn subject(&self) -> Option<&String> {
         self.headers.subject()
     }
+
+    /// Returns the name of the file contained in the mail, if any.
+    ///
+    /// It is looked for in the content disposition, and then in the content type.
+    pub fn filename(&self) -> Option<&str> {
+        self.headers
+            .content_disposition()
+            .and_then(ContentDisposition::filename)
+            .or_else(|| self.headers.content_type().and_then(|x| x.parameter("name")))
+    }
+
+    /// Returns the attachments of the mail.
+    ///
+    /// A part is considered to be an attachment if its disposition is attachment or if it has a
+    /// filename, and the embedded mails are always attachments.
+    pub fn attachments(&self) -> Attachments<'_> {
+        Attachments { stack: vec![self] }
+    }
+
+    /// Returns the first part of the mail that has this content type and that is not an
+    /// attachment, such as the plain text or the HTML version of the mail.
+    ///
+    /// The part may be the mail itself if it is not a multipart mail, and the parts of the embedded
+    /// mails are not searched.
+    pub fn part(&self, main_type: &str, subtype: &str) -> Option<&Mail> {
+        let mut stack = vec![self];
+
+        while let Some(mail) = stack.pop() {
+            match mail.body {
+                Body::Multi { ref parts, .. } => stack.extend(parts.iter().rev()),
+                Body::Message(_) => (),
+                Body::Content(ref content) => {
+                    let is_match = match mail.headers.content_type() {
+                        Some(content_type) => content_type.is(main_type, subtype),
+                        None => ContentType::default().is(main_type, subtype),
+                    };
+
+                    if is_match && mail.attachment(content).is_none() {
+                        return Some(mail);
+                    }
+                },
+            }
+        }
+
+        None
+    }
+
+    /// Returns the part of the mail that has this content id, such as an image that is displayed
+    /// in the HTML version of the mail.
+    ///
+    /// The content id is given without its angle brackets, as in the `cid` URLs. Since the content
+    /// ids are unique, the parts of the embedded mails are searched as well.
+    pub fn inline_part(&self, content_id: &str) -> Option<&Mail> {
+        let mut stack = vec![self];
+
+        while let Some(mail) = stack.pop() {
+            match mail.body {
+                Body::Multi { ref parts, .. } => stack.extend(parts.iter().rev()),
+                Body::Message(ref message) => stack.push(message),
+                Body::Content(_) => {
+                    let is_match = mail.headers.get_all("Content-ID").into_iter().any(|header| match header {
+                        Header::Unknown { value, .. } => value.trim().trim_start_matches('<').trim_end_matches('>') == content_id,
+                        _ => false,
+                    });
+
+                    if is_match {
+                        return Some(mail);
+                    }
+                },
+            }
+        }
+
+        None
+    }
+
+    /// Returns the attachment corresponding to this part, if it is an attachment.
+    fn attachment<'a>(&self, content: &'a [u8]) -> Option<Attachment<'a>> {
+        let disposition = self.headers.content_disposition().map(|x| &x.disposition);
+        let filename = self.filename();
+
+        if disposition != Some(&DispositionType::Attachment) && filename.is_none() {
+            return None;
+        }
+
+        Some(Attachment {
+            filename: filename.map(String::from),
+            mime_type: self.headers
+                .content_type()
+                .map(ContentType::mime_type)
+                .unwrap_or_else(|| ContentType::default().mime_type()),
+            size: content.len(),
+            content,
+            message: None,
+        })
+    }
+
+    /// Returns the name of the charset that is used to decode the text of the mail, e.g. `UTF-8` or
+    /// `windows-1252`.
+    ///
+    /// Returns none if the mail is not a text mail.
+    pub fn charset(&self) -> Option<&'static str> {
+        self.charset.map(encoding_rs::Encoding::name)
+    }
+
+    /// Returns the text of the mail, converted from its charset to UTF-8.
+    ///
+    /// Returns none if the mail is not a text mail.
+    pub fn text(&self) -> Option<String> {
+        match (&self.body, self.charset) {
+            (Body::Content(content), Some(charset)) => Some(charset::decode_with(charset, content)),
+            _ => None,
+        }
+    }
+
+    /// Returns the paragraphs of the text of a plain text mail, with their quote depth.
+    ///
+    /// The lines of the mails that use the flowed format of RFC 3676 are joined into paragraphs,
+    /// and each line of the other mails is a paragraph. Returns none if the mail is not a plain text
+    /// mail.
+    pub fn paragraphs(&self) -> Option<Vec<Paragraph>> {
+        let content_type = self.headers.content_type().cloned().unwrap_or_default();
+
+        if !content_type.is("text", "plain") {
+            return None;
+        }
+
+        let text = self.text()?;
+        let delsp = content_type.parameter("delsp").map(|x| x.eq_ignore_ascii_case("yes")).unwrap_or(false);
+
+        match content_type.parameter("format") {
+            Some(format) if format.eq_ignore_ascii_case("flowed") => Some(flowed::decode(&text, delsp)),
+            _ => Some(flowed::decode_fixed(&text)),
+        }
+    }
+
+    /// Returns the plain text of the mail, converted from its HTML version if it has no plain text
+    /// version.
+    ///
+    /// The plain text version is preferred among the alternatives, and the first part that has
+    /// some text is used in the other multipart mails. The paragraphs of the text are lines, which
+    /// start with their quote markers if they are quoted. Returns none if the mail has no text.
+    pub fn plain_text(&self) -> Option<String> {
+        match self.body {
+            Body::Multi { ref subtype, ref parts } if subtype.eq_ignore_ascii_case("alternative") => {
+                let is_plain = |x: &&Mail| x.headers.content_type().map(|x| x.is("text", "plain")).unwrap_or(true);
+
+                parts
+                    .iter()
+                    .filter(is_plain)
+                    .chain(parts.iter().rev())
+                    .find_map(Mail::plain_text)
+            },
+
+            Body::Multi { ref parts, .. } => parts.iter().find_map(Mail::plain_text),
+
+            // The text of the embedded mails is not the text of the mail.
+            Body::Message(_) => None,
+
+            Body::Content(ref content) => {
+                if self.attachment(content).is_some() {
+                    return None;
+                }
+
+                let content_type = self.headers.content_type().cloned().unwrap_or_default();
+
+                if content_type.is("text", "html") {
+                    return self.text().map(|x| html::to_text(&x));
+                }
+
+                let paragraphs = self.paragraphs()?
+                    .into_iter()
+                    .map(|x| match x.quote_depth {
+                        0 => x.text,
+                        depth => format!("{} {}", ">".repeat(depth), x.text),
+                    })
+                    .collect::<Vec<_>>();
+
+                Some(paragraphs.join("\n"))
+            },
+        }
+    }
+
+    /// Returns a short preview of the text of the mail, with at most `length` chars, such as the
+    /// ones that are displayed in the lists of mails.
+    ///
+    /// The quotes, the lines that introduce them and the signature are removed, and the white
+    /// spaces are collapsed. If the text is longer, it is cut at the end of a word and followed by
+    /// an ellipsis, which is not counted in the length. Returns none if the mail has no text.
+    pub fn snippet(&self, length: usize) -> Option<String> {
+        let text = self.plain_text()?;
+        let lines = text.lines().take_while(|x| *x != "-- ").collect::<Vec<_>>();
+        let mut kept = vec![];
+
+        for (index, line) in lines.iter().enumerate() {
+            let next = lines[index + 1 ..].iter().find(|x| !x.trim().is_empty());
+            let introduces_quote = line.trim_end().ends_with(':') && next.map(|x| x.starts_with('>')).unwrap_or(false);
+
+            if !line.starts_with('>') && !introduces_quote {
+                kept.push(*line);
+            }
+        }
+
+        let words = kept.iter().flat_map(|x| x.split_whitespace()).collect::<Vec<_>>();
+        let text = words.join(" ");
+
+        if text.chars().count() <= length {
+            return Some(text);
+        }
+
+        // The text is cut at the end of a word if possible, which is the case if the character
+        // after the cut is a space.
+        let (end, next) = match text.char_indices().nth(length) {
+            Some((index, c)) => (index, index + c.len_utf8()),
+            None => (text.len(), text.len()),
+        };
+
+        let cut = match text[..next].rfind(' ') {
+            Some(space) if space > 0 => &text[..space],
+            _ => &text[..end],
+        };
+
+        Some(format!("{}\u{2026}", cut.trim_end()))
+    }
+
+    /// Returns the calendar of the mail, such as a meeting invitation, if it has a `text/calendar`
+    /// part.
+    ///
+    /// The calendars that are attachments are returned as well, since some clients attach the
+    /// invitations instead of displaying them.
+    pub fn calendar(&self) -> Option<calendar::Calendar> {
+        let text = match self.part("text", "calendar") {
+            Some(part) => part.text()?,
+            None => {
+                let attachment = self.attachments().find(|x| x.mime_type == "text/calendar")?;
+                String::from_utf8_lossy(attachment.content).into_owned()
+            },
+        };
+
+        Some(calendar::parse_calendar(&text))
+    }
+
+    /// Returns the delivery status notification of the mail, if it is a bounce or a delay warning
+    /// sent back by a mail server.
+    ///
+    /// The parts of the embedded mails, such as the mail that bounced, are not searched.
+    pub fn delivery_report(&self) -> Option<report::DeliveryReport> {
+        let mut stack = vec![self];
+
+        while let Some(mail) = stack.pop() {
+            let parts = match mail.body {
+                Body::Multi { ref parts, .. } => parts,
+                _ => continue,
+            };
+
+            let is_report = match mail.headers.content_type() {
+                Some(x) if x.is("multipart", "report") => {
+                    x.parameter("report-type").map(|x| x.eq_ignore_ascii_case("delivery-status")).unwrap_or(false)
+                },
+                _ => false,
+            };
+
+            if !is_report {
+                stack.extend(parts.iter().rev());
+                continue;
+            }
+
+            for part in parts {
+                let is_status = part
+                    .headers
+                    .content_type()
+                    .map(|x| x.is("message", "delivery-status") || x.is("message", "global-delivery-status"))
+                    .unwrap_or(false);
+
+                if let (true, Body::Content(content)) = (is_status, &part.body) {
+                    return Some(report::parse_delivery_status(content));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Serializes the mail, as specified by RFC 5322 and MIME.
+    ///
+    /// The headers are folded and their non ASCII text is encoded, and the content transfer
+    /// encoding of each part is chosen according to its content, which replaces the one of its
+    /// headers. The boundaries of the multipart parts are kept if they don't appear in the parts.
+    /// The bcc header is not serialized, so that the recipients don't see it.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        writer::write_mail(self)
+    }
 }
 
@@ -0,0 +1,219 @@
+//! This module contains the parsers for the address headers of a mail (RFC 5322, section 3.4).
+
+use nom::IResult;
+use nom::types::CompleteStr;
+
+use crate::{Address, Group, Recipient};
+use crate::parser::encoded_word;
+use crate::parser::lexical::{Input, cfws, error, quoted_string, complete};
+
+/// Checks if a char is allowed in an atom.
+///
+/// Non ASCII chars are allowed as well, as specified in RFC 6532.
+fn is_atext(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~".contains(c) || !c.is_ascii()
+}
+
+/// Checks if a char is allowed in a dot atom.
+fn is_dot_atext(c: char) -> bool {
+    is_atext(c) || c == '.'
+}
+
+/// Checks if some text has no control chars, which are not allowed in the quoted local parts and
+/// in the domain literals, and would let the addresses add lines to the headers.
+fn has_no_control(text: &str) -> bool {
+    !text.chars().any(char::is_control)
+}
+
+/// Parses a domain literal, such as `[127.0.0.1]`.
+named!(domain_literal<Input, String>,
+    map!(
+        verify!(
+            recognize!(delimited!(char!('['), take_until!("]"), char!(']'))),
+            |x: Input| has_no_control(x.0)
+        ),
+        |x| x.0.to_string()
+    )
+);
+
+/// Recognizes an encoded word.
+///
+/// Encoded words are not supposed to contain special chars, but a lot of mailers encode display
+/// names such as `Doe, John` without escaping the comma.
+named!(encoded_word<Input, Input>,
+    recognize!(tuple!(
+        tag!("=?"),
+        is_not!("?"),
+        char!('?'),
+        is_not!("?"),
+        char!('?'),
+        take_while!(|c: char| c != '?' && !c.is_whitespace()),
+        tag!("?=")
+    ))
+);
+
+/// Parses a word of a phrase.
+///
+/// Dots are accepted, since a lot of mailers don't quote display names such as `John Q. Public`.
+named!(word<Input, String>,
+    delimited!(
+        cfws,
+        alt!(
+            map!(encoded_word, |x| x.0.to_string()) |
+            quoted_string |
+            map!(take_while1!(is_dot_atext), |x| x.0.to_string())
+        ),
+        cfws
+    )
+);
+
+/// Parses a phrase, which is the display name of an address or a group.
+///
+/// The encoded words of the phrase are decoded, even inside quoted strings, since many mailers
+/// quote them.
+named!(pub phrase<Input, String>,
+    map!(many1!(word), |x| encoded_word::decode(x.join(" ").as_bytes()))
+);
+
+/// Parses a quoted local part, such as `"john doe"`.
+fn quoted_local_part(input: Input) -> IResult<Input, String> {
+    match quoted_string(input)? {
+        (rest, local_part) if has_no_control(&local_part) => Ok((rest, local_part)),
+        _ => error(input),
+    }
+}
+
+/// Parses the local part of an address.
+named!(local_part<Input, String>,
+    delimited!(
+        cfws,
+        alt!(
+            quoted_local_part |
+            map!(take_while1!(is_dot_atext), |x| x.0.to_string())
+        ),
+        cfws
+    )
+);
+
+/// Parses the domain of an address.
+named!(domain<Input, String>,
+    delimited!(
+        cfws,
+        alt!(
+            domain_literal |
+            map!(take_while1!(is_dot_atext), |x| x.0.to_string())
+        ),
+        cfws
+    )
+);
+
+/// Parses an address of the form `local@domain`.
+named!(addr_spec<Input, (String, String)>,
+    separated_pair!(local_part, char!('@'), domain)
+);
+
+/// Parses an obsolete route, such as `@a.example,@b.example:`, that is ignored.
+named!(obs_route<Input, ()>,
+    map!(
+        pair!(preceded!(char!('@'), take_until!(":")), char!(':')),
+        |_| ()
+    )
+);
+
+/// Parses an address between angle brackets.
+named!(angle_addr<Input, (String, String)>,
+    delimited!(
+        pair!(cfws, char!('<')),
+        preceded!(opt!(obs_route), addr_spec),
+        pair!(char!('>'), cfws)
+    )
+);
+
+/// Parses an address with an optional display name.
+named!(name_addr<Input, Address>,
+    map!(
+        pair!(opt!(phrase), angle_addr),
+        |(display_name, (local_part, domain))| Address { display_name, local_part, domain }
+    )
+);
+
+/// Parses a mailbox, which is a single address.
+named!(mailbox<Input, Address>,
+    alt!(
+        name_addr |
+        map!(addr_spec, |(local_part, domain)| Address { display_name: None, local_part, domain })
+    )
+);
+
+/// Parses a group of addresses, such as `Friends: a@example.com, b@example.com;`.
+named!(group<Input, Group>,
+    do_parse!(
+        name: phrase >>
+        char!(':') >>
+        addresses: call!(list, mailbox) >>
+        cfws >>
+        char!(';') >>
+        cfws >>
+        (Group { name, addresses })
+    )
+);
+
+/// Parses an element of an address list.
+named!(recipient<Input, Recipient>,
+    alt!(
+        group => { Recipient::Group } |
+        mailbox => { Recipient::Address }
+    )
+);
+
+/// Parses a comma separated list.
+///
+/// Empty elements are allowed and skipped, as specified by the obsolete syntax of RFC 5322.
+fn list<'a, T>(input: Input<'a>, item: fn(Input<'a>) -> IResult<Input<'a>, T>) -> IResult<Input<'a>, Vec<T>> {
+    let mut elements = vec![];
+    let mut input = cfws(input)?.0;
+
+    loop {
+        if input.0.starts_with(',') {
+            input = cfws(CompleteStr(&input.0[1..]))?.0;
+            continue;
+        }
+
+        let (rest, element) = match item(input) {
+            Ok(x) => x,
+            Err(_) => return Ok((input, elements)),
+        };
+
+        elements.push(element);
+        input = cfws(rest)?.0;
+
+        if !input.0.starts_with(',') {
+            return Ok((input, elements));
+        }
+    }
+}
+
+/// Parses a mailbox list, as in the from header.
+fn mailbox_list(input: Input) -> IResult<Input, Vec<Address>> {
+    list(input, mailbox)
+}
+
+/// Parses an address list, as in the to, cc, bcc and reply-to headers.
+fn address_list(input: Input) -> IResult<Input, Vec<Recipient>> {
+    list(input, recipient)
+}
+
+/// Parses the value of a header containing a single mailbox, such as the sender header.
+pub fn parse_mailbox(input: &str) -> Option<Address> {
+    complete(input, mailbox)
+}
+
+/// Parses the value of a header containing a mailbox list, such as the from header.
+pub fn parse_mailbox_list(input: &str) -> Option<Vec<Address>> {
+    complete(input, mailbox_list)
+}
+
+/// Parses the value of a header containing an address list, such as the to header.
+pub fn parse_address_list(input: &str) -> Option<Vec<Recipient>> {
+    complete(input, address_list)
+}
@@ -0,0 +1,220 @@
+//! This module contains the parsers of the content type (RFC 2045, section 5) and content
+//! disposition (RFC 2183) headers, whose parameters can be encoded as specified by RFC 2231.
+
+use nom::types::CompleteStr;
+
+use crate::{ContentType, ContentDisposition, DispositionType, charset};
+use crate::parser::encoded_word;
+use crate::parser::lexical::{Input, cfws, quoted_string, complete};
+use crate::transfer_encoding::hex_value;
+
+/// Checks if a char is allowed in a token.
+fn is_token(c: char) -> bool {
+    c > ' ' && c != '\x7f' && !"()<>@,;:\\\"/[]?=".contains(c)
+}
+
+/// Checks if a char is allowed in a parameter value that is not quoted.
+///
+/// This is more permissive than tokens, since a lot of mailers don't quote boundaries such as
+/// `----=_Part_0_1234`.
+fn is_value(c: char) -> bool {
+    c > ' ' && c != '\x7f' && c != ';' && c != '"'
+}
+
+/// Parses a token and converts it to lowercase.
+named!(token<Input, String>,
+    map!(take_while1!(is_token), |x| x.0.to_ascii_lowercase())
+);
+
+/// Parses a parameter, such as `charset="utf-8"`.
+named!(parameter<Input, (String, String)>,
+    do_parse!(
+        cfws >>
+        name: token >>
+        cfws >>
+        char!('=') >>
+        cfws >>
+        value: alt!(
+            quoted_string |
+            map!(take_while1!(is_value), |x| x.0.to_string())
+        ) >>
+        cfws >>
+        ((name, value))
+    )
+);
+
+/// Decodes the percent encoded chars of an extended parameter value.
+fn percent_decode(input: &str) -> Vec<u8> {
+    let input = input.as_bytes();
+    let mut decoded = Vec::with_capacity(input.len());
+    let mut i = 0;
+
+    while i < input.len() {
+        match (input[i], input.get(i + 1).and_then(|c| hex_value(*c)), input.get(i + 2).and_then(|c| hex_value(*c))) {
+            (b'%', Some(high), Some(low)) => {
+                decoded.push(high << 4 | low);
+                i += 3;
+            },
+            (c, _, _) => {
+                decoded.push(c);
+                i += 1;
+            },
+        }
+    }
+
+    decoded
+}
+
+/// A section of a parameter value, as specified by RFC 2231.
+struct Section {
+    /// The index of the section, which is zero for parameters that are not split.
+    index: usize,
+
+    /// Whether the section is percent encoded.
+    extended: bool,
+
+    /// The raw value of the section.
+    value: String,
+}
+
+/// Decodes the parameters that are split or encoded as specified by RFC 2231, such as
+/// `filename*0*=utf-8''%C3%A9t%C3%A9; filename*1=".pdf"`.
+///
+/// The encoded words that some mailers use in parameter values are decoded as well.
+fn decode_parameters(raw: Vec<(String, String)>) -> Vec<(String, String)> {
+    // The parameters with their sections, in the order in which they first appear.
+    let mut parameters: Vec<(String, Vec<Section>)> = vec![];
+
+    // The parameters that are not encoded, which are only used if there is no encoded version.
+    let mut plain: Vec<(String, String)> = vec![];
+
+    for (name, value) in raw {
+        let mut split = name.splitn(2, '*');
+        let base = split.next().unwrap_or_default().to_string();
+
+        let section = match split.next() {
+            None => {
+                plain.push((base, value));
+                continue;
+            },
+            Some("") => Section { index: 0, extended: true, value },
+            Some(rest) => {
+                let extended = rest.ends_with('*');
+                match rest.trim_end_matches('*').parse() {
+                    Ok(index) => Section { index, extended, value },
+                    Err(_) => continue,
+                }
+            },
+        };
+
+        match parameters.iter_mut().find(|(n, _)| *n == base) {
+            Some((_, sections)) => sections.push(section),
+            None => parameters.push((base, vec![section])),
+        }
+    }
+
+    let mut decoded = vec![];
+
+    for (name, value) in plain {
+        if !parameters.iter().any(|(n, _)| *n == name) {
+            decoded.push((name, encoded_word::decode(value.as_bytes())));
+        }
+    }
+
+    for (name, mut sections) in parameters {
+        sections.sort_by_key(|x| x.index);
+
+        // The charset and the language are specified at the beginning of the first section.
+        let mut label = None;
+        let mut bytes = vec![];
+
+        for (i, section) in sections.iter().enumerate() {
+            if !section.extended {
+                bytes.extend_from_slice(section.value.as_bytes());
+                continue;
+            }
+
+            let mut value = section.value.as_str();
+
+            if i == 0 {
+                let split = value.splitn(3, '\'').collect::<Vec<_>>();
+                if let [charset, _language, rest] = &split[..] {
+                    label = Some(charset.to_string());
+                    value = rest;
+                }
+            }
+
+            bytes.extend_from_slice(&percent_decode(value));
+        }
+
+        let value = match label {
+            Some(ref label) if !label.is_empty() => charset::decode(label, &bytes),
+            _ => charset::decode_unknown(&bytes),
+        };
+
+        decoded.push((name, value));
+    }
+
+    decoded
+}
+
+/// Parses the parameters of a content type, separated by semicolons.
+///
+/// Empty parameters are skipped, since a lot of mailers add a trailing semicolon.
+named!(parameters<Input, Vec<(String, String)>>,
+    map!(
+        many0!(preceded!(char!(';'), opt!(parameter))),
+        |x| decode_parameters(x.into_iter().flatten().collect())
+    )
+);
+
+/// Parses a content type, such as `multipart/mixed; boundary="foo"`.
+named!(content_type<Input, ContentType>,
+    do_parse!(
+        cfws >>
+        main_type: token >>
+        cfws >>
+        char!('/') >>
+        cfws >>
+        subtype: token >>
+        cfws >>
+        parameters: parameters >>
+        cfws >>
+        (ContentType { main_type, subtype, parameters })
+    )
+);
+
+/// Parses the value of a content type header.
+pub fn parse_content_type(input: &str) -> Option<ContentType> {
+    complete(input, content_type).or_else(|| {
+        // Ignore the invalid parameters rather than the whole header.
+        content_type(CompleteStr(input)).ok().map(|(_, content_type)| content_type)
+    })
+}
+
+/// Parses a content disposition, such as `attachment; filename="foo.pdf"`.
+named!(content_disposition<Input, ContentDisposition>,
+    do_parse!(
+        cfws >>
+        disposition: token >>
+        cfws >>
+        parameters: parameters >>
+        cfws >>
+        (ContentDisposition {
+            disposition: match disposition.as_str() {
+                "inline" => DispositionType::Inline,
+                "attachment" => DispositionType::Attachment,
+                _ => DispositionType::Other(disposition),
+            },
+            parameters,
+        })
+    )
+);
+
+/// Parses the value of a content disposition header.
+pub fn parse_content_disposition(input: &str) -> Option<ContentDisposition> {
+    complete(input, content_disposition).or_else(|| {
+        // Ignore the invalid parameters rather than the whole header.
+        content_disposition(CompleteStr(input)).ok().map(|(_, content_disposition)| content_disposition)
+    })
+}
@@ -0,0 +1,158 @@
+//! This module contains the parser of the date header (RFC 5322, section 3.3).
+
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone};
+use nom::types::CompleteStr;
+
+use crate::Date;
+use crate::parser::lexical::{Input, cfws, complete};
+
+/// The names of the days, that are only checked and ignored.
+const DAYS: &[&str] = &["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// The names of the months.
+const MONTHS: &[&str] = &["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+/// The obsolete names of the time zones, with their offsets in hours.
+const ZONES: &[(&str, i32)] = &[
+    ("ut", 0), ("gmt", 0), ("utc", 0), ("z", 0),
+    ("est", -5), ("edt", -4),
+    ("cst", -6), ("cdt", -5),
+    ("mst", -7), ("mdt", -6),
+    ("pst", -8), ("pdt", -7),
+];
+
+/// Finds the index of a name in a list of names, comparing only the first three letters.
+fn find_name(names: &[&str], name: &str) -> Option<usize> {
+    if name.len() < 3 || !name.is_char_boundary(3) {
+        return None;
+    }
+
+    names.iter().position(|x| x.eq_ignore_ascii_case(&name[..3]))
+}
+
+/// Parses a number of at most `max` digits.
+fn number(input: Input, max: usize) -> nom::IResult<Input, u32> {
+    let length = input.0.chars().take(max).take_while(char::is_ascii_digit).count();
+
+    match input.0[..length].parse() {
+        Ok(n) => Ok((CompleteStr(&input.0[length..]), n)),
+        Err(_) => crate::parser::lexical::error(input),
+    }
+}
+
+/// Parses the optional day of the week, such as `Tue,`.
+///
+/// The comma is optional, since some mailers forget it.
+named!(day_of_week<Input, ()>,
+    map!(
+        pair!(
+            verify!(take_while1!(|c: char| c.is_ascii_alphabetic()), |x: Input| find_name(DAYS, x.0).is_some()),
+            pair!(cfws, opt!(char!(',')))
+        ),
+        |_| ()
+    )
+);
+
+/// Parses a month name, such as `Jan` or `January`.
+named!(month<Input, u32>,
+    map_opt!(take_while1!(|c: char| c.is_ascii_alphabetic()), |x: Input| find_name(MONTHS, x.0).map(|m| m as u32 + 1))
+);
+
+/// Parses a year, that may be written with two or three digits in the obsolete syntax.
+named!(year<Input, i32>,
+    map!(
+        verify!(recognize!(call!(number, 4)), |x: Input| x.0.len() >= 2),
+        |x: Input| {
+            let year = x.0.parse::<i32>().unwrap_or_default();
+            match x.0.len() {
+                2 if year < 50 => year + 2000,
+                2 | 3 => year + 1900,
+                _ => year,
+            }
+        }
+    )
+);
+
+/// Parses a numeric time zone, such as `+0100`, and returns its offset in seconds.
+named!(numeric_zone<Input, i32>,
+    do_parse!(
+        sign: alt!(char!('+') => { |_| 1 } | char!('-') => { |_| -1 }) >>
+        hours: verify!(call!(number, 2), |x| x < 24) >>
+        opt!(char!(':')) >>
+        minutes: verify!(call!(number, 2), |x| x < 60) >>
+        (sign * (hours as i32 * 3600 + minutes as i32 * 60))
+    )
+);
+
+/// Parses an obsolete time zone, such as `GMT` or `EST`, and returns its offset in seconds.
+///
+/// Military zones and unknown zones are considered to be equivalent to UTC, as specified by RFC
+/// 5322, since the meaning of the military zones was reversed by mistake in RFC 822.
+named!(obsolete_zone<Input, i32>,
+    map!(take_while1!(|c: char| c.is_ascii_alphabetic()), |x: Input| {
+        ZONES.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(x.0))
+            .map(|(_, offset)| offset * 3600)
+            .unwrap_or(0)
+    })
+);
+
+/// Parses a time zone, which may be followed by its name, as in `+0100 CET`.
+named!(zone<Input, i32>,
+    alt!(
+        terminated!(numeric_zone, opt!(preceded!(cfws, obsolete_zone))) |
+        obsolete_zone
+    )
+);
+
+/// Parses a date and a time, such as `Tue, 22 Jan 2019 01:43:14 +0000 (UTC)`.
+///
+/// The seconds and the time zone are optional.
+named!(date_time<Input, Option<DateTime<FixedOffset>>>,
+    do_parse!(
+        cfws >>
+        opt!(day_of_week) >>
+        cfws >>
+        day: call!(number, 2) >>
+        cfws >>
+        month: month >>
+        cfws >>
+        year: year >>
+        cfws >>
+        hour: call!(number, 2) >>
+        cfws >>
+        char!(':') >>
+        cfws >>
+        minute: call!(number, 2) >>
+        second: opt!(preceded!(pair!(cfws, char!(':')), preceded!(cfws, call!(number, 2)))) >>
+        cfws >>
+        offset: opt!(zone) >>
+        cfws >>
+        ({
+            // Leap seconds are not supported by chrono's parsers, so we clamp them.
+            let second = second.unwrap_or(0).min(59);
+
+            NaiveDate::from_ymd_opt(year, month, day)
+                .and_then(|date| date.and_hms_opt(hour, minute, second))
+                .and_then(|date| {
+                    FixedOffset::east_opt(offset.unwrap_or(0))?
+                        .from_local_datetime(&date)
+                        .single()
+                })
+        })
+    )
+);
+
+/// Parses the value of a date header.
+///
+/// Dates that don't follow RFC 5322 are also tried as RFC 3339 dates, since some mailers use them,
+/// and the raw value is kept if the parsing fails.
+pub fn parse_date(input: &str) -> Date {
+    match complete(input, date_time) {
+        Some(Some(date)) => Date::Valid(date),
+        _ => match DateTime::parse_from_rfc3339(input.trim()) {
+            Ok(date) => Date::Valid(date),
+            Err(_) => Date::Invalid(input.to_string()),
+        },
+    }
+}
@@ -0,0 +1,192 @@
+//! This module contains the decoding of the encoded words of the headers (RFC 2047).
+
+use nom::types::CompleteByteSlice;
+
+use crate::charset;
+use crate::transfer_encoding::hex_value;
+
+/// The input of the encoded word parsers.
+type Input<'a> = CompleteByteSlice<'a>;
+
+/// The encodings an encoded word can use.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Encoding {
+    /// The Q encoding, similar to quoted printable.
+    Q,
+
+    /// The B encoding, which is base64.
+    B,
+}
+
+/// Checks if a byte is allowed in the charset and encoding of an encoded word.
+fn is_token(c: u8) -> bool {
+    c > b' ' && c < 127 && !b"()<>@,;:\"/[]?.=".contains(&c)
+}
+
+/// Checks if a byte is allowed in the text of an encoded word.
+fn is_encoded_text(c: u8) -> bool {
+    c > b' ' && c < 127 && c != b'?'
+}
+
+/// Parses an encoded word, such as `=?iso-8859-1?Q?caf=E9?=`.
+///
+/// Returns the charset, the encoding and the encoded text of the word.
+named!(encoded_word<Input, (Input, Encoding, Input)>,
+    do_parse!(
+        tag!("=?") >>
+        charset: take_while1!(is_token) >>
+        char!('?') >>
+        encoding: alt!(
+            tag_no_case!("Q") => { |_| Encoding::Q } |
+            tag_no_case!("B") => { |_| Encoding::B }
+        ) >>
+        char!('?') >>
+        text: take_while!(is_encoded_text) >>
+        tag!("?=") >>
+        (charset, encoding, text)
+    )
+);
+
+/// Decodes some text encoded with the Q encoding.
+///
+/// Invalid escape sequences are kept as is.
+fn decode_q(text: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(text.len());
+    let mut i = 0;
+
+    while i < text.len() {
+        match text[i] {
+            b'_' => decoded.push(b' '),
+            b'=' => match (text.get(i + 1).and_then(|c| hex_value(*c)), text.get(i + 2).and_then(|c| hex_value(*c))) {
+                (Some(high), Some(low)) => {
+                    decoded.push(high << 4 | low);
+                    i += 2;
+                },
+                _ => decoded.push(b'='),
+            },
+            c => decoded.push(c),
+        }
+
+        i += 1;
+    }
+
+    decoded
+}
+
+/// Decodes some text encoded with the B encoding.
+///
+/// Missing padding is tolerated, and returns none if the text is not valid base64.
+fn decode_b(text: &[u8]) -> Option<Vec<u8>> {
+    let text = text.iter().cloned().filter(|c| *c != b'=').collect::<Vec<_>>();
+    base64::decode_config(&text, base64::STANDARD_NO_PAD).ok()
+}
+
+/// Removes the language specification of a charset, as allowed by RFC 2231, e.g. `utf-8*fr`.
+fn strip_language(charset: &[u8]) -> &[u8] {
+    match charset.iter().position(|c| *c == b'*') {
+        Some(i) => &charset[..i],
+        None => charset,
+    }
+}
+
+/// A piece of a header value, which is either some raw text or a decoded word.
+enum Token<'a> {
+    /// Some text that is not encoded.
+    Raw(&'a [u8]),
+
+    /// An encoded word, with its charset and its decoded bytes.
+    Encoded(&'a [u8], Vec<u8>),
+}
+
+/// Splits a header value into raw text and encoded words.
+fn tokenize(input: &[u8]) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < input.len() {
+        if input[i..].starts_with(b"=?") {
+            if let Ok((rest, (charset, encoding, text))) = encoded_word(CompleteByteSlice(&input[i..])) {
+                let decoded = match encoding {
+                    Encoding::Q => Some(decode_q(text.0)),
+                    Encoding::B => decode_b(text.0),
+                };
+
+                if let Some(decoded) = decoded {
+                    if start < i {
+                        tokens.push(Token::Raw(&input[start..i]));
+                    }
+
+                    tokens.push(Token::Encoded(strip_language(charset.0), decoded));
+                    i = input.len() - rest.0.len();
+                    start = i;
+                    continue;
+                }
+            }
+        }
+
+        i += 1;
+    }
+
+    if start < input.len() {
+        tokens.push(Token::Raw(&input[start..]));
+    }
+
+    tokens
+}
+
+/// Decodes all the encoded words of a header value.
+///
+/// White spaces between two adjacent encoded words are ignored, and consecutive encoded words that
+/// share the same charset are decoded together, so that a multi-byte character may be split across
+/// several encoded words. Text that is not encoded is expected to be UTF-8, but other 8-bit text is
+/// tolerated.
+pub fn decode(input: &[u8]) -> String {
+    let tokens = tokenize(input);
+    let mut output = String::new();
+
+    // The charset and the bytes of the encoded words that have not been decoded yet.
+    let mut pending: Option<(&[u8], Vec<u8>)> = None;
+
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Raw(raw) => {
+                // Raw text and encoded words alternate, so a raw token that is neither the first nor
+                // the last one is between two encoded words.
+                let after_encoded_word = index > 0;
+                let before_encoded_word = index + 1 < tokens.len();
+
+                if after_encoded_word && before_encoded_word && raw.iter().all(|c| b" \t\r\n".contains(c)) {
+                    continue;
+                }
+
+                if let Some((charset, bytes)) = pending.take() {
+                    output.push_str(&charset::decode(&String::from_utf8_lossy(charset), &bytes));
+                }
+
+                output.push_str(&charset::decode_unknown(raw));
+            },
+
+            Token::Encoded(charset, bytes) => {
+                match pending {
+                    Some((pending_charset, ref mut pending_bytes)) if pending_charset.eq_ignore_ascii_case(charset) => {
+                        pending_bytes.extend_from_slice(bytes);
+                    },
+                    _ => {
+                        if let Some((charset, bytes)) = pending.take() {
+                            output.push_str(&charset::decode(&String::from_utf8_lossy(charset), &bytes));
+                        }
+
+                        pending = Some((charset, bytes.clone()));
+                    },
+                }
+            },
+        }
+    }
+
+    if let Some((charset, bytes)) = pending.take() {
+        output.push_str(&charset::decode(&String::from_utf8_lossy(charset), &bytes));
+    }
+
+    output
+}
@@ -0,0 +1,83 @@
+//! This module contains the lexical tokens shared by the parsers of the structured header values
+//! (RFC 5322, section 3.2).
+
+use nom::{IResult, ErrorKind, multispace};
+use nom::types::CompleteStr;
+
+/// The input of the parsers of structured header values.
+///
+/// Header values are already unfolded when they reach these parsers, so we use complete input.
+pub type Input<'a> = CompleteStr<'a>;
+
+/// Returns an error at the given position of the input.
+pub fn error<T>(input: Input) -> IResult<Input, T> {
+    Err(nom::Err::Error(error_position!(input, ErrorKind::Custom(0))))
+}
+
+/// Parses a comment, that may contain nested comments.
+///
+/// An unterminated comment is considered to end with the input.
+pub fn comment(input: Input) -> IResult<Input, Input> {
+    if !input.0.starts_with('(') {
+        return error(input);
+    }
+
+    let mut depth = 0;
+    let mut escaped = false;
+
+    for (i, c) in input.0.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok((CompleteStr(&input.0[i + 1..]), CompleteStr(&input.0[..=i])));
+                }
+            },
+            _ => (),
+        }
+    }
+
+    Ok((CompleteStr(""), input))
+}
+
+/// Parses a quoted string and returns its unescaped content.
+///
+/// An unterminated quoted string is considered to end with the input.
+pub fn quoted_string(input: Input) -> IResult<Input, String> {
+    if !input.0.starts_with('"') {
+        return error(input);
+    }
+
+    let mut content = String::new();
+    let mut escaped = false;
+
+    for (i, c) in input.0.char_indices().skip(1) {
+        match c {
+            _ if escaped => {
+                content.push(c);
+                escaped = false;
+            },
+            '\\' => escaped = true,
+            '"' => return Ok((CompleteStr(&input.0[i + 1..]), content)),
+            _ => content.push(c),
+        }
+    }
+
+    Ok((CompleteStr(""), content))
+}
+
+/// Parses comments and folding white spaces.
+named!(pub cfws<Input, ()>,
+    map!(many0!(alt!(multispace | comment)), |_| ())
+);
+
+/// Runs a parser on a whole header value, and fails if some input is left.
+pub fn complete<T>(input: &str, parser: fn(Input) -> IResult<Input, T>) -> Option<T> {
+    match parser(CompleteStr(input)) {
+        Ok((rest, output)) if rest.0.is_empty() => Some(output),
+        _ => None,
+    }
+}
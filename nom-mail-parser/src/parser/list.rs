@@ -0,0 +1,61 @@
+//! This module contains the parsers of the headers that the mailing lists add to their mails, such
+//! as the list id header (RFC 2919) and the list URL headers (RFC 2369).
+
+use crate::ListId;
+use crate::parser::address::phrase;
+use crate::parser::lexical::{Input, cfws, complete};
+
+/// Checks if a char is allowed in a list id.
+///
+/// This is more permissive than RFC 2919, which only allows dot atoms.
+fn is_id(c: char) -> bool {
+    c > ' ' && c != '\x7f' && c != '<' && c != '>'
+}
+
+/// Parses a list id, with its description if any, such as `Announcements <announce.example.com>`.
+named!(list_id<Input, ListId>,
+    do_parse!(
+        description: opt!(phrase) >>
+        cfws >>
+        char!('<') >>
+        id: take_while1!(is_id) >>
+        char!('>') >>
+        cfws >>
+        (ListId { description, id: id.0.to_string() })
+    )
+);
+
+/// Parses a URL between angle brackets.
+///
+/// The white spaces of the URL are removed, since RFC 2369 allows the URLs to be folded.
+named!(url<Input, String>,
+    map!(
+        delimited!(pair!(cfws, char!('<')), take_until!(">"), pair!(char!('>'), cfws)),
+        |x| x.0.split_whitespace().collect()
+    )
+);
+
+/// Parses a list of URLs between angle brackets, separated by commas.
+named!(url_list<Input, Vec<String>>,
+    separated_nonempty_list!(char!(','), url)
+);
+
+/// Parses the value of the list id header.
+pub fn parse_list_id(input: &str) -> Option<ListId> {
+    complete(input, list_id)
+}
+
+/// Parses the value of a header containing a list of URLs, such as the list unsubscribe header.
+pub fn parse_url_list(input: &str) -> Option<Vec<String>> {
+    complete(input, url_list)
+}
+
+/// Parses the value of the list post header, which is `NO` if posting to the list is not allowed.
+///
+/// Returns an empty list of URLs if posting is not allowed.
+pub fn parse_list_post(input: &str) -> Option<Vec<String>> {
+    match input.trim_start().get(..2) {
+        Some(no) if no.eq_ignore_ascii_case("no") && complete(&input.trim_start()[2..], cfws).is_some() => Some(vec![]),
+        _ => parse_url_list(input),
+    }
+}
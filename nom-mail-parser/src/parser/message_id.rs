@@ -0,0 +1,70 @@
+//! This module contains the parsers of the identification headers of a mail, which are the
+//! message id, in reply to and references headers (RFC 5322, section 3.6.4).
+
+use crate::MessageId;
+use crate::parser::lexical::{Input, cfws, quoted_string, complete};
+
+/// Checks if a char is allowed in a message id.
+///
+/// This is more permissive than RFC 5322, since a lot of mailers generate message ids that are not
+/// valid, for example with several at signs or with quoted local parts.
+fn is_id(c: char) -> bool {
+    c > ' ' && c != '\x7f' && c != '<' && c != '>'
+}
+
+/// Parses a message id between angle brackets, such as `<1234@example.com>`.
+named!(msg_id<Input, MessageId>,
+    map!(
+        delimited!(
+            pair!(cfws, char!('<')),
+            take_while1!(is_id),
+            pair!(char!('>'), cfws)
+        ),
+        |x| MessageId(x.0.to_string())
+    )
+);
+
+/// Parses something that is not a message id in an obsolete list of message ids, that is ignored.
+named!(obs_phrase<Input, ()>,
+    map!(
+        delimited!(
+            cfws,
+            alt!(
+                map!(quoted_string, |_| ()) |
+                map!(take_while1!(|c: char| !c.is_whitespace() && c != '<' && c != '(' && c != '"'), |_| ())
+            ),
+            cfws
+        ),
+        |_| ()
+    )
+);
+
+/// Parses a list of message ids, as in the in reply to and references headers.
+///
+/// The phrases and the other words that appear between the message ids, as allowed by the
+/// obsolete syntax, are ignored.
+named!(msg_id_list<Input, Vec<MessageId>>,
+    map!(
+        many0!(alt!(map!(msg_id, Some) | map!(obs_phrase, |_| None))),
+        |x| x.into_iter().flatten().collect()
+    )
+);
+
+/// Parses the value of the message id header.
+///
+/// Message ids without angle brackets are accepted as well, provided they are a single word.
+pub fn parse_message_id(input: &str) -> Option<MessageId> {
+    complete(input, msg_id).or_else(|| {
+        let input = input.trim();
+        if !input.is_empty() && input.chars().all(is_id) {
+            Some(MessageId(input.to_string()))
+        } else {
+            None
+        }
+    })
+}
+
+/// Parses the value of a header containing a list of message ids, such as the references header.
+pub fn parse_message_id_list(input: &str) -> Option<Vec<MessageId>> {
+    complete(input, msg_id_list)
+}
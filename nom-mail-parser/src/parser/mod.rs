@@ -0,0 +1,476 @@
+//! This module contains all the parsing functions of this crate.
+
+// nom's named! macro drops the doc comments of the parsers, which triggers this warning.
+#![allow(unused_doc_comments)]
+
+mod lexical;
+mod address;
+mod content_type;
+mod date;
+mod message_id;
+mod list;
+mod encoded_word;
+
+use nom::types::CompleteByteSlice;
+use crate::charset;
+use crate::{Result, ParseError, ErrorKind, Address, ContentType, ContentTransferEncoding, Header, Headers, Mail, Body};
+use crate::borrowed::{self, Field};
+
+/// The maximum depth of nested multipart mails and embedded mails, beyond which the parts are not
+/// parsed so that malicious mails can't overflow the stack.
+const MAX_DEPTH: usize = 64;
+
+/// Parses a content transfer encoding.
+fn parse_content_transfer_encoding(input: &str) -> Option<ContentTransferEncoding> {
+    match &input.trim().to_ascii_lowercase()[..] {
+        "quoted-printable" => Some(ContentTransferEncoding::QuotedPrintable),
+        "base64" => Some(ContentTransferEncoding::Base64),
+        "7bit" => Some(ContentTransferEncoding::SevenBit),
+        "8bit" => Some(ContentTransferEncoding::EightBit),
+        "binary" => Some(ContentTransferEncoding::Binary),
+        _ => None,
+    }
+}
+
+/// Checks if some content is encoded with quoted printable or base64.
+pub(crate) fn is_encoded(encoding: Option<ContentTransferEncoding>) -> bool {
+    matches!(encoding, Some(ContentTransferEncoding::QuotedPrintable) | Some(ContentTransferEncoding::Base64))
+}
+
+/// Checks if a byte is allowed in the name of a header.
+fn is_field_name(c: u8) -> bool {
+    c > b' ' && c < 0x7f && c != b':'
+}
+
+/// Parses the name of a header, followed by its colon.
+///
+/// White spaces are allowed before the colon, as specified by the obsolete syntax of RFC 5322.
+named!(field_name<CompleteByteSlice, CompleteByteSlice>,
+    terminated!(
+        take_while1!(is_field_name),
+        tuple!(opt!(is_a!(" \t")), char!(':'), opt!(is_a!(" \t")))
+    )
+);
+
+/// Recognizes the raw value of a header, which may be folded on several lines, with its line
+/// break.
+///
+/// The lines may end with CRLF or with a single LF, as in the mails stored by Unix mailers.
+named!(raw_header_value<CompleteByteSlice, CompleteByteSlice>,
+    recognize!(pair!(
+        take_until_and_consume!("\n"),
+        many0!(pair!(is_a!(" \t"), take_until_and_consume!("\n")))
+    ))
+);
+
+/// Parses a header, and returns its name with its raw value.
+named!(field<CompleteByteSlice, (CompleteByteSlice, CompleteByteSlice)>,
+    pair!(field_name, raw_header_value)
+);
+
+/// Finds the next line break, which is either CRLF or a single LF, and returns the offsets of its
+/// beginning and of its end.
+pub(crate) fn line_break(input: &[u8]) -> Option<(usize, usize)> {
+    let end = input.iter().position(|c| *c == b'\n')?;
+
+    match end.checked_sub(1) {
+        Some(start) if input[start] == b'\r' => Some((start, end + 1)),
+        _ => Some((end, end + 1)),
+    }
+}
+
+/// Unfolds the raw value of a header, removing its line breaks.
+///
+/// As specified by RFC 5322, only the line breaks are removed, and the white spaces that start the
+/// continuation lines are kept.
+pub(crate) fn unfold(value: &[u8]) -> Vec<u8> {
+    let mut unfolded = Vec::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some((start, end)) = line_break(rest) {
+        unfolded.extend_from_slice(&rest[..start]);
+        rest = &rest[end..];
+    }
+
+    unfolded.extend_from_slice(rest);
+    unfolded
+}
+
+/// Decodes the value of a structured header, whose encoded words can only be decoded once its
+/// structure has been parsed.
+fn structured(value: &[u8]) -> String {
+    charset::decode_unknown(value)
+}
+
+/// Creates an unknown header from its name and its raw value.
+fn unknown(name: &str, value: &[u8]) -> Header {
+    Header::Unknown {
+        name: name.to_string(),
+        value: encoded_word::decode(value),
+    }
+}
+
+/// Parses the unfolded value of a header according to its name.
+///
+/// Returns `None` if the header is known but its value is invalid.
+pub(crate) fn header(name: &str, value: &[u8]) -> Option<Header> {
+    Some(match &name.to_ascii_lowercase()[..] {
+        "subject" => Header::Subject(encoded_word::decode(value)),
+        "date" => Header::Date(date::parse_date(&encoded_word::decode(value))),
+        "from" => Header::From(address::parse_mailbox_list(&structured(value))?),
+        "sender" => Header::Sender(address::parse_mailbox(&structured(value))?),
+        "reply-to" => Header::ReplyTo(address::parse_address_list(&structured(value))?),
+        "to" => Header::To(address::parse_address_list(&structured(value))?),
+        "cc" => Header::Cc(address::parse_address_list(&structured(value))?),
+        "bcc" => Header::Bcc(address::parse_address_list(&structured(value))?),
+        "message-id" => Header::MessageId(message_id::parse_message_id(&structured(value))?),
+        "in-reply-to" => Header::InReplyTo(message_id::parse_message_id_list(&structured(value))?),
+        "references" => Header::References(message_id::parse_message_id_list(&structured(value))?),
+        "content-type" => Header::ContentType(content_type::parse_content_type(&structured(value))?),
+        "content-disposition" => {
+            Header::ContentDisposition(content_type::parse_content_disposition(&structured(value))?)
+        },
+
+        // The mailing lists often send malformed list headers, which are kept as unknown headers.
+        "list-id" => list::parse_list_id(&structured(value)).map(Header::ListId).unwrap_or_else(|| unknown(name, value)),
+        "list-unsubscribe" => list::parse_url_list(&structured(value)).map(Header::ListUnsubscribe).unwrap_or_else(|| unknown(name, value)),
+        "list-post" => list::parse_list_post(&structured(value)).map(Header::ListPost).unwrap_or_else(|| unknown(name, value)),
+        "list-archive" => list::parse_url_list(&structured(value)).map(Header::ListArchive).unwrap_or_else(|| unknown(name, value)),
+
+        // Unknown encodings, such as x-uuencode, are allowed by RFC 2045.
+        "content-transfer-encoding" => match parse_content_transfer_encoding(&structured(value)) {
+            Some(encoding) => Header::ContentTransferEncoding(encoding),
+            None => unknown(name, value),
+        },
+
+        _ => unknown(name, value),
+    })
+}
+
+/// Splits the body of a multipart mail into its parts.
+///
+/// The preamble and the epilogue are ignored, and if the closing boundary is missing, the last part
+/// ends with the body.
+fn split_parts<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let mut delimiter = b"--".to_vec();
+    delimiter.extend_from_slice(boundary.as_bytes());
+
+    let mut parts = vec![];
+    let mut start = None;
+    let mut position = 0;
+
+    // The beginning of the line break that ends the previous line, if any.
+    let mut previous_line_break = None;
+
+    while position < body.len() {
+        let (line_end, next) = line_break(&body[position..])
+            .map(|(start, end)| (position + start, position + end))
+            .unwrap_or((body.len(), body.len()));
+
+        let line = &body[position..line_end];
+
+        if line.starts_with(&delimiter) {
+            let rest = &line[delimiter.len()..];
+            let closing = rest.starts_with(b"--");
+            let rest = if closing { &rest[2..] } else { rest };
+
+            // The boundary may be followed by some white spaces, but nothing else.
+            if rest.iter().all(|c| *c == b' ' || *c == b'\t') {
+                if let Some(start) = start {
+                    // The line break before the boundary belongs to the boundary.
+                    let end = match previous_line_break {
+                        Some(end) if end > start => end,
+                        _ => start,
+                    };
+
+                    parts.push(&body[start..end]);
+                }
+
+                if closing {
+                    return parts;
+                }
+
+                start = Some(next);
+            }
+        }
+
+        previous_line_break = Some(line_end);
+        position = next;
+    }
+
+    if let Some(start) = start {
+        parts.push(&body[start..]);
+    }
+
+    parts
+}
+
+/// The parser of a mail.
+struct Parser<'a> {
+    /// The whole input, used to compute the offsets of the errors.
+    input: &'a [u8],
+
+    /// Whether the parser recovers from the malformed headers rather than failing.
+    lenient: bool,
+}
+
+impl<'a> Parser<'a> {
+    /// Creates an error that occured at the beginning of a slice of the input.
+    fn error(&self, kind: ErrorKind, at: &[u8], header: Option<String>) -> ParseError {
+        ParseError {
+            kind,
+            offset: (at.as_ptr() as usize).saturating_sub(self.input.as_ptr() as usize),
+            header,
+        }
+    }
+
+    /// Splits the headers of a mail, and returns them with the body that follows them.
+    ///
+    /// The headers may end with the input, in which case the body is empty.
+    fn fields(&self, mut input: &'a [u8]) -> Result<(&'a [u8], Vec<Field<'a>>)> {
+        let mut fields = vec![];
+
+        loop {
+            if input.is_empty() {
+                return Ok((input, fields));
+            }
+
+            if input.starts_with(b"\r\n") {
+                return Ok((&input[2..], fields));
+            }
+
+            if input.starts_with(b"\n") {
+                return Ok((&input[1..], fields));
+            }
+
+            if let Ok((rest, (name, value))) = field(CompleteByteSlice(input)) {
+                fields.push(Field { name: std::str::from_utf8(name.0).unwrap_or_default(), value: value.0 });
+                input = rest.0;
+                continue;
+            }
+
+            match line_break(input) {
+                // The last line is not terminated, so we parse it as if it were.
+                None => {
+                    let name = field_name(CompleteByteSlice(input))
+                        .ok()
+                        .map(|(value, name)| (std::str::from_utf8(name.0).unwrap_or_default(), value.0));
+
+                    if !self.lenient {
+                        let at = &input[input.len()..];
+                        return Err(self.error(ErrorKind::UnexpectedEnd, at, name.map(|(name, _)| name.to_string())));
+                    }
+
+                    fields.push(match name {
+                        Some((name, value)) => Field { name, value },
+                        None => Field { name: "", value: input },
+                    });
+
+                    return Ok((&input[input.len()..], fields));
+                },
+
+                // The line is not a header, so we keep it as a field without name.
+                Some((start, end)) => {
+                    if !self.lenient {
+                        return Err(self.error(ErrorKind::InvalidHeader, input, None));
+                    }
+
+                    fields.push(Field { name: "", value: &input[..start] });
+                    input = &input[end..];
+                },
+            }
+        }
+    }
+
+    /// Parses a mail without decoding it, and recursively parses its parts if it is a multipart
+    /// mail, or the mail that it contains if it is an embedded mail that is not encoded.
+    fn raw_mail(&self, input: &'a [u8], depth: usize) -> Result<borrowed::Mail<'a>> {
+        let (content, fields) = self.fields(input)?;
+        let headers = borrowed::Headers(fields);
+        let content_type = headers.content_type();
+
+        let multipart = content_type
+            .as_ref()
+            .filter(|x| x.is_multipart())
+            .and_then(|x| x.boundary().map(|boundary| (x.subtype.clone(), boundary.to_string())));
+
+        let is_message = content_type.as_ref().map(ContentType::is_message).unwrap_or(false)
+            && !is_encoded(headers.content_transfer_encoding());
+
+        if (multipart.is_some() || is_message) && depth >= MAX_DEPTH && !self.lenient {
+            return Err(self.error(ErrorKind::TooDeep, content, None));
+        }
+
+        let body = match multipart {
+            // The parts that are nested too deeply are kept as is in lenient mode.
+            Some((subtype, boundary)) if depth < MAX_DEPTH => {
+                let mut parts = vec![];
+
+                for part in split_parts(content, &boundary) {
+                    parts.push(self.raw_mail(part, depth + 1)?);
+                }
+
+                borrowed::Body::Multi { subtype, parts }
+            },
+
+            None if is_message && depth < MAX_DEPTH => {
+                borrowed::Body::Message(Box::new(self.raw_mail(content, depth + 1)?))
+            },
+
+            _ => borrowed::Body::Content(content),
+        };
+
+        Ok(borrowed::Mail { headers, body, raw: input })
+    }
+
+    /// Decodes a header, checking that its value is valid unless the parser is lenient.
+    fn header(&self, field: &Field<'a>) -> Result<Header> {
+        // The lines that are not headers are kept as is by the lenient parser.
+        if field.name.is_empty() {
+            return Ok(Header::Unknown { name: String::new(), value: charset::decode_unknown(field.value) });
+        }
+
+        let value = unfold(field.value);
+
+        match header(field.name, &value) {
+            Some(header) => Ok(header),
+            None if self.lenient => Ok(unknown(field.name, &value)),
+            None => Err(self.error(ErrorKind::InvalidHeader, field.name.as_bytes(), Some(field.name.to_string()))),
+        }
+    }
+
+    /// Decodes the headers of a mail.
+    fn headers(&self, raw: &borrowed::Headers<'a>) -> Result<Headers> {
+        let mut headers = vec![];
+
+        for field in &raw.0 {
+            headers.push(self.header(field)?);
+        }
+
+        Ok(Headers(headers))
+    }
+
+    /// Decodes a mail and its parts, given its depth in the mail that is parsed.
+    fn mail(&self, raw: borrowed::Mail<'a>, depth: usize) -> Result<Mail> {
+        let headers = self.headers(&raw.headers)?;
+
+        let body = match raw.body {
+            borrowed::Body::Multi { subtype, parts } => {
+                let mut decoded = vec![];
+
+                for part in parts {
+                    decoded.push(self.mail(part, depth + 1)?);
+                }
+
+                Body::Multi { subtype, parts: decoded }
+            },
+
+            borrowed::Body::Message(message) => Body::Message(Box::new(self.mail(*message, depth + 1)?)),
+
+            borrowed::Body::Content(content) => {
+                let decoded = match headers.content_transfer_encoding() {
+                    Some(encoding) => encoding.decode(content),
+                    None => content.to_vec(),
+                };
+
+                let is_message = headers.content_type().map(|x| x.is_message()).unwrap_or(false);
+
+                // The embedded mails that are encoded can only be parsed once they are decoded.
+                if is_message && depth < MAX_DEPTH {
+                    let parser = Parser { input: &decoded, lenient: self.lenient };
+                    let message = parser.mail(parser.raw_mail(&decoded, depth + 1)?, depth + 1)?;
+                    Body::Message(Box::new(message))
+                } else if is_message && !self.lenient {
+                    return Err(self.error(ErrorKind::TooDeep, content, None));
+                } else {
+                    Body::Content(decoded)
+                }
+            },
+        };
+
+        Ok(Mail::new(headers, body))
+    }
+}
+
+/// Parses a single address, with its display name if any.
+pub(crate) fn parse_address(input: &str) -> Option<Address> {
+    address::parse_mailbox(input)
+}
+
+/// Parses a mail.
+///
+/// An error is returned if the mail is malformed, use `parse_lenient` to parse mails that may be
+/// broken.
+pub fn parse(bytes: &[u8]) -> Result<Mail> {
+    let parser = Parser { input: bytes, lenient: false };
+    parser.mail(parser.raw_mail(bytes, 0)?, 0)
+}
+
+/// Parses a mail, recovering from the errors.
+///
+/// The malformed headers are kept as unknown headers, the last line of the headers may lack its
+/// line break, and the parts that are nested too deeply are kept as content.
+pub fn parse_lenient(bytes: &[u8]) -> Mail {
+    into_owned(parse_borrowed_lenient(bytes))
+}
+
+/// Parses only the headers of a mail.
+///
+/// This is useful if you make an IMAP request that doesn't fetch the body of a mail but only the
+/// headers.
+pub fn parse_headers(bytes: &[u8]) -> Result<Headers> {
+    let parser = Parser { input: bytes, lenient: false };
+    parser.headers(&borrowed::Headers(parser.fields(bytes)?.1))
+}
+
+/// Parses only the headers of a mail, recovering from the errors like `parse_lenient`.
+pub fn parse_headers_lenient(bytes: &[u8]) -> Headers {
+    headers_into_owned(&parse_headers_borrowed_lenient(bytes))
+}
+
+/// Parses a mail without decoding it.
+///
+/// Only the structure of the mail is checked, the values of the headers are decoded by the
+/// accessors of the borrowed mail.
+pub(crate) fn parse_borrowed(bytes: &[u8]) -> Result<borrowed::Mail<'_>> {
+    Parser { input: bytes, lenient: false }.raw_mail(bytes, 0)
+}
+
+/// Parses a mail without decoding it, recovering from the errors like `parse_lenient`.
+pub(crate) fn parse_borrowed_lenient(bytes: &[u8]) -> borrowed::Mail<'_> {
+    match (Parser { input: bytes, lenient: true }).raw_mail(bytes, 0) {
+        Ok(mail) => mail,
+        Err(_) => borrowed::Mail {
+            headers: borrowed::Headers(vec![]),
+            body: borrowed::Body::Content(bytes),
+            raw: bytes,
+        },
+    }
+}
+
+/// Parses only the headers of a mail without decoding them.
+pub(crate) fn parse_headers_borrowed(bytes: &[u8]) -> Result<borrowed::Headers<'_>> {
+    Ok(borrowed::Headers(Parser { input: bytes, lenient: false }.fields(bytes)?.1))
+}
+
+/// Parses only the headers of a mail without decoding them, recovering from the errors like
+/// `parse_lenient`.
+pub(crate) fn parse_headers_borrowed_lenient(bytes: &[u8]) -> borrowed::Headers<'_> {
+    match (Parser { input: bytes, lenient: true }).fields(bytes) {
+        Ok((_, fields)) => borrowed::Headers(fields),
+        Err(_) => borrowed::Headers(vec![]),
+    }
+}
+
+/// Decodes a borrowed mail, keeping the invalid headers as unknown headers.
+pub(crate) fn into_owned(mail: borrowed::Mail<'_>) -> Mail {
+    match (Parser { input: &[], lenient: true }).mail(mail, 0) {
+        Ok(mail) => mail,
+        Err(_) => Mail { headers: Headers(vec![]), body: Body::Content(vec![]), charset: None },
+    }
+}
+
+/// Decodes borrowed headers, keeping the invalid headers as unknown headers.
+pub(crate) fn headers_into_owned(headers: &borrowed::Headers<'_>) -> Headers {
+    (Parser { input: &[], lenient: true }).headers(headers).unwrap_or_else(|_| Headers(vec![]))
+}
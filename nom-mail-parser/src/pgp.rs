@@ -0,0 +1,232 @@
+//! This module contains the support of OpenPGP/MIME (RFC 3156), which signs and encrypts the
+//! content of the mails, and keeps their other headers, such as their subject, in clear.
+//!
+//! A signed mail is a `multipart/signed` mail whose first part is the content that is signed, and
+//! whose second part is a detached signature of the raw bytes of the first part, with its headers.
+//! An encrypted mail is a `multipart/encrypted` mail whose first part only contains `Version: 1`,
+//! and whose second part is the encrypted content, with its headers.
+//!
+//! The cryptography itself is not done here: the functions that compose the mails receive the
+//! bytes that must be signed or encrypted, and return the armored signature or the armored
+//! encrypted content.
+
+use crate::{ContentType, ContentDisposition, DispositionType, Header, Headers, Mail, Body, borrowed, writer};
+
+/// The content type of the signatures.
+const SIGNATURE_TYPE: &str = "application/pgp-signature";
+
+/// The content type of the control part of the encrypted mails.
+const ENCRYPTED_TYPE: &str = "application/pgp-encrypted";
+
+/// A signed mail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signed {
+    /// The bytes that were signed, which are the raw bytes of the signed part, with its headers and
+    /// with CRLF line breaks.
+    pub content: Vec<u8>,
+
+    /// The detached signature of the content, which is usually armored.
+    pub signature: Vec<u8>,
+
+    /// The hash algorithm of the signature, in lowercase, e.g. `pgp-sha256`, if any.
+    pub micalg: Option<String>,
+}
+
+/// An encrypted mail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Encrypted {
+    /// The encrypted content, which is usually armored.
+    ///
+    /// Once decrypted, it is a whole part, with its headers, that can be parsed as a mail.
+    pub data: Vec<u8>,
+}
+
+/// The OpenPGP protection of a mail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Protection {
+    /// The mail is signed.
+    Signed(Signed),
+
+    /// The mail is encrypted, and may be signed inside.
+    Encrypted(Encrypted),
+}
+
+/// Checks if the protocol parameter of a multipart content type is the expected one.
+fn has_protocol(content_type: &ContentType, protocol: &str) -> bool {
+    content_type.parameter("protocol").map(|x| x.eq_ignore_ascii_case(protocol)).unwrap_or(false)
+}
+
+/// Converts the line breaks of some bytes to CRLF, which is the canonical form of the signed parts.
+fn canonicalize(bytes: &[u8]) -> Vec<u8> {
+    let mut canonical = Vec::with_capacity(bytes.len());
+
+    for (i, c) in bytes.iter().enumerate() {
+        if *c == b'\n' && (i == 0 || bytes[i - 1] != b'\r') {
+            canonical.push(b'\r');
+        }
+
+        canonical.push(*c);
+    }
+
+    canonical
+}
+
+/// Returns the protection of a mail, if it is a signed or an encrypted mail.
+fn own_protection(mail: &borrowed::Mail) -> Option<Protection> {
+    let content_type = mail.headers().content_type()?;
+
+    let parts = match mail.body() {
+        borrowed::Body::Multi { parts, .. } if parts.len() == 2 => parts,
+        _ => return None,
+    };
+
+    let second_type = parts[1].headers().content_type();
+
+    if content_type.is("multipart", "signed") && has_protocol(&content_type, SIGNATURE_TYPE) {
+        if second_type.map(|x| !x.mime_type().eq_ignore_ascii_case(SIGNATURE_TYPE)).unwrap_or(true) {
+            return None;
+        }
+
+        return Some(Protection::Signed(Signed {
+            content: canonicalize(parts[0].raw()),
+            signature: parts[1].content()?.into_owned(),
+            micalg: content_type.parameter("micalg").map(str::to_lowercase),
+        }));
+    }
+
+    if content_type.is("multipart", "encrypted") && has_protocol(&content_type, ENCRYPTED_TYPE) {
+        if second_type.map(|x| !x.is("application", "octet-stream")).unwrap_or(true) {
+            return None;
+        }
+
+        return Some(Protection::Encrypted(Encrypted { data: parts[1].content()?.into_owned() }));
+    }
+
+    None
+}
+
+/// Returns the protection of a mail, if it is signed or encrypted.
+///
+/// Some mailing lists wrap the signed mails in a `multipart/mixed` mail, which is protected as well
+/// if the protected mail is its only part. The protected parts that are next to other parts are
+/// ignored, since the protection would not cover the other parts, that are shown along with them.
+pub fn protection(mail: &borrowed::Mail) -> Option<Protection> {
+    if let Some(protection) = own_protection(mail) {
+        return Some(protection);
+    }
+
+    let is_mixed = mail.headers().content_type().map(|x| x.is("multipart", "mixed")).unwrap_or(false);
+
+    match mail.body() {
+        borrowed::Body::Multi { parts, .. } if is_mixed && parts.len() == 1 => own_protection(&parts[0]),
+        _ => None,
+    }
+}
+
+/// Checks if a header describes the content of a mail, such as its content type, in which case it
+/// belongs to the part that is signed or encrypted.
+fn is_content_header(header: &Header) -> bool {
+    header.name().get(..8).map(|x| x.eq_ignore_ascii_case("content-")).unwrap_or(false)
+}
+
+/// Splits a mail into its headers that don't describe its content, and the part made of its
+/// content with the headers that describe it.
+fn split(mail: Mail) -> (Vec<Header>, Mail) {
+    let Mail { headers: Headers(headers), body, .. } = mail;
+    let (content_headers, headers) = headers.into_iter().partition(is_content_header);
+    (headers, Mail::new(Headers(content_headers), body))
+}
+
+/// Creates the content type of a multipart protected mail.
+fn multipart_type(subtype: &str, parameters: Vec<(String, String)>) -> Header {
+    Header::ContentType(ContentType {
+        main_type: String::from("multipart"),
+        subtype: subtype.to_string(),
+        parameters,
+    })
+}
+
+/// Creates a part of a protected mail, with some armored content.
+///
+/// The armored content is text, whose line breaks are converted to CRLF so that it is not encoded.
+fn part(mime_type: &str, filename: Option<&str>, content: Vec<u8>) -> Mail {
+    let (main_type, subtype) = mime_type.split_once('/').unwrap_or((mime_type, ""));
+
+    let mut headers = vec![Header::ContentType(ContentType {
+        main_type: main_type.to_string(),
+        subtype: subtype.to_string(),
+        parameters: filename.map(|x| (String::from("name"), x.to_string())).into_iter().collect(),
+    })];
+
+    if let Some(filename) = filename {
+        headers.push(Header::ContentDisposition(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![(String::from("filename"), filename.to_string())],
+        }));
+    }
+
+    Mail::new(Headers(headers), Body::Content(canonicalize(&content)))
+}
+
+/// Signs a mail, as specified by RFC 3156.
+///
+/// The content of the mail, with the headers that describe it, is serialized and given to the
+/// signing function, which must return a detached armored signature. The other headers of the
+/// mail, such as its subject and its recipients, are not signed. The micalg is the hash algorithm
+/// of the signature, e.g. `pgp-sha256`.
+pub fn sign<E, F>(mail: Mail, micalg: &str, sign: F) -> Result<Mail, E>
+where
+    F: FnOnce(&[u8]) -> Result<Vec<u8>, E>,
+{
+    let (mut headers, content) = split(mail);
+
+    // The signed part is serialized the same way when the whole mail is serialized.
+    let signature = sign(&writer::write_mail(&content))?;
+
+    headers.push(multipart_type("signed", vec![
+        (String::from("micalg"), micalg.to_lowercase()),
+        (String::from("protocol"), String::from(SIGNATURE_TYPE)),
+    ]));
+
+    let body = Body::Multi {
+        subtype: String::from("signed"),
+        parts: vec![content, part(SIGNATURE_TYPE, Some("signature.asc"), signature)],
+    };
+
+    Ok(Mail::new(Headers(headers), body))
+}
+
+/// Encrypts a mail, as specified by RFC 3156.
+///
+/// The content of the mail, with the headers that describe it, is serialized and given to the
+/// encryption function, which must return the armored encrypted content. The other headers of the
+/// mail, such as its subject and its recipients, stay in clear.
+pub fn encrypt<E, F>(mail: Mail, encrypt: F) -> Result<Mail, E>
+where
+    F: FnOnce(&[u8]) -> Result<Vec<u8>, E>,
+{
+    let (mut headers, content) = split(mail);
+    let data = encrypt(&writer::write_mail(&content))?;
+
+    headers.push(multipart_type("encrypted", vec![(String::from("protocol"), String::from(ENCRYPTED_TYPE))]));
+
+    let body = Body::Multi {
+        subtype: String::from("encrypted"),
+        parts: vec![
+            part(ENCRYPTED_TYPE, None, b"Version: 1\r\n".to_vec()),
+            part("application/octet-stream", Some("encrypted.asc"), data),
+        ],
+    };
+
+    Ok(Mail::new(Headers(headers), body))
+}
+
+/// Signs and then encrypts a mail, so that the signature is encrypted as well, as specified by the
+/// section 6.1 of RFC 3156.
+pub fn sign_and_encrypt<E, S, F>(mail: Mail, micalg: &str, sign: S, encrypt: F) -> Result<Mail, E>
+where
+    S: FnOnce(&[u8]) -> Result<Vec<u8>, E>,
+    F: FnOnce(&[u8]) -> Result<Vec<u8>, E>,
+{
+    self::encrypt(self::sign(mail, micalg, sign)?, encrypt)
+}
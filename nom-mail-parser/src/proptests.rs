@@ -0,0 +1,206 @@
+//! Property tests that generate MIME trees, serialize them and check that parsing them gives the
+//! same trees.
+
+use proptest::prelude::*;
+
+use crate::{flowed, parse, Body, Mail, Paragraph};
+use crate::tests::{shape, stream_shape};
+
+/// The content transfer encodings used to serialize the leaves.
+#[derive(Debug, Clone, Copy)]
+enum Encoding {
+    SevenBit,
+    QuotedPrintable,
+    Base64,
+}
+
+/// A MIME tree to serialize.
+#[derive(Debug, Clone)]
+enum Node {
+    Leaf {
+        mime_type: &'static str,
+        encoding: Encoding,
+        content: Vec<u8>,
+    },
+    Multi {
+        subtype: &'static str,
+        parts: Vec<Node>,
+    },
+}
+
+/// Encodes some bytes in quoted printable, with lines of at most 76 chars.
+fn encode_quoted_printable(content: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![];
+    let mut line_length = 0;
+
+    for c in content {
+        let escaped = if c.is_ascii_alphanumeric() {
+            vec![*c]
+        } else {
+            format!("={:02X}", c).into_bytes()
+        };
+
+        if line_length + escaped.len() > 75 {
+            encoded.extend_from_slice(b"=\r\n");
+            line_length = 0;
+        }
+
+        line_length += escaped.len();
+        encoded.extend(escaped);
+    }
+
+    encoded
+}
+
+/// Encodes some bytes in base64, with lines of 76 chars.
+fn encode_base64(content: &[u8]) -> Vec<u8> {
+    base64::encode(content).into_bytes().chunks(76).collect::<Vec<_>>().join(&b"\r\n"[..])
+}
+
+/// Encodes a subject with RFC 2047 encoded words, folded on several lines.
+fn encode_subject(subject: &str) -> String {
+    subject
+        .as_bytes()
+        .chunks(30)
+        .map(|x| format!("=?utf-8?b?{}?=", base64::encode(x)))
+        .collect::<Vec<_>>()
+        .join("\r\n ")
+}
+
+/// Serializes a MIME tree.
+///
+/// The boundaries of the parts are derived from the boundary of their parent, so that they are unique.
+fn serialize(node: &Node, boundary: &str) -> Vec<u8> {
+    match node {
+        Node::Leaf { mime_type, encoding, content } => {
+            let (name, encoded) = match encoding {
+                Encoding::SevenBit => ("7bit", content.clone()),
+                Encoding::QuotedPrintable => ("quoted-printable", encode_quoted_printable(content)),
+                Encoding::Base64 => ("base64", encode_base64(content)),
+            };
+
+            let mut serialized = format!(
+                "Content-Type: {}\r\nContent-Transfer-Encoding: {}\r\n\r\n",
+                mime_type,
+                name,
+            ).into_bytes();
+
+            serialized.extend(encoded);
+            serialized
+        },
+
+        Node::Multi { subtype, parts } => {
+            let mut serialized = format!(
+                "Content-Type: multipart/{}; boundary=\"{}\"\r\n\r\n",
+                subtype,
+                boundary,
+            ).into_bytes();
+
+            for (i, part) in parts.iter().enumerate() {
+                serialized.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+                serialized.extend(serialize(part, &format!("{}.{}", boundary, i)));
+                serialized.extend_from_slice(b"\r\n");
+            }
+
+            serialized.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+            serialized
+        },
+    }
+}
+
+/// Checks that a parsed mail has the same structure and content as a MIME tree.
+fn same_tree(mail: &Mail, node: &Node) -> bool {
+    match (mail.body(), node) {
+        (Body::Content(parsed), Node::Leaf { mime_type, content, .. }) => {
+            mail.headers().content_type().map(|x| x.mime_type()).as_deref() == Some(*mime_type) && parsed == content
+        },
+
+        (Body::Multi { subtype: parsed, parts: parsed_parts }, Node::Multi { subtype, parts }) => {
+            parsed == subtype
+                && parsed_parts.len() == parts.len()
+                && parsed_parts.iter().zip(parts).all(|(x, y)| same_tree(x, y))
+        },
+
+        _ => false,
+    }
+}
+
+/// Generates a leaf, whose 7bit content is made of text lines that can't contain a boundary.
+fn leaf() -> impl Strategy<Value = Node> {
+    prop_oneof![
+        "[a-zA-Z0-9 .,!?]{0,80}(\r\n[a-zA-Z0-9 .,!?]{1,80}){0,5}".prop_map(|content| Node::Leaf {
+            mime_type: "text/plain",
+            encoding: Encoding::SevenBit,
+            content: content.into_bytes(),
+        }),
+        any::<Vec<u8>>().prop_map(|content| Node::Leaf {
+            mime_type: "text/html",
+            encoding: Encoding::QuotedPrintable,
+            content,
+        }),
+        any::<Vec<u8>>().prop_map(|content| Node::Leaf {
+            mime_type: "application/octet-stream",
+            encoding: Encoding::Base64,
+            content,
+        }),
+    ]
+}
+
+/// Generates a MIME tree.
+fn tree() -> impl Strategy<Value = Node> {
+    leaf().prop_recursive(4, 32, 4, |inner| {
+        (prop_oneof![Just("mixed"), Just("alternative"), Just("related")], prop::collection::vec(inner, 1..4))
+            .prop_map(|(subtype, parts)| Node::Multi { subtype, parts })
+    })
+}
+
+proptest! {
+    #[test]
+    fn parse_serialized_trees(subject in "\\PC{0,60}", tree in tree()) {
+        let mut mail = format!("Subject: {}\r\n", encode_subject(&subject)).into_bytes();
+        mail.extend(serialize(&tree, "boundary"));
+
+        let parsed = parse(&mail).map_err(|x| TestCaseError::fail(x.to_string()))?;
+
+        if !subject.is_empty() {
+            prop_assert_eq!(parsed.subject(), Some(&subject));
+        }
+
+        prop_assert!(same_tree(&parsed, &tree));
+    }
+
+    #[test]
+    fn stream_serialized_trees(tree in tree(), chunk_size in 1usize..100) {
+        let mail = serialize(&tree, "boundary");
+        let parsed = parse(&mail).map_err(|x| TestCaseError::fail(x.to_string()))?;
+        prop_assert_eq!(stream_shape(&mail, chunk_size), shape(&parsed));
+    }
+
+    #[test]
+    fn serialize_parsed_trees(subject in "\\PC{0,200}", tree in tree()) {
+        let mut mail = format!("Subject: {}\r\n", encode_subject(&subject)).into_bytes();
+        mail.extend(serialize(&tree, "boundary"));
+
+        let parsed = parse(&mail).map_err(|x| TestCaseError::fail(x.to_string()))?;
+        let serialized = parse(&parsed.to_bytes()).map_err(|x| TestCaseError::fail(x.to_string()))?;
+
+        prop_assert_eq!(serialized.subject(), parsed.subject());
+        prop_assert!(same_tree(&serialized, &tree));
+    }
+
+    #[test]
+    fn encode_flowed_paragraphs(lines in prop::collection::vec((0usize..4, "[a-zA-Z ][a-zA-Z>. ]{0,200}"), 1..8)) {
+        let text = lines
+            .iter()
+            .map(|(depth, text)| if *depth > 0 { format!("{} {}", ">".repeat(*depth), text) } else { text.clone() })
+            .map(|x| x + "\n")
+            .collect::<String>();
+
+        let expected = lines
+            .iter()
+            .map(|(depth, text)| Paragraph { quote_depth: *depth, text: text.trim_end_matches(' ').to_string() })
+            .collect::<Vec<_>>();
+
+        prop_assert_eq!(flowed::decode(&flowed::encode(&text), false), expected);
+    }
+}
@@ -0,0 +1,239 @@
+//! This module contains the parsing of the delivery status notifications (RFC 3464), which are the
+//! reports that the mail servers send back when a mail could not be delivered, or was delayed.
+//!
+//! A notification is a `multipart/report` mail with a `report-type=delivery-status` parameter. Its
+//! first part is a message for humans, and its second part is a `message/delivery-status` part,
+//! made of groups of fields separated by blank lines: the first group describes the report, and
+//! each of the other groups describes what happened for one of the recipients of the mail.
+
+use std::fmt;
+
+use crate::{Header, Headers};
+use crate::parser::parse_headers_lenient;
+
+/// The action that a mail server performed for a recipient of a mail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// The mail could not be delivered, and the server won't try again.
+    Failed,
+
+    /// The mail could not be delivered yet, but the server will try again.
+    Delayed,
+
+    /// The mail was delivered to the recipient.
+    Delivered,
+
+    /// The mail was sent to a server that doesn't send delivery status notifications.
+    Relayed,
+
+    /// The mail was delivered, and forwarded to other recipients, such as the members of a list.
+    Expanded,
+
+    /// Some action that is not defined by RFC 3464, in lowercase.
+    Unknown(String),
+}
+
+impl Action {
+    /// Parses the value of an `Action` field, which is case insensitive.
+    pub fn parse(value: &str) -> Action {
+        let value = value.trim().to_lowercase();
+
+        match value.as_str() {
+            "failed" => Action::Failed,
+            "delayed" => Action::Delayed,
+            "delivered" => Action::Delivered,
+            "relayed" => Action::Relayed,
+            "expanded" => Action::Expanded,
+            _ => Action::Unknown(value),
+        }
+    }
+
+    /// Returns the name of the action, as it appears in the notifications, e.g. `failed`.
+    pub fn name(&self) -> &str {
+        match self {
+            Action::Failed => "failed",
+            Action::Delayed => "delayed",
+            Action::Delivered => "delivered",
+            Action::Relayed => "relayed",
+            Action::Expanded => "expanded",
+            Action::Unknown(name) => name,
+        }
+    }
+}
+
+/// An enhanced status code (RFC 3463), such as `5.1.1` when the mailbox of the recipient doesn't
+/// exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatusCode {
+    /// The class of the status, which is 2 for a success, 4 for a transient failure, and 5 for a
+    /// permanent failure.
+    pub class: u8,
+
+    /// The subject of the status, e.g. 1 for the problems with the address of the recipient.
+    pub subject: u16,
+
+    /// The detail of the status, which is specific to its subject.
+    pub detail: u16,
+}
+
+impl StatusCode {
+    /// Parses a status code, which may be followed by a comment, e.g. `5.1.1 (bad mailbox)`.
+    pub fn parse(value: &str) -> Option<StatusCode> {
+        let code = value.split_whitespace().next()?;
+        let mut numbers = code.split('.');
+
+        let class = numbers.next()?.parse().ok().filter(|x| [2, 4, 5].contains(x))?;
+        let subject = numbers.next()?.parse().ok()?;
+        let detail = numbers.next()?.parse().ok()?;
+
+        if numbers.next().is_some() {
+            return None;
+        }
+
+        Some(StatusCode { class, subject, detail })
+    }
+
+    /// Checks if the status is a success.
+    pub fn is_success(&self) -> bool {
+        self.class == 2
+    }
+
+    /// Checks if the status is a failure that may be fixed by trying again later.
+    pub fn is_transient(&self) -> bool {
+        self.class == 4
+    }
+
+    /// Checks if the status is a failure that won't be fixed by trying again, such as an unknown
+    /// recipient.
+    pub fn is_permanent(&self) -> bool {
+        self.class == 5
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.class, self.subject, self.detail)
+    }
+}
+
+/// What happened for one of the recipients of a mail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecipientStatus {
+    /// The address of the recipient to which the server tried to deliver the mail, without its
+    /// type, e.g. `someone@example.com`.
+    pub final_recipient: String,
+
+    /// The address of the recipient as it was given by the sender of the mail, if it is known and
+    /// if the recipient was rewritten, e.g. by an alias.
+    pub original_recipient: Option<String>,
+
+    /// The action that the server performed for the recipient.
+    pub action: Action,
+
+    /// The status of the delivery, if it is valid.
+    pub status: Option<StatusCode>,
+
+    /// The message of the server that rejected the mail, without its type, e.g. `550 5.1.1 User
+    /// unknown`.
+    pub diagnostic: Option<String>,
+
+    /// The name of the server that rejected the mail, without its type, if any.
+    pub remote_mta: Option<String>,
+}
+
+impl RecipientStatus {
+    /// Checks if the mail will never reach this recipient.
+    pub fn is_failed(&self) -> bool {
+        self.action == Action::Failed || self.status.map(|x| x.is_permanent()).unwrap_or(false)
+    }
+}
+
+/// A delivery status notification, with the status of each recipient of the mail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeliveryReport {
+    /// The name of the server that wrote the notification, without its type, if any.
+    pub reporting_mta: Option<String>,
+
+    /// The status of the recipients of the mail, in the order in which they appear.
+    pub recipients: Vec<RecipientStatus>,
+}
+
+impl DeliveryReport {
+    /// Returns the recipients that the mail will never reach.
+    pub fn failed(&self) -> impl Iterator<Item = &RecipientStatus> {
+        self.recipients.iter().filter(|x| x.is_failed())
+    }
+}
+
+/// Removes the type that precedes the value of some fields, such as `rfc822;` before an address or
+/// `smtp;` before a diagnostic.
+fn strip_type(value: &str) -> &str {
+    match value.split_once(';') {
+        Some((_, value)) => value.trim(),
+        None => value.trim(),
+    }
+}
+
+/// Returns the value of a field of a group, without its type.
+fn field<'a>(group: &'a Headers, name: &str) -> Option<&'a str> {
+    match group.get(name) {
+        Some(Header::Unknown { value, .. }) => Some(strip_type(value)).filter(|x| !x.is_empty()),
+        _ => None,
+    }
+}
+
+/// Splits the content of a delivery status part into its groups of fields.
+fn groups(content: &[u8]) -> Vec<Headers> {
+    let mut groups = vec![];
+    let mut group = vec![];
+
+    for line in content.split(|c| *c == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        if line.iter().all(|c| *c == b' ' || *c == b'\t') {
+            if !group.is_empty() {
+                groups.push(parse_headers_lenient(&group));
+                group.clear();
+            }
+        } else {
+            group.extend_from_slice(line);
+            group.extend_from_slice(b"\r\n");
+        }
+    }
+
+    if !group.is_empty() {
+        groups.push(parse_headers_lenient(&group));
+    }
+
+    groups
+}
+
+/// Parses the content of a `message/delivery-status` part.
+///
+/// The groups of fields that have no final recipient or no action are ignored, since they can't be
+/// attributed to a recipient.
+pub fn parse_delivery_status(content: &[u8]) -> DeliveryReport {
+    let groups = groups(content);
+
+    // The fields of the report should be in the first group, but some servers omit this group.
+    let reporting_mta = groups
+        .iter()
+        .find_map(|group| field(group, "Reporting-MTA"))
+        .map(String::from);
+
+    let recipients = groups
+        .iter()
+        .filter_map(|group| {
+            Some(RecipientStatus {
+                final_recipient: field(group, "Final-Recipient")?.to_string(),
+                original_recipient: field(group, "Original-Recipient").map(String::from),
+                action: Action::parse(field(group, "Action")?),
+                status: field(group, "Status").and_then(StatusCode::parse),
+                diagnostic: field(group, "Diagnostic-Code").map(String::from),
+                remote_mta: field(group, "Remote-MTA").map(String::from),
+            })
+        })
+        .collect();
+
+    DeliveryReport { reporting_mta, recipients }
+}
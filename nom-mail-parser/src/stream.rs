@@ -0,0 +1,408 @@
+//! This module contains an incremental parser, that parses mails chunk by chunk as they arrive and
+//! emits events, so that large mails never need to be held in memory.
+
+use crate::{ContentType, ContentTransferEncoding, Header, Headers};
+use crate::parser::{is_encoded, parse_headers_lenient};
+use crate::transfer_encoding::{decode_base64, decode_quoted_printable};
+
+/// The events emitted by the incremental parser.
+///
+/// The parts are nested: each `PartStart` is followed by the headers of the part, by a
+/// `HeadersEnd`, then either by the body of the part or by its parts, and finally by a `PartEnd`.
+/// The mail itself is the first part, and the embedded mails, such as the forwarded mails, are the
+/// only part of the part that contains them.
+#[derive(Debug)]
+pub enum Event {
+    /// A part starts.
+    PartStart,
+
+    /// A header of the current part.
+    Header(Header),
+
+    /// The headers of the current part are over.
+    HeadersEnd,
+
+    /// A chunk of the body of the current part, decoded according to its content transfer
+    /// encoding.
+    BodyChunk(Vec<u8>),
+
+    /// The current part is over.
+    PartEnd,
+}
+
+/// A decoder for the content transfer encodings, that decodes the content chunk by chunk.
+enum Decoder {
+    /// The content is not encoded.
+    Identity,
+
+    /// The content is quoted printable, and the last line that is not complete yet is kept.
+    QuotedPrintable(Vec<u8>),
+
+    /// The content is base64, and the chars that don't make a complete group yet are kept, along
+    /// with whether the padding has been reached.
+    Base64(Vec<u8>, bool),
+}
+
+impl Decoder {
+    /// Creates the decoder for a content transfer encoding.
+    fn new(encoding: Option<&ContentTransferEncoding>) -> Decoder {
+        match encoding {
+            Some(ContentTransferEncoding::QuotedPrintable) => Decoder::QuotedPrintable(vec![]),
+            Some(ContentTransferEncoding::Base64) => Decoder::Base64(vec![], false),
+            _ => Decoder::Identity,
+        }
+    }
+
+    /// Decodes a chunk of content, keeping what can't be decoded yet.
+    fn feed(&mut self, chunk: &[u8]) -> Vec<u8> {
+        match self {
+            Decoder::Identity => chunk.to_vec(),
+
+            Decoder::QuotedPrintable(pending) => {
+                pending.extend_from_slice(chunk);
+
+                // Only the complete lines can be decoded, because of the soft line breaks.
+                match pending.iter().rposition(|c| *c == b'\n') {
+                    Some(end) => {
+                        let rest = pending.split_off(end + 1);
+                        let decoded = decode_quoted_printable(pending);
+                        *pending = rest;
+                        decoded
+                    },
+                    None => vec![],
+                }
+            },
+
+            Decoder::Base64(pending, done) => {
+                for c in chunk {
+                    if *done || *c == b'=' {
+                        *done = true;
+                    } else if c.is_ascii_alphanumeric() || *c == b'+' || *c == b'/' {
+                        pending.push(*c);
+                    }
+                }
+
+                let end = pending.len() / 4 * 4;
+                let rest = pending.split_off(end);
+                let decoded = decode_base64(pending);
+                *pending = rest;
+                decoded
+            },
+        }
+    }
+
+    /// Decodes the end of the content.
+    fn finish(&mut self) -> Vec<u8> {
+        match self {
+            Decoder::Identity => vec![],
+            Decoder::QuotedPrintable(pending) => decode_quoted_printable(&std::mem::take(pending)),
+            Decoder::Base64(pending, _) => decode_base64(&std::mem::take(pending)),
+        }
+    }
+}
+
+/// What the parser is currently reading.
+enum State {
+    /// The headers of a part, whose raw lines are kept until they are complete.
+    Headers(Vec<u8>),
+
+    /// The body of a part that is not multipart.
+    Content {
+        /// The decoder of the content.
+        decoder: Decoder,
+
+        /// The line break of the last line, which is kept since the line break that precedes a
+        /// boundary belongs to the boundary.
+        pending_line_break: Option<&'static [u8]>,
+    },
+
+    /// The preamble or the epilogue of a multipart part, which are ignored.
+    Ignored,
+}
+
+/// A multipart part that is being read, or an embedded mail, which ends with the part that contains
+/// it and has no delimiter.
+struct Multipart {
+    /// The delimiter of the parts, which is the boundary preceded by two dashes.
+    delimiter: Vec<u8>,
+
+    /// Whether the closing delimiter has been read, after which the delimiter is ignored.
+    closed: bool,
+}
+
+/// An incremental parser, that receives the chunks of a mail and emits events.
+///
+/// The mails are read line by line, and the only things that are kept in memory are the headers
+/// of the current part and the current line. Long lines that can't be a boundary are emitted
+/// without waiting for their end. The lines may end with CRLF or with a single LF.
+pub struct StreamParser {
+    /// What the parser is currently reading.
+    state: State,
+
+    /// The multipart parts that contain the current position, from the outermost.
+    multiparts: Vec<Multipart>,
+
+    /// The beginning of the current line, that is not complete yet.
+    line: Vec<u8>,
+
+    /// Whether the beginning of the current line was already emitted as content.
+    in_content_line: bool,
+
+    /// The events that have not been returned yet.
+    events: Vec<Event>,
+}
+
+impl Default for StreamParser {
+    fn default() -> StreamParser {
+        StreamParser::new()
+    }
+}
+
+impl StreamParser {
+    /// Creates a new parser, ready to receive the beginning of a mail.
+    pub fn new() -> StreamParser {
+        StreamParser {
+            state: State::Headers(vec![]),
+            multiparts: vec![],
+            line: vec![],
+            in_content_line: false,
+            events: vec![Event::PartStart],
+        }
+    }
+
+    /// Gives a chunk of the mail to the parser, and returns the events that it triggered.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<Event> {
+        self.line.extend_from_slice(chunk);
+
+        let mut start = 0;
+        while let Some(end) = self.line[start..].iter().position(|c| *c == b'\n') {
+            let line = self.line[start .. start + end].to_vec();
+
+            match line.strip_suffix(b"\r") {
+                Some(line) => self.read_line(line, b"\r\n"),
+                None => self.read_line(&line, b"\n"),
+            }
+
+            start += end + 1;
+        }
+
+        self.line.drain(..start);
+
+        // Emit the beginning of the current line if it is content and can't be a boundary.
+        let is_content = match self.state {
+            State::Content { .. } => self.in_content_line || !self.may_be_delimiter(&self.line),
+            _ => false,
+        };
+
+        // The last char may be the beginning of a line break.
+        let end = if self.line.ends_with(b"\r") { self.line.len() - 1 } else { self.line.len() };
+
+        if is_content && end > 0 {
+            let content = self.line.drain(..end).collect::<Vec<_>>();
+            self.content(&content);
+            self.in_content_line = true;
+        }
+
+        std::mem::take(&mut self.events)
+    }
+
+    /// Signals the end of the mail to the parser, and returns the last events.
+    pub fn finish(mut self) -> Vec<Event> {
+        let line = std::mem::take(&mut self.line);
+        let is_delimiter = !self.in_content_line && self.delimiter(&line).is_some();
+
+        match self.state {
+            // The last line of the content has no line break.
+            State::Content { .. } if !is_delimiter => self.content(&line),
+            _ if !line.is_empty() => self.read_line(&line, b""),
+            _ => (),
+        }
+
+        // The line break at the end of the mail belongs to the content.
+        if let State::Content { pending_line_break: Some(_), .. } = self.state {
+            self.content(&[]);
+        }
+
+        self.end_part();
+
+        while self.multiparts.pop().is_some() {
+            self.events.push(Event::PartEnd);
+        }
+
+        self.events
+    }
+
+    /// Checks if the beginning of a line may be the beginning of a delimiter.
+    fn may_be_delimiter(&self, line: &[u8]) -> bool {
+        self.multiparts
+            .iter()
+            .any(|x| !x.closed && (line.starts_with(&x.delimiter) || x.delimiter.starts_with(line)))
+    }
+
+    /// Checks if a line is a delimiter of one of the multipart parts, and returns the index of the
+    /// part with whether it is a closing delimiter.
+    fn delimiter(&self, line: &[u8]) -> Option<(usize, bool)> {
+        self.multiparts.iter().enumerate().rev().find_map(|(index, multipart)| {
+            if multipart.closed || !line.starts_with(&multipart.delimiter) {
+                return None;
+            }
+
+            let rest = &line[multipart.delimiter.len()..];
+            let closing = rest.starts_with(b"--");
+            let rest = if closing { &rest[2..] } else { rest };
+
+            // The boundary may be followed by some white spaces, but nothing else.
+            if rest.iter().all(|c| *c == b' ' || *c == b'\t') {
+                Some((index, closing))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Reads a complete line, without its line break.
+    fn read_line(&mut self, line: &[u8], line_break: &'static [u8]) {
+        let delimiter = if self.in_content_line { None } else { self.delimiter(line) };
+
+        if let Some((index, closing)) = delimiter {
+            self.end_part();
+
+            while self.multiparts.len() > index + 1 {
+                self.multiparts.pop();
+                self.events.push(Event::PartEnd);
+            }
+
+            if closing {
+                self.multiparts[index].closed = true;
+                self.state = State::Ignored;
+            } else {
+                self.events.push(Event::PartStart);
+                self.state = State::Headers(vec![]);
+            }
+
+            return;
+        }
+
+        match &mut self.state {
+            State::Headers(raw) if line.is_empty() => {
+                let raw = std::mem::take(raw);
+                self.end_headers(&raw);
+            },
+
+            State::Headers(raw) => {
+                raw.extend_from_slice(line);
+                raw.extend_from_slice(b"\r\n");
+            },
+
+            State::Content { .. } => {
+                self.content(line);
+                self.in_content_line = false;
+
+                if let State::Content { pending_line_break, .. } = &mut self.state {
+                    *pending_line_break = Some(line_break);
+                }
+            },
+
+            State::Ignored => (),
+        }
+    }
+
+    /// Emits some content of the current part.
+    fn content(&mut self, content: &[u8]) {
+        if let State::Content { decoder, pending_line_break } = &mut self.state {
+            let mut decoded = vec![];
+
+            if let Some(line_break) = pending_line_break.take() {
+                decoded = decoder.feed(line_break);
+            }
+
+            decoded.extend(decoder.feed(content));
+
+            if !decoded.is_empty() {
+                self.events.push(Event::BodyChunk(decoded));
+            }
+        }
+    }
+
+    /// Parses the headers of the current part, and prepares to read its body.
+    fn end_headers(&mut self, raw: &[u8]) {
+        let Headers(headers) = parse_headers_lenient(raw);
+
+        let content_type = headers.iter().find_map(|header| match header {
+            Header::ContentType(content_type) => Some(content_type),
+            _ => None,
+        });
+
+        let delimiter = content_type
+            .filter(|x| x.is_multipart())
+            .and_then(ContentType::boundary)
+            .map(|boundary| [&b"--"[..], boundary.as_bytes()].concat());
+
+        let encoding = headers.iter().find_map(|header| match header {
+            Header::ContentTransferEncoding(encoding) => Some(encoding),
+            _ => None,
+        });
+
+        // The embedded mails that are encoded are emitted as content, since they have to be
+        // decoded before being parsed.
+        let is_message = content_type.map(ContentType::is_message).unwrap_or(false) && !is_encoded(encoding.cloned());
+
+        self.state = match delimiter {
+            Some(delimiter) => {
+                self.multiparts.push(Multipart { delimiter, closed: false });
+                State::Ignored
+            },
+
+            None if is_message => {
+                self.multiparts.push(Multipart { delimiter: vec![], closed: true });
+                State::Headers(vec![])
+            },
+
+            None => State::Content {
+                decoder: Decoder::new(encoding),
+                pending_line_break: None,
+            },
+        };
+
+        self.events.extend(headers.into_iter().map(Event::Header));
+        self.events.push(Event::HeadersEnd);
+
+        if is_message {
+            self.events.push(Event::PartStart);
+        }
+    }
+
+    /// Ends the current part if it is not multipart.
+    fn end_part(&mut self) {
+        match std::mem::replace(&mut self.state, State::Ignored) {
+            // The headers end with the part, so the body is empty.
+            State::Headers(raw) => {
+                self.end_headers(&raw);
+
+                match self.state {
+                    State::Content { .. } => {
+                        self.state = State::Ignored;
+                        self.events.push(Event::PartEnd);
+                    },
+
+                    // The part was an embedded mail, whose headers end with it as well.
+                    State::Headers(_) => self.end_part(),
+
+                    State::Ignored => (),
+                }
+            },
+
+            State::Content { mut decoder, .. } => {
+                let decoded = decoder.finish();
+
+                if !decoded.is_empty() {
+                    self.events.push(Event::BodyChunk(decoded));
+                }
+
+                self.events.push(Event::PartEnd);
+            },
+
+            State::Ignored => (),
+        }
+    }
+}
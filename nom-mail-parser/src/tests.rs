@@ -1,14 +1,16 @@
-use crate::{parse, Result};
+use crate::{parse, parse_lenient, parse_headers, parse_headers_lenient, thread, Result, ParseError, ErrorKind};
+use crate::{borrowed, flowed, Address, Recipient, Body, Date, MessageId, Thread, Mail, MailBuilder, Header, ContentType, ListId, Paragraph, html, report, calendar, pgp};
+use crate::stream::{StreamParser, Event};
 
 #[test]
-fn parse_mail_1() -> Result<'static, ()> {
+fn parse_mail_1() -> Result<()> {
     let mail = parse(include_bytes!("../mails/simple.txt"))?;
     assert_eq!(mail.subject(), Some(&String::from("This is a test email")));
     Ok(())
 }
 
 #[test]
-fn parse_mail_2() -> Result<'static, ()> {
+fn parse_mail_2() -> Result<()> {
     let mail = parse(include_bytes!("../mails/big.txt"))?;
 
     assert_eq!(
@@ -19,7 +21,7 @@ fn parse_mail_2() -> Result<'static, ()> {
 }
 
 #[test]
-fn parse_mail_3() -> Result<'static, ()> {
+fn parse_mail_3() -> Result<()> {
     let mail = parse(include_bytes!("../mails/mail_validation.txt"))?;
 
     assert_eq!(
@@ -28,3 +30,1610 @@ fn parse_mail_3() -> Result<'static, ()> {
 
     Ok(())
 }
+
+#[test]
+fn parse_from() -> Result<()> {
+    let mail = parse(include_bytes!("../mails/simple.txt"))?;
+
+    assert_eq!(mail.headers().from(), Some(&[Address {
+        display_name: Some(String::from("Someone Something")),
+        local_part: String::from("someone"),
+        domain: String::from("something.com"),
+    }][..]));
+
+    Ok(())
+}
+
+#[test]
+fn parse_address_lists() -> Result<()> {
+    let headers = parse_headers(b"To: \"Doe, John\" <john@example.com>, jane@example.com (Jane)\r\n\
+        Cc: Friends: Alice <alice@example.com>, bob@example.com;, , undisclosed-recipients:;\r\n\
+        Reply-To: <\"john doe\"@example.com>\r\n\
+        Bcc: \r\n\
+        Sender: Mailing List <list@[127.0.0.1]>\r\n\
+        \r\n")?;
+
+    let to = headers.to().unwrap();
+    assert_eq!(to.len(), 2);
+    assert_eq!(to[0].addresses()[0].to_string(), "\"Doe, John\" <john@example.com>");
+    assert_eq!(to[1].addresses()[0].to_string(), "jane@example.com");
+
+    let cc = headers.cc().unwrap();
+    assert_eq!(cc.len(), 2);
+    match &cc[0] {
+        Recipient::Group(group) => {
+            assert_eq!(group.name, "Friends");
+            assert_eq!(group.addresses[0].display_name, Some(String::from("Alice")));
+            assert_eq!(group.addresses[1].address(), "bob@example.com");
+        },
+        _ => panic!("expected a group"),
+    }
+    assert!(cc[1].addresses().is_empty());
+
+    let reply_to = headers.reply_to().unwrap();
+    assert_eq!(reply_to[0].addresses()[0].local_part, "john doe");
+    assert_eq!(reply_to[0].addresses()[0].address(), "\"john doe\"@example.com");
+
+    assert_eq!(headers.sender().unwrap().domain, "[127.0.0.1]");
+    assert_eq!(headers.bcc(), Some(&[][..]));
+
+    // The addresses can't contain line breaks, even quoted.
+    assert_eq!(Address::parse("\"a\r\nX-Injected: 1\"@example.com"), None);
+    assert_eq!(Address::parse("a@[127.0.0.1\r\nX-Injected: 1]"), None);
+
+    Ok(())
+}
+
+#[test]
+fn parse_encoded_words() -> Result<()> {
+    let subject = |header: &'static [u8]| parse_headers(header).map(|h| h.subject().cloned());
+
+    assert_eq!(subject(b"Subject: =?iso-8859-1?Q?caf=E9_cr=E8me?=\r\n\r\n")?, Some(String::from("café crème")));
+    assert_eq!(subject(b"Subject: =?windows-1252?B?gHVybw==?=\r\n\r\n")?, Some(String::from("€uro")));
+    assert_eq!(subject(b"Subject: =?utf-8?b?dGjDqQ?=\r\n\r\n")?, Some(String::from("thé")));
+
+    // Plain text and encoded words can be mixed.
+    assert_eq!(
+        subject(b"Subject: Re: =?utf-8?q?Caf=C3=A9?= and =?UTF-8?B?dGjDqQ==?= time\r\n\r\n")?,
+        Some(String::from("Re: Café and thé time")));
+
+    // White spaces between adjacent encoded words are ignored, and a character can be split
+    // across several encoded words.
+    assert_eq!(
+        subject(b"Subject: =?utf-8?q?a?= =?utf-8?q?b_?=\r\n =?utf-8?Q?=C3?= =?utf-8?Q?=A9?=\r\n\r\n")?,
+        Some(String::from("ab é")));
+
+    // Invalid encoded words are kept as is.
+    assert_eq!(subject(b"Subject: =?utf-8?B?@@@?= =?utf-8?X?abc?=\r\n\r\n")?, Some(String::from("=?utf-8?B?@@@?= =?utf-8?X?abc?=")));
+
+    let headers = parse_headers(b"From: =?utf-8?q?Doe,_John?= <john@example.com>\r\n\r\n")?;
+    assert_eq!(headers.from().unwrap()[0].display_name, Some(String::from("Doe, John")));
+
+    Ok(())
+}
+
+#[test]
+fn decode_bodies() -> Result<()> {
+    let mail = parse(include_bytes!("../mails/simple.txt"))?;
+
+    let parts = match mail.body() {
+        Body::Multi { parts, .. } => parts,
+        _ => panic!("expected a multipart mail"),
+    };
+
+    assert_eq!(parts[0].text(), Some(String::from("This is the plaintext version, in utf-8. Proof by Euro: €")));
+    assert_eq!(parts[1].text(), Some(String::from(
+        "<html><body>This is the <b>HTML</b> version, in us-ascii. Proof by Euro: &euro;</body></html>\n")));
+
+    Ok(())
+}
+
+#[test]
+fn decode_quoted_printable_and_base64() -> Result<()> {
+    let mail = parse(b"Content-Type: multipart/alternative; boundary=\"b\"\r\n\
+        \r\n\
+        --b\r\n\
+        Content-Type: text/plain; charset=\"iso-8859-1\"\r\n\
+        Content-Transfer-Encoding: Quoted-Printable\r\n\
+        \r\n\
+        Voil=E0 une ligne tr=E8s longue qui est coup=E9e =\r\n\
+        en deux.  \r\n\
+        1 + 1 =3D 2 =\r\n\
+        \r\n\
+        --b\r\n\
+        Content-Type: text/html; charset=utf-8\r\n\
+        Content-Transfer-Encoding: base64\r\n\
+        \r\n\
+        PHA+Q2Fmw6k8\r\n\
+        L3A+\r\n\
+        --b--\r\n")?;
+
+    let parts = match mail.body() {
+        Body::Multi { parts, .. } => parts,
+        _ => panic!("expected a multipart mail"),
+    };
+
+    assert_eq!(parts[0].text(), Some(String::from("Voilà une ligne très longue qui est coupée en deux.\r\n1 + 1 = 2 ")));
+
+    match parts[1].body() {
+        Body::Content(content) => assert_eq!(content, b"<p>Caf\xc3\xa9</p>"),
+        _ => panic!("expected a single part"),
+    }
+
+    assert_eq!(parts[1].text(), Some(String::from("<p>Café</p>")));
+
+    Ok(())
+}
+
+#[test]
+fn parse_nested_multiparts() -> Result<()> {
+    let mail = parse(include_bytes!("../mails/nested.txt"))?;
+
+    let content_type = mail.headers().content_type().unwrap();
+    assert_eq!(content_type.mime_type(), "multipart/mixed");
+    assert_eq!(content_type.boundary(), Some("outer-boundary"));
+
+    let parts = match mail.body() {
+        Body::Multi { subtype, parts } if subtype == "mixed" => parts,
+        _ => panic!("expected a multipart mixed mail"),
+    };
+    assert_eq!(parts.len(), 2);
+
+    let alternatives = match parts[0].body() {
+        Body::Multi { subtype, parts } if subtype == "alternative" => parts,
+        _ => panic!("expected a multipart alternative part"),
+    };
+    assert_eq!(alternatives.len(), 2);
+    assert_eq!(alternatives[0].text(), Some(String::from("Here are the pictures from the holidays.")));
+
+    let related = match alternatives[1].body() {
+        Body::Multi { subtype, parts } if subtype == "related" => parts,
+        _ => panic!("expected a multipart related part"),
+    };
+    assert_eq!(alternatives[1].headers().content_type().unwrap().parameter("type"), Some("text/html"));
+    assert_eq!(related.len(), 2);
+    assert_eq!(
+        related[0].text(),
+        Some(String::from("<p>Here are the pictures from the holidays.</p><img src=\"cid:logo@example.com\">")));
+    assert_eq!(related[1].headers().content_type().unwrap().mime_type(), "image/png");
+    assert_eq!(related[1].text(), None);
+
+    match related[1].body() {
+        Body::Content(content) => assert_eq!(content, b"\x89PNG\r\n\x1a\n"),
+        _ => panic!("expected a single part"),
+    }
+
+    let pdf = parts[1].headers().content_type().unwrap();
+    assert_eq!(pdf.mime_type(), "application/pdf");
+    assert_eq!(pdf.parameter("name"), Some("pictures.pdf"));
+
+    Ok(())
+}
+
+#[test]
+fn parse_attachments() -> Result<()> {
+    let mail = parse(include_bytes!("../mails/nested.txt"))?;
+    let attachments = mail.attachments().collect::<Vec<_>>();
+
+    assert_eq!(attachments.len(), 1);
+    assert_eq!(attachments[0].filename, Some(String::from("pictures.pdf")));
+    assert_eq!(attachments[0].mime_type, "application/pdf");
+    assert_eq!(attachments[0].size, 9);
+    assert_eq!(attachments[0].content, b"%PDF-1.4\n");
+
+    let mail = parse(b"Content-Type: multipart/mixed; boundary=b\r\n\
+        \r\n\
+        --b\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        See attached.\r\n\
+        --b\r\n\
+        Content-Type: application/octet-stream\r\n\
+        Content-Disposition: attachment;\r\n \
+            filename*0*=utf-8''%C3%A9t%C3%A9;\r\n \
+            filename*1=\" 2019.pdf\"\r\n\
+        \r\n\
+        content\r\n\
+        --b\r\n\
+        Content-Type: text/plain; name*=iso-8859-1'fr'caf%E9.txt\r\n\
+        \r\n\
+        coffee\r\n\
+        --b\r\n\
+        Content-Type: image/png; name=\"=?utf-8?q?th=C3=A9.png?=\"\r\n\
+        Content-Disposition: inline\r\n\
+        Content-Transfer-Encoding: base64\r\n\
+        \r\n\
+        iVBORw0KGgo=\r\n\
+        --b--\r\n")?;
+
+    let attachments = mail.attachments().collect::<Vec<_>>();
+    let filenames = attachments.iter().map(|x| x.filename.clone().unwrap()).collect::<Vec<_>>();
+    assert_eq!(filenames, vec!["été 2019.pdf", "café.txt", "thé.png"]);
+    assert_eq!(attachments[0].content, b"content");
+    assert_eq!(attachments[1].mime_type, "text/plain");
+    assert_eq!(attachments[2].size, 8);
+
+    Ok(())
+}
+
+#[test]
+fn convert_charsets() {
+    let mut mail = b"Content-Type: multipart/mixed; boundary=b\r\n\r\n".to_vec();
+
+    let parts: &[(&str, &[u8])] = &[
+        ("text/plain; charset=iso-8859-1", b"caf\xe9"),
+        ("text/plain; charset=\"Windows-1252\"", b"\x80 co\xfbte"),
+        ("text/plain; charset=latin-2", b"\xbe\xe1k"),
+        ("text/plain; charset=Shift_JIS", b"\x93\xfa\x96\x7b"),
+        ("text/plain; charset=gb2312", b"\xd6\xd0\xce\xc4"),
+        ("text/html; charset=utf-8", b"invalid \xff utf-8"),
+        ("text/plain; charset=us-ascii", b"caf\xc3\xa9"),
+        ("text/plain; charset=x-unknown", b"caf\xe9"),
+        ("text/plain", b"\xef\xbb\xbfcaf\xc3\xa9"),
+    ];
+
+    for (content_type, content) in parts {
+        mail.extend_from_slice(b"--b\r\nContent-Type: ");
+        mail.extend_from_slice(content_type.as_bytes());
+        mail.extend_from_slice(b"\r\nContent-Transfer-Encoding: 8bit\r\n\r\n");
+        mail.extend_from_slice(content);
+        mail.extend_from_slice(b"\r\n");
+    }
+
+    mail.extend_from_slice(b"--b--\r\n");
+
+    let mail = parse(&mail).expect("failed to parse mail");
+    let parts = match mail.body() {
+        Body::Multi { parts, .. } => parts,
+        _ => panic!("expected a multipart mail"),
+    };
+
+    let decoded = parts.iter().map(|x| (x.charset().unwrap(), x.text().unwrap())).collect::<Vec<_>>();
+
+    assert_eq!(decoded, vec![
+        ("windows-1252", String::from("café")),
+        ("windows-1252", String::from("€ coûte")),
+        ("ISO-8859-2", String::from("žák")),
+        ("Shift_JIS", String::from("日本")),
+        ("GBK", String::from("中文")),
+        ("UTF-8", String::from("invalid \u{FFFD} utf-8")),
+        ("UTF-8", String::from("café")),
+        ("windows-1252", String::from("café")),
+        ("UTF-8", String::from("café")),
+    ]);
+
+    assert_eq!(mail.charset(), None);
+}
+
+#[test]
+fn parse_dates() -> Result<()> {
+    let mail = parse(include_bytes!("../mails/simple.txt"))?;
+    let date = mail.headers().date().and_then(Date::date_time).expect("failed to parse date");
+    assert_eq!(date.to_rfc3339(), "2016-10-02T07:06:22-07:00");
+
+    let dates = [
+        "Date: Tue, 22 Jan 2019 01:43:14 +0000 (UTC)",
+        "Date: Fri,  8 Feb 2019 10:18:38 +0100 (CET)",
+        "Date: 2 Oct 16 07:06 EST",
+        "Date: Thursday, 01 Jan 70 00:00:00 GMT",
+        "Date: Mon, 3 Jan 1994 12:30:00 Z",
+        "Date: 3 Jan 094 12:30:00 pdt",
+        "Date: Sat, 31 Dec 2016 23:59:60 +0000",
+        "Date: 2019-02-11T10:12:00+01:00",
+        "Date: yesterday",
+        "Date: Wed, 31 Feb 2019 10:00:00 +0000",
+    ];
+
+    let parsed = dates
+        .iter()
+        .map(|x| {
+            let input = format!("{}\r\n\r\n", x);
+            match parse_headers(input.as_bytes()).map(|x| x.date().cloned()) {
+                Ok(Some(Date::Valid(date))) => date.to_rfc3339(),
+                Ok(Some(Date::Invalid(raw))) => raw,
+                _ => panic!("failed to parse headers"),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(parsed, vec![
+        "2019-01-22T01:43:14+00:00",
+        "2019-02-08T10:18:38+01:00",
+        "2016-10-02T07:06:00-05:00",
+        "1970-01-01T00:00:00+00:00",
+        "1994-01-03T12:30:00+00:00",
+        "1994-01-03T12:30:00-07:00",
+        "2016-12-31T23:59:59+00:00",
+        "2019-02-11T10:12:00+01:00",
+        "yesterday",
+        "Wed, 31 Feb 2019 10:00:00 +0000",
+    ]);
+
+    Ok(())
+}
+
+#[test]
+fn parse_message_ids() -> Result<()> {
+    let headers = parse_headers(
+        b"Message-ID: <1234.5678@example.com>\r\n\
+          In-Reply-To: John's message <abcd@example.com> (sent yesterday)\r\n\
+          References: <a@example.com>\r\n <b@example.com> <abcd@example.com>\r\n\r\n")?;
+
+    assert_eq!(headers.message_id(), Some(&MessageId(String::from("1234.5678@example.com"))));
+    assert_eq!(headers.in_reply_to(), Some(&[MessageId(String::from("abcd@example.com"))][..]));
+    assert_eq!(
+        headers.references().map(|x| x.iter().map(ToString::to_string).collect::<Vec<_>>()),
+        Some(vec![
+            String::from("<a@example.com>"),
+            String::from("<b@example.com>"),
+            String::from("<abcd@example.com>"),
+        ]));
+
+    Ok(())
+}
+
+#[test]
+fn thread_mails() {
+    let mails = [
+        // 0: the start of a conversation.
+        "Message-ID: <1@x>\r\nSubject: Lunch\r\nDate: Mon, 4 Feb 2019 10:00:00 +0000\r\n\r\n",
+        // 1: a reply to a mail that we don't have, which replies to 0, so it is moved under 0.
+        "Message-ID: <3@x>\r\nReferences: <1@x> <2@x>\r\nSubject: Re: Lunch\r\nDate: Mon, 4 Feb 2019 12:00:00 +0000\r\n\r\n",
+        // 2: a direct reply to 0 that only has an in reply to header.
+        "Message-ID: <4@x>\r\nIn-Reply-To: <1@x>\r\nSubject: Re: Lunch\r\nDate: Mon, 4 Feb 2019 10:30:00 +0000\r\n\r\n",
+        // 3: an unrelated mail, that was sent earlier.
+        "Message-ID: <5@x>\r\nSubject: Meeting\r\nDate: Sun, 3 Feb 2019 09:00:00 +0000\r\n\r\n",
+        // 4: a reply to 3 whose references were lost, which is grouped by subject.
+        "Message-ID: <6@x>\r\nSubject: RE: [team] Meeting\r\nDate: Sun, 3 Feb 2019 09:30:00 +0000\r\n\r\n",
+        // 5: two mails with the same subject, that are not replies to each other.
+        "Message-ID: <7@x>\r\nSubject: Report\r\nDate: Tue, 5 Feb 2019 09:00:00 +0000\r\n\r\n",
+        "Message-ID: <8@x>\r\nSubject: Report\r\nDate: Tue, 5 Feb 2019 10:00:00 +0000\r\n\r\n",
+        // 7: a mail that references itself and a missing mail.
+        "Message-ID: <9@x>\r\nReferences: <10@x> <9@x>\r\nSubject: Loop\r\nDate: Wed, 6 Feb 2019 09:00:00 +0000\r\n\r\n",
+    ];
+
+    let headers = mails
+        .iter()
+        .map(|x| parse_headers(x.as_bytes()).expect("failed to parse headers"))
+        .collect::<Vec<_>>();
+
+    let leaf = |message| Thread { message: Some(message), children: vec![] };
+
+    assert_eq!(thread(&headers), vec![
+        Thread { message: Some(3), children: vec![leaf(4)] },
+        Thread { message: Some(0), children: vec![leaf(2), leaf(1)] },
+        Thread { message: None, children: vec![leaf(5), leaf(6)] },
+        leaf(7),
+    ]);
+
+    assert_eq!(thread(&headers)[1].messages(), vec![0, 2, 1]);
+
+    // A mail with a lot of references doesn't make the conversation too deep to be walked.
+    let references = (0 .. 5000).map(|x| format!("<{}@x>", x)).collect::<Vec<_>>().join(" ");
+    let mail = format!("Message-ID: <deep@x>\r\nReferences: {}\r\nSubject: Deep\r\n\r\n", references);
+    let headers = [parse_headers(mail.as_bytes()).expect("failed to parse headers")];
+
+    let threads = thread(&headers);
+    assert_eq!(threads.len(), 1);
+    assert_eq!(threads[0].messages(), vec![0]);
+}
+
+
+#[test]
+fn report_errors() {
+    let mail = b"Subject: Hello\r\nFrom: <<<\r\nTo: john@example.com\r\n\r\nHi";
+
+    assert_eq!(parse(mail).err(), Some(ParseError {
+        kind: ErrorKind::InvalidHeader,
+        offset: 16,
+        header: Some(String::from("From")),
+    }));
+
+    let mail = parse_lenient(mail);
+    assert_eq!(mail.subject(), Some(&String::from("Hello")));
+    assert!(mail.headers().from().is_none());
+    assert_eq!(mail.headers().to().map(|x| x.len()), Some(1));
+    assert!(matches!(mail.body(), Body::Content(content) if content == b"Hi"));
+
+    let mail = b"Subject: Hello\r\nthis is not a header\r\nTo: john@example.com\r\n\r\nHi";
+    assert_eq!(parse(mail).map_err(|x| (x.kind, x.offset)).err(), Some((ErrorKind::InvalidHeader, 16)));
+    assert_eq!(parse_lenient(mail).headers().to().map(|x| x.len()), Some(1));
+
+    let headers = b"Subject: Hello\r\nTo: john@example.com";
+    assert_eq!(parse_headers(headers).err().map(|x| x.to_string()), Some(String::from("unexpected end of input at byte 36 in header To")));
+    assert_eq!(parse_headers_lenient(headers).to().map(|x| x.len()), Some(1));
+
+    // Mails that are nested too deeply are rejected, or kept as content in lenient mode.
+    let mut mail = vec![];
+    for i in 0 .. 100 {
+        mail.extend_from_slice(format!("Content-Type: multipart/mixed; boundary={0}\r\n\r\n--{0}\r\n", i).as_bytes());
+    }
+
+    assert_eq!(parse(&mail).map_err(|x| x.kind).err(), Some(ErrorKind::TooDeep));
+    assert!(matches!(parse_lenient(&mail).body(), Body::Multi { .. }));
+}
+
+#[test]
+fn never_panic() {
+    let inputs: &[&[u8]] = &[
+        b"",
+        b"\r\n",
+        b":",
+        b": \r\n\r\n",
+        b"\xff\xfe: \xff\r\n\r\n\xff",
+        b"Subject: =?utf-8?b?====?= =?\xff?q?=ff?=\r\n\r\n",
+        b"Content-Type: multipart/mixed; boundary=\"\"\r\n\r\n--\r\n--\r\n",
+        b"Content-Type: multipart/mixed; boundary=a\r\n\r\n--a",
+        b"Content-Type: text/plain; charset*0*=''%; name*=\r\nContent-Transfer-Encoding: base64\r\n\r\n=",
+        b"Content-Transfer-Encoding: quoted-printable\r\n\r\n=\r\n=4",
+        b"Date: Mon, 99 Xyz 99999999 99:99:99 +9999\r\nFrom: \"unterminated\r\nTo: (((\r\n\r\n",
+        b"References: <<>\r\nMessage-ID: <>\r\nIn-Reply-To: <\r\n\r\n",
+    ];
+
+    for input in inputs {
+        let _ = parse(input);
+        let _ = parse_headers(input);
+        let _ = parse_lenient(input).text();
+        let _ = parse_headers_lenient(input);
+    }
+}
+
+#[test]
+fn parse_eight_bit_headers() -> Result<()> {
+    let mail = parse(include_bytes!("../mails/eight_bit_headers.txt"))?;
+    let headers = mail.headers();
+
+    assert_eq!(mail.subject(), Some(&String::from("Réunion de lundi – ordre du jour")));
+    assert_eq!(headers.from(), Some(&[Address {
+        display_name: Some(String::from("Zoë Dupré")),
+        local_part: String::from("zoe"),
+        domain: String::from("example.fr"),
+    }][..]));
+
+    let to = headers.to().unwrap().iter().flat_map(Recipient::addresses).collect::<Vec<_>>();
+    assert_eq!(to.iter().map(|x| x.display_name.as_deref()).collect::<Vec<_>>(), vec![Some("Jürgen Groß"), Some("Ana Muñoz")]);
+    assert_eq!(to.iter().map(|x| x.address()).collect::<Vec<_>>(), vec!["juergen@example.de", "ana@example.es"]);
+
+    assert_eq!(headers.message_id(), Some(&MessageId(String::from("réunion-42@example.fr"))));
+    assert_eq!(headers.date().and_then(Date::timestamp), Some(1_549_873_800));
+    assert_eq!(mail.charset(), Some("UTF-8"));
+    assert_eq!(mail.text(), Some(String::from("Bonjour à tous,\r\n\r\nVoici l’ordre du jour.\r\n")));
+
+    // Headers that are neither UTF-8 nor encoded are considered to be windows-1252.
+    let mail = parse(include_bytes!("../mails/latin1_headers.txt"))?;
+
+    assert_eq!(mail.subject(), Some(&String::from("Déjà vu")));
+    assert_eq!(mail.headers().from().unwrap()[0].display_name, Some(String::from("François")));
+    assert_eq!(mail.charset(), Some("windows-1252"));
+    assert_eq!(mail.text(), Some(String::from("Café crème\r\n")));
+
+    Ok(())
+}
+
+/// Converts the CRLF line breaks of some bytes to LF, or only one line break out of two if `mixed`
+/// is true.
+fn to_lf(bytes: &[u8], mixed: bool) -> Vec<u8> {
+    let mut converted = vec![];
+    let mut count = 0;
+
+    for (i, c) in bytes.iter().enumerate() {
+        if *c == b'\r' && bytes.get(i + 1) == Some(&b'\n') {
+            count += 1;
+
+            if !mixed || count % 2 == 0 {
+                continue;
+            }
+        }
+
+        converted.push(*c);
+    }
+
+    converted
+}
+
+/// Converts the CRLF line breaks of the contents of a shape to LF.
+fn shape_to_lf(shape: Shape) -> Shape {
+    match shape {
+        Shape::Content(mime_type, content) => Shape::Content(mime_type, to_lf(&content, false)),
+        Shape::Multi(subtype, parts) => Shape::Multi(subtype, parts.into_iter().map(shape_to_lf).collect()),
+        Shape::Message(message) => Shape::Message(Box::new(shape_to_lf(*message))),
+    }
+}
+
+#[test]
+fn parse_lf_only_mails() -> Result<()> {
+    let mail = parse(include_bytes!("../mails/lf_only.txt"))?;
+    assert_eq!(mail.subject(), Some(&String::from("Sent from a Unix mailer")));
+
+    match mail.body() {
+        Body::Multi { subtype, parts } if subtype == "alternative" => {
+            assert_eq!(parts.len(), 2);
+            assert_eq!(parts[0].text(), Some(String::from("Lines end with a single line feed.")));
+            assert_eq!(parts[1].text(), Some(String::from("<p>Lines end with a single line feed.</p>")));
+        },
+        _ => panic!("expected a multipart alternative mail"),
+    }
+
+    // The fixtures give the same mails once converted to LF, or to a mix of CRLF and LF.
+    let mails: &[&[u8]] = &[
+        include_bytes!("../mails/simple.txt"),
+        include_bytes!("../mails/big.txt"),
+        include_bytes!("../mails/nested.txt"),
+        include_bytes!("../mails/truncated.txt"),
+        include_bytes!("../mails/eight_bit_headers.txt"),
+        include_bytes!("../mails/latin1_headers.txt"),
+    ];
+
+    for mail in mails {
+        let expected = parse(mail)?;
+
+        for mixed in &[false, true] {
+            let converted = to_lf(mail, *mixed);
+            let parsed = parse(&converted)?;
+
+            assert_eq!(shape_to_lf(shape(&parsed)), shape_to_lf(shape(&expected)));
+            assert_eq!(parsed.subject(), expected.subject());
+            assert_eq!(parsed.headers().from(), expected.headers().from());
+            assert_eq!(parsed.headers().to(), expected.headers().to());
+            assert_eq!(parsed.headers().date(), expected.headers().date());
+            assert_eq!(stream_shape(&converted, 5), shape(&parsed));
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn parse_truncated_mails() -> Result<()> {
+    let mail = parse(include_bytes!("../mails/truncated.txt"))?;
+
+    let parts = match mail.body() {
+        Body::Multi { subtype, parts } if subtype == "mixed" => parts,
+        _ => panic!("expected a multipart mixed mail"),
+    };
+    assert_eq!(parts.len(), 2);
+    assert_eq!(parts[0].text(), Some(String::from("The report is attached.")));
+
+    // The closing boundary is missing and the attachment is cut in the middle of a line, so only
+    // the complete bytes are kept.
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    pdf.extend((0 ..= 255).chain(0 ..= 255));
+
+    let attachments = mail.attachments().collect::<Vec<_>>();
+    assert_eq!(attachments.len(), 1);
+    assert_eq!(attachments[0].filename, Some(String::from("report.pdf")));
+    assert_eq!(attachments[0].mime_type, "application/pdf");
+    assert_eq!(attachments[0].size, 267);
+    assert_eq!(attachments[0].content, &pdf[..267]);
+
+    Ok(())
+}
+
+#[test]
+fn parse_borrowed_mails() -> Result<()> {
+    let input = include_bytes!("../mails/nested.txt");
+    let mail = borrowed::Mail::parse(input)?;
+
+    // The values of the headers are slices of the input, that are decoded on demand.
+    let content_type = mail.headers().get("content-type").unwrap();
+    assert_eq!(content_type.name, "Content-Type");
+    assert!(input.as_ptr_range().contains(&content_type.value.as_ptr()));
+    assert_eq!(&content_type.unfolded()[..], &b"multipart/mixed; boundary=outer-boundary"[..]);
+    assert_eq!(mail.subject(), parse(input)?.subject().cloned());
+
+    let parts = match mail.body() {
+        borrowed::Body::Multi { subtype, parts } if subtype == "mixed" => parts,
+        _ => panic!("expected a multipart mixed mail"),
+    };
+    assert_eq!(parts.len(), 2);
+
+    let related = match parts[0].body() {
+        borrowed::Body::Multi { parts, .. } => match parts[1].body() {
+            borrowed::Body::Multi { parts, .. } => parts,
+            _ => panic!("expected a multipart related part"),
+        },
+        _ => panic!("expected a multipart alternative part"),
+    };
+
+    match related[1].body() {
+        borrowed::Body::Content(content) => assert_eq!(*content, &b"iVBORw0KGgo="[..]),
+        _ => panic!("expected a single part"),
+    }
+    assert_eq!(related[1].content().as_deref(), Some(&b"\x89PNG\r\n\x1a\n"[..]));
+    assert_eq!(related[1].text(), None);
+
+    let owned = mail.clone().into_owned();
+    assert_eq!(owned.attachments().map(|x| x.filename).collect::<Vec<_>>(), vec![Some(String::from("pictures.pdf"))]);
+
+    let headers = borrowed::Headers::parse(b"SUBJECT: =?utf-8?q?caf=C3=A9?=\r\n\r\n")?;
+    assert_eq!(headers.subject(), Some(String::from("café")));
+    assert_eq!(headers.into_owned().subject(), Some(&String::from("café")));
+
+    Ok(())
+}
+
+/// The structure and the content of a mail, used to compare the parsers.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Shape {
+    Content(Option<String>, Vec<u8>),
+    Multi(String, Vec<Shape>),
+    Message(Box<Shape>),
+}
+
+/// Returns the shape of a parsed mail.
+pub(crate) fn shape(mail: &Mail) -> Shape {
+    match mail.body() {
+        Body::Content(content) => Shape::Content(mail.headers().content_type().map(|x| x.mime_type()), content.clone()),
+        Body::Multi { subtype, parts } => Shape::Multi(subtype.clone(), parts.iter().map(shape).collect()),
+        Body::Message(message) => Shape::Message(Box::new(shape(message))),
+    }
+}
+
+/// Parses a mail with the incremental parser, giving it chunks of the same size, and returns its
+/// shape.
+pub(crate) fn stream_shape(mail: &[u8], chunk_size: usize) -> Shape {
+    let mut parser = StreamParser::new();
+    let mut events = vec![];
+
+    for chunk in mail.chunks(chunk_size) {
+        events.extend(parser.push(chunk));
+    }
+    events.extend(parser.finish());
+
+    // The parts that are being read, with their content type, their content and their parts.
+    let mut stack: Vec<(Option<ContentType>, Vec<u8>, Vec<Shape>)> = vec![];
+    let mut shapes = vec![];
+
+    for event in events {
+        match event {
+            Event::PartStart => stack.push((None, vec![], vec![])),
+            Event::Header(Header::ContentType(content_type)) => stack.last_mut().unwrap().0 = Some(content_type),
+            Event::Header(_) | Event::HeadersEnd => (),
+            Event::BodyChunk(chunk) => stack.last_mut().unwrap().1.extend(chunk),
+            Event::PartEnd => {
+                let (content_type, content, mut parts) = stack.pop().unwrap();
+
+                let part = match content_type {
+                    Some(ref x) if x.is_multipart() && x.boundary().is_some() => Shape::Multi(x.subtype.clone(), parts),
+                    Some(ref x) if x.is_message() && parts.len() == 1 => Shape::Message(Box::new(parts.remove(0))),
+                    _ => Shape::Content(content_type.map(|x| x.mime_type()), content),
+                };
+
+                match stack.last_mut() {
+                    Some(parent) => parent.2.push(part),
+                    None => shapes.push(part),
+                }
+            },
+        }
+    }
+
+    assert_eq!(shapes.len(), 1);
+    shapes.remove(0)
+}
+
+#[test]
+fn parse_streams() -> Result<()> {
+    let mails: &[&[u8]] = &[
+        include_bytes!("../mails/simple.txt"),
+        include_bytes!("../mails/big.txt"),
+        include_bytes!("../mails/nested.txt"),
+        include_bytes!("../mails/truncated.txt"),
+        include_bytes!("../mails/eight_bit_headers.txt"),
+    ];
+
+    for mail in mails {
+        let expected = shape(&parse(mail)?);
+
+        for chunk_size in &[1, 2, 3, 7, 64, 4096] {
+            assert_eq!(stream_shape(mail, *chunk_size), expected);
+        }
+    }
+
+    // Long lines are emitted before their end.
+    let mut parser = StreamParser::new();
+    parser.push(b"Content-Type: multipart/mixed; boundary=b\r\n\r\n--b\r\n\r\n");
+
+    let events = parser.push(&[b'a'; 1000]);
+    assert!(matches!(&events[..], [Event::BodyChunk(chunk)] if chunk.len() == 1000));
+
+    let events = parser.push(b"\r\n--b");
+    assert!(events.is_empty());
+
+    let events = parser.push(b"--\r\n");
+    assert!(matches!(&events[..], [Event::PartEnd]));
+    assert!(matches!(&parser.finish()[..], [Event::PartEnd]));
+
+    Ok(())
+}
+
+#[test]
+fn serialize_mails() -> Result<()> {
+    let mails: &[&[u8]] = &[
+        include_bytes!("../mails/simple.txt"),
+        include_bytes!("../mails/big.txt"),
+        include_bytes!("../mails/nested.txt"),
+        include_bytes!("../mails/eight_bit_headers.txt"),
+        include_bytes!("../mails/latin1_headers.txt"),
+    ];
+
+    for mail in mails {
+        let parsed = parse(mail)?;
+        let serialized = parse(&parsed.to_bytes())?;
+
+        assert_eq!(shape(&serialized), shape(&parsed));
+        assert_eq!(serialized.subject(), parsed.subject());
+        assert_eq!(serialized.headers().from(), parsed.headers().from());
+        assert_eq!(serialized.headers().to(), parsed.headers().to());
+        assert_eq!(serialized.headers().date(), parsed.headers().date());
+        assert_eq!(serialized.headers().message_id(), parsed.headers().message_id());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn build_mails() -> Result<()> {
+    let subject = "Un très long sujet, avec des accents, qui ne tient pas sur une seule ligne de l'en-tête";
+    let from = Address::parse("\"Doé, John\" <john@example.com>").unwrap();
+    let pdf = (0..=255).cycle().take(1000).collect::<Vec<u8>>();
+
+    let mail = MailBuilder::new()
+        .subject(subject)
+        .from(from.clone())
+        .to(Address::parse("jane@example.com").unwrap())
+        .to(Address::parse("Bob <bob@example.com>").unwrap())
+        .message_id(MessageId(String::from("1234@example.com")))
+        .text("Hello,\nthis line looks like a boundary:\n--=_part_0\n")
+        .html("<p>Hello,</p>")
+        .attachment("résumé.pdf", "application/pdf", pdf.clone())
+        .build();
+
+    let bytes = mail.to_bytes();
+    let end = bytes.windows(4).position(|x| x == b"\r\n\r\n").unwrap();
+
+    for line in bytes[..end].split(|c| *c == b'\n') {
+        assert!(line.len() <= 79);
+        assert!(line.is_ascii());
+    }
+
+    let parsed = parse(&bytes)?;
+    assert_eq!(parsed.subject().map(String::as_str), Some(subject));
+    assert_eq!(parsed.headers().from(), Some(&[from][..]));
+    assert_eq!(parsed.headers().to().map(<[_]>::len), Some(2));
+    assert_eq!(parsed.headers().message_id(), Some(&MessageId(String::from("1234@example.com"))));
+
+    let parts = match parsed.body() {
+        Body::Multi { subtype, parts } if subtype == "mixed" => parts,
+        _ => panic!("expected a multipart mixed mail"),
+    };
+
+    match parts[0].body() {
+        Body::Multi { subtype, parts } if subtype == "alternative" => {
+            assert_eq!(parts[0].text().unwrap(), "Hello,\r\nthis line looks like a boundary:\r\n--=_part_0\r\n");
+            assert_eq!(parts[1].text().unwrap(), "<p>Hello,</p>");
+        },
+        _ => panic!("expected a multipart alternative part"),
+    }
+
+    let attachments = parsed.attachments().collect::<Vec<_>>();
+    assert_eq!(attachments.len(), 1);
+    assert_eq!(attachments[0].filename.as_deref(), Some("résumé.pdf"));
+    assert_eq!(attachments[0].mime_type, "application/pdf");
+    assert_eq!(attachments[0].content, &pdf[..]);
+
+    // The blind carbon copies are not serialized, and the addresses can't add headers.
+    let forged = Address {
+        display_name: Some(String::from("Eve\r\nX-Injected: 1")),
+        local_part: String::from("eve\r\nX-Injected: 2"),
+        domain: String::from("example.com\r\nX-Injected: 3"),
+    };
+
+    let mail = MailBuilder::new().to(forged).bcc(Address::parse("secret@example.com").unwrap()).text("Hi").build();
+    assert_eq!(mail.headers().bcc().map(<[_]>::len), Some(1));
+
+    let bytes = String::from_utf8(mail.to_bytes()).unwrap();
+    assert!(!bytes.contains("secret@example.com"));
+    assert!(!bytes.contains("\nX-Injected"));
+    assert!(bytes.contains("2\"@example.comX-Injected: 3>"));
+
+    Ok(())
+}
+
+#[test]
+fn access_headers_by_name() -> Result<()> {
+    let input = b"Received: from a.example.com\r\n\
+        received: from b.example.com\r\n\
+        SUBJECT : A subject\r\n\
+        \tfolded with a tab,\r\n  and two spaces\r\n\
+        X-Mailer: =?utf-8?q?Caf=C3=A9?= 1.0\r\n\
+        content-TYPE: text/plain;\r\n charset=utf-8\r\n\
+        \r\n\
+        Hello";
+
+    let headers = parse_headers(input)?;
+
+    // Unfolding only removes the line breaks.
+    assert_eq!(headers.subject(), Some(&String::from("A subject\tfolded with a tab,  and two spaces")));
+    assert_eq!(headers.content_type().and_then(ContentType::charset), Some("utf-8"));
+
+    // The names are not case sensitive, and the headers keep their order.
+    assert!(matches!(headers.get("subject"), Some(Header::Subject(_))));
+    assert!(matches!(headers.get("x-mailer"), Some(Header::Unknown { value, .. }) if value == "Café 1.0"));
+    assert!(headers.get("List-Id").is_none());
+
+    let received = headers
+        .get_all("Received")
+        .into_iter()
+        .map(|x| match x {
+            Header::Unknown { name, value } => format!("{}: {}", name, value),
+            _ => panic!("expected an unknown header"),
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(received, vec!["Received: from a.example.com", "received: from b.example.com"]);
+
+    let names = headers.0.iter().map(Header::name).collect::<Vec<_>>();
+    assert_eq!(names, vec!["Received", "received", "Subject", "X-Mailer", "Content-Type"]);
+
+    // The borrowed headers keep the names and the values as they appear in the mail.
+    let borrowed = borrowed::Headers::parse(input)?;
+    assert_eq!(borrowed.get_all("received").len(), 2);
+    assert_eq!(borrowed.get("subject").unwrap().name, "SUBJECT");
+    assert_eq!(&borrowed.get("subject").unwrap().unfolded()[..], &b"A subject\tfolded with a tab,  and two spaces"[..]);
+
+    Ok(())
+}
+
+#[test]
+fn decode_flowed_text() -> Result<()> {
+    let paragraph = |quote_depth, text: &str| Paragraph { quote_depth, text: String::from(text) };
+
+    let mail = parse(b"Content-Type: text/plain; charset=utf-8; format=flowed; delsp=yes\r\n\
+        \r\n\
+        On Monday, Jane wrote:\r\n\
+        > This is a long para \r\n\
+        > graph that is split.\r\n\
+        >> An older quote \r\n\
+        > A quote that ends \r\n\
+        Not quoted anymore\r\n\
+        \x20>not a quote\r\n\
+        -- \r\n\
+        John\r\n")?;
+
+    assert_eq!(mail.paragraphs(), Some(vec![
+        paragraph(0, "On Monday, Jane wrote:"),
+        paragraph(1, "This is a long paragraph that is split."),
+        paragraph(2, "An older quote"),
+        paragraph(1, "A quote that ends"),
+        paragraph(0, "Not quoted anymore"),
+        paragraph(0, ">not a quote"),
+        paragraph(0, "-- "),
+        paragraph(0, "John"),
+    ]));
+
+    // Without delsp, the spaces that end the flowed lines are kept.
+    assert_eq!(flowed::decode("A flowed \r\nline\r\n", false), vec![paragraph(0, "A flowed line")]);
+
+    // The text that is not flowed has a paragraph per line.
+    let mail = parse(b"Content-Type: text/plain\r\n\r\nA fixed \r\nline\r\n")?;
+    assert_eq!(mail.paragraphs(), Some(vec![paragraph(0, "A fixed "), paragraph(0, "line")]));
+
+    // The text that is not flowed keeps its indentation, since its lines are not stuffed.
+    assert_eq!(flowed::decode_fixed("* A list\n  * Nested\n> Quoted\n"), vec![
+        paragraph(0, "* A list"),
+        paragraph(0, "  * Nested"),
+        paragraph(1, "Quoted"),
+    ]);
+
+    // The composed mails are flowed.
+    let text = "A paragraph that is long enough to be wrapped on several lines when it is encoded.\n\
+        > A quote\n\
+        From here";
+
+    let mail = parse(&MailBuilder::new().text(text).build().to_bytes())?;
+    let part = mail.part("text", "plain").unwrap();
+    assert!(part.text().unwrap().lines().all(|x| x.len() <= 72));
+    assert!(part.text().unwrap().ends_with("\r\n From here\r\n"));
+
+    assert_eq!(part.paragraphs(), Some(vec![
+        paragraph(0, "A paragraph that is long enough to be wrapped on several lines when it is encoded."),
+        paragraph(1, "A quote"),
+        paragraph(0, "From here"),
+    ]));
+
+    Ok(())
+}
+
+#[test]
+fn sanitize_html() {
+    let inline_url = |id: &str| format!("/api/get-inline?cid={}", id);
+
+    // Known XSS payloads, none of which may keep something that runs a script.
+    let payloads = [
+        "<script>alert(1)</script>",
+        "<SCRIPT SRC=http://evil.example/xss.js></SCRIPT>",
+        "<scr<script>ipt>alert(1)</script>",
+        "<img src=x onerror=alert(1)>",
+        "<img src=\"x\" OnError=\"alert(1)\">",
+        "<img/src=x/onerror=alert(1)>",
+        "<img src=javascript:alert(1)>",
+        "<img src=\"jav&#x09;ascript:alert(1)\">",
+        "<img src=\"jav\tascript:alert(1)\">",
+        "<img src=\"&#106;&#97;&#118;&#97;&#115;&#99;&#114;&#105;&#112;&#116;&#58;alert(1)\">",
+        "<img src=\"&#0000106&#0000097&#0000118&#0000097&#0000115&#0000099&#0000114&#0000105&#0000112&#0000116&#0000058alert(1)\">",
+        "<a href=\"java&Tab;script&colon;alert(1)\">link</a>",
+        "<a href=\" \x01javascript:alert(1)\">link</a>",
+        "<a href=\"JaVaScRiPt:alert(1)\">link</a>",
+        "<a href=\"vbscript:msgbox(1)\">link</a>",
+        "<a href=\"data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg==\">link</a>",
+        "<body onload=alert(1)>",
+        "<svg onload=alert(1)><script>alert(1)</script></svg>",
+        "<svg><a xlink:href=\"javascript:alert(1)\"><text>x</text></a></svg>",
+        "<math><mi xlink:href=\"javascript:alert(1)\">x</mi></math>",
+        "<iframe src=\"javascript:alert(1)\"></iframe>",
+        "<iframe srcdoc=\"<script>alert(1)</script>\"></iframe>",
+        "<object data=\"javascript:alert(1)\"></object>",
+        "<embed src=\"javascript:alert(1)\">",
+        "<form action=\"javascript:alert(1)\"><input type=submit></form>",
+        "<form><button formaction=javascript:alert(1)>x</button></form>",
+        "<input onfocus=alert(1) autofocus>",
+        "<details open ontoggle=alert(1)>",
+        "<div style=\"background:url(javascript:alert(1))\">x</div>",
+        "<div style=\"width: expression(alert(1))\">x</div>",
+        "<div style=\"width: expr\\65 ssion(alert(1))\">x</div>",
+        "<div style=\"behavior: url(xss.htc)\">x</div>",
+        "<div style=\"-moz-binding: url(http://evil.example/xss.xml#xss)\">x</div>",
+        "<style>@import 'http://evil.example/xss.css';</style>",
+        "<link rel=stylesheet href=\"javascript:alert(1)\">",
+        "<meta http-equiv=\"refresh\" content=\"0;url=javascript:alert(1)\">",
+        "<base href=\"javascript:alert(1)//\">",
+        "<!--<img src=\"--><img src=x onerror=alert(1)//\">",
+        "<textarea><img src=x onerror=alert(1)></textarea>",
+        "<title><img src=x onerror=alert(1)></title>",
+        "<noscript><p title=\"</noscript><img src=x onerror=alert(1)>\"></noscript>",
+        "<p title=\"\"><img src=x onerror=alert(1)>\">",
+        "<div onmouseover=\"alert(1)\">x</div>",
+        "<a href=\"http://example.com\" onclick=\"alert(1)\">x</a>",
+        "<img src=\"x\"onerror=\"alert(1)\">",
+        "<img src=`x`onerror=alert(1)>",
+        "<img \"\"\"><script>alert(1)</script>\">",
+        "<<script>alert(1);//<</script>",
+        "<template><script>alert(1)</script></template>",
+        "<video><source onerror=\"alert(1)\"></video>",
+        "<marquee onstart=alert(1)>",
+        "<isindex action=javascript:alert(1) type=image>",
+        "<table background=\"javascript:alert(1)\"><tr><td>x</td></tr></table>",
+    ];
+
+    for payload in payloads.iter() {
+        let sanitized = html::sanitize(payload, inline_url, true).html.to_ascii_lowercase();
+
+        for forbidden in ["<script", "javascript", "vbscript", "data:", "onerror", "onload", "onclick",
+            "onmouseover", "onfocus", "ontoggle", "onstart", "<iframe", "<svg", "<math", "<form",
+            "<input", "<button", "<object", "<embed", "<style", "<link", "<meta", "<base", "<textarea",
+            "expression", "expr", "binding", "behavior", "@import", "<body", "<details", "<source",
+            "<marquee", "<isindex", "background="].iter()
+        {
+            assert!(!sanitized.contains(forbidden), "{:?} became {:?}", payload, sanitized);
+        }
+    }
+
+    // The harmless HTML is kept, and the links open in new tabs.
+    assert_eq!(
+        html::sanitize("<p style=\"color: red; position: fixed\">Hello <b>you</b> &amp; &eacute; &lt;3</p>", inline_url, true).html,
+        "<p style=\"color: red\">Hello <b>you</b> &amp; &eacute; &lt;3</p>",
+    );
+
+    assert_eq!(
+        html::sanitize("<a href=\"https://example.com/?a=1&amp;b=2\" class=\"x\">link</a>", inline_url, true).html,
+        "<a href=\"https://example.com/?a=1&amp;b=2\" target=\"_blank\" rel=\"noopener noreferrer\">link</a>",
+    );
+
+    // The inline attachments are served by the server.
+    assert_eq!(
+        html::sanitize("<img src=\"cid:logo%40example.com\" alt=\"Logo\">", inline_url, true).html,
+        "<img src=\"/api/get-inline?cid=logo@example.com\" alt=\"Logo\">",
+    );
+
+    assert_eq!(
+        html::sanitize("<td style=\"background-image: url('cid:bg')\">", inline_url, true).html,
+        "<td style=\"background-image: url(&quot;/api/get-inline?cid=bg&quot;)\"></td>",
+    );
+
+    // The HTML can't close the elements that contain it, and the elements it opens are closed.
+    assert_eq!(
+        html::sanitize("</div></td><div><table><tr><td>x</div> a < b > c", inline_url, true).html,
+        "<div><table><tr><td>x</td></tr></table></div> a &lt; b &gt; c",
+    );
+
+    // The scripts, the styles and the comments are removed with their content.
+    assert_eq!(
+        html::sanitize("<html><head><style>p { color: red }</style></head><body><!-- x -->Hi<script>x</script></body></html>", inline_url, true).html,
+        "Hi",
+    );
+}
+
+#[test]
+fn find_inline_parts() -> Result<()> {
+    let mail = parse(b"Content-Type: multipart/related; boundary=b\r\n\
+        \r\n\
+        --b\r\n\
+        Content-Type: text/html\r\n\
+        \r\n\
+        <img src=\"cid:logo@example.com\">\r\n\
+        --b\r\n\
+        Content-Type: image/png\r\n\
+        Content-ID: <logo@example.com>\r\n\
+        \r\n\
+        PNG\r\n\
+        --b--\r\n")?;
+
+    match mail.inline_part("logo@example.com").map(Mail::body) {
+        Some(Body::Content(content)) => assert_eq!(content, b"PNG"),
+        _ => panic!("the inline part was not found"),
+    }
+
+    assert!(mail.inline_part("other@example.com").is_none());
+    Ok(())
+}
+
+#[test]
+fn block_remote_content() {
+    let inline_url = |id: &str| format!("/api/get-inline?cid={}", id);
+
+    let content = "<p style=\"background: url(https://example.com/bg.png); color: red\">\
+        <img src=\"https://example.com/photo.jpg\" alt=\"Photo\" title=\"A photo\">\
+        <img src=\"cid:logo\">\
+        <img src=\"https://example.com/open.gif\" width=\"1\" height=\"1\">\
+        <img src=\"https://example.com/open.png\" style=\"width: 0px; height: 0px\">\
+        <img src=\"https://example.us1.list-manage.com/track/open.php?u=1\">\
+        <a href=\"https://example.com\">link</a></p>";
+
+    // By default, the remote content is blocked and replaced by placeholders.
+    let sanitized = html::sanitize(content, inline_url, false);
+
+    assert_eq!(sanitized.html, "<p style=\"color: red\">\
+        <img alt=\"Photo\" title=\"Remote image blocked\">\
+        <img src=\"/api/get-inline?cid=logo\">\
+        <a href=\"https://example.com\" target=\"_blank\" rel=\"noopener noreferrer\">link</a></p>");
+
+    assert_eq!(sanitized.blocked, 5);
+    assert_eq!(sanitized.trackers, 3);
+
+    // When the remote content is allowed, the tracking pixels are still removed.
+    let sanitized = html::sanitize(content, inline_url, true);
+
+    assert_eq!(sanitized.html, "<p style=\"background: url(&quot;https://example.com/bg.png&quot;); color: red\">\
+        <img src=\"https://example.com/photo.jpg\" alt=\"Photo\" title=\"A photo\">\
+        <img src=\"/api/get-inline?cid=logo\">\
+        <a href=\"https://example.com\" target=\"_blank\" rel=\"noopener noreferrer\">link</a></p>");
+
+    assert_eq!(sanitized.blocked, 3);
+    assert_eq!(sanitized.trackers, 3);
+}
+
+#[test]
+fn convert_html_to_text() -> Result<()> {
+    let text = html::to_text("<html><head><title>Newsletter</title><style>p { color: red }</style></head>\
+        <body>\
+        <h1>Hello&nbsp;there</h1>\
+        <p>Some   <b>bold</b>\n text, and <a href=\"https://example.com/news\">a link</a>.</p>\
+        <ul><li>First</li><li>Second<ol start=\"3\"><li>Third</li><li>Fourth</li></ol></li></ul>\
+        <table><tr><th>Name</th><th>Price</th></tr><tr><td>Tea</td><td>3 &euro;</td></tr></table>\
+        <p>Line<br>break<br><br>and <a href=\"https://example.com\">https://example.com</a> \
+        <a href=\"mailto:jane@example.com\">jane@example.com</a> <a href=\"https://example.com/news\">again</a></p>\
+        <div>On Monday, Jane wrote:</div>\
+        <blockquote><p>A quote</p><blockquote>An older quote</blockquote></blockquote>\
+        <pre>  fn main() {\n      println!();\n  }</pre>\
+        <script>alert(1)</script>\
+        </body></html>");
+
+    assert_eq!(text, "Hello there\n\
+        \n\
+        Some bold text, and a link [1].\n\
+        \n\
+        * First\n\
+        * Second\n\
+        \x20 3. Third\n\
+        \x20 4. Fourth\n\
+        \n\
+        Name | Price\n\
+        Tea | 3 \u{20ac}\n\
+        \n\
+        Line\n\
+        break\n\
+        \n\
+        and https://example.com jane@example.com again [1]\n\
+        \n\
+        On Monday, Jane wrote:\n\
+        \n\
+        > A quote\n\
+        >\n\
+        >> An older quote\n\
+        \n\
+        \x20 fn main() {\n\
+        \x20     println!();\n\
+        \x20 }\n\
+        \n\
+        [1] https://example.com/news");
+
+    // The deeply nested quotes and lists don't make every line huge.
+    let text = html::to_text(&format!("{}{}", "<blockquote><ul><li>".repeat(1000), "<p>a</p><p>b</p>".repeat(100)));
+    assert!(text.lines().all(|x| x.len() <= 32 * 3 + 4));
+
+    // The numbers of the list items don't overflow.
+    let text = html::to_text(&format!("<ol start=\"{}\"><li>First</li><li>Second</li></ol>", usize::MAX));
+    assert_eq!(text, format!("{0}. First\n{0}. Second", usize::MAX));
+
+    // The plain text version is preferred, and the HTML version is converted otherwise.
+    let mail = parse(b"Content-Type: multipart/alternative; boundary=b\r\n\
+        \r\n\
+        --b\r\n\
+        Content-Type: text/plain; format=flowed\r\n\
+        \r\n\
+        A flowed \r\n\
+        paragraph\r\n\
+        > A quote\r\n\
+        --b\r\n\
+        Content-Type: text/html\r\n\
+        \r\n\
+        <p>The HTML version</p>\r\n\
+        --b--\r\n")?;
+
+    assert_eq!(mail.plain_text(), Some(String::from("A flowed paragraph\n> A quote")));
+
+    let mail = parse(b"Content-Type: multipart/mixed; boundary=b\r\n\
+        \r\n\
+        --b\r\n\
+        Content-Type: text/html\r\n\
+        \r\n\
+        <p>Only <i>HTML</i></p>\r\n\
+        --b\r\n\
+        Content-Type: text/plain\r\n\
+        Content-Disposition: attachment; filename=notes.txt\r\n\
+        \r\n\
+        An attachment\r\n\
+        --b--\r\n")?;
+
+    assert_eq!(mail.plain_text(), Some(String::from("Only HTML")));
+    Ok(())
+}
+
+#[test]
+fn generate_snippets() -> Result<()> {
+    let mail = parse(b"Content-Type: text/plain\r\n\
+        \r\n\
+        Hi Jane,\r\n\
+        \r\n\
+        Sounds   good to me.\r\n\
+        \r\n\
+        On Monday, Jane wrote:\r\n\
+        > Shall we meet\r\n\
+        > tomorrow?\r\n\
+        \r\n\
+        See you!\r\n\
+        -- \r\n\
+        John\r\n")?;
+
+    assert_eq!(mail.snippet(200), Some(String::from("Hi Jane, Sounds good to me. See you!")));
+    assert_eq!(mail.snippet(15), Some(String::from("Hi Jane, Sounds\u{2026}")));
+    assert_eq!(mail.snippet(14), Some(String::from("Hi Jane,\u{2026}")));
+    assert_eq!(mail.snippet(3), Some(String::from("Hi\u{2026}")));
+
+    // The beginning of the body is enough, even if it is encoded and cut in the middle.
+    let mail = parse_lenient(b"Content-Type: multipart/alternative; boundary=b\r\n\
+        \r\n\
+        --b\r\n\
+        Content-Type: text/html; charset=iso-8859-1\r\n\
+        Content-Transfer-Encoding: quoted-printable\r\n\
+        \r\n\
+        <p>Caf=E9 ou th=\r\n\
+        =E9 ?</p><p>Le menu est en pi=E8ce jointe, et la suite est coup=");
+
+    assert_eq!(mail.snippet(200), Some(String::from("Caf\u{e9} ou th\u{e9} ? Le menu est en pi\u{e8}ce jointe, et la suite est coup")));
+
+    // The text is cut between characters, even if they are not ASCII.
+    let mail = parse("Content-Type: text/plain; charset=utf-8\r\n\r\nabc\u{e9}\u{e9}\u{e9} caf\u{e9} th\u{e9}\r\n".as_bytes())?;
+    assert_eq!(mail.snippet(3), Some(String::from("abc\u{2026}")));
+    assert_eq!(mail.snippet(11), Some(String::from("abc\u{e9}\u{e9}\u{e9} caf\u{e9}\u{2026}")));
+    assert_eq!(mail.snippet(12), Some(String::from("abc\u{e9}\u{e9}\u{e9} caf\u{e9}\u{2026}")));
+
+    // The mails without text have no snippet.
+    let mail = parse(b"Content-Type: image/png\r\n\r\nPNG")?;
+    assert_eq!(mail.snippet(200), None);
+
+    Ok(())
+}
+
+#[test]
+fn parse_embedded_mails() -> Result<()> {
+    let forwarded = b"Subject: Fwd: Meeting\r\n\
+        Content-Type: multipart/mixed; boundary=outer\r\n\
+        \r\n\
+        --outer\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        See below.\r\n\
+        --outer\r\n\
+        Content-Type: message/rfc822\r\n\
+        Content-Disposition: attachment; filename=meeting.eml\r\n\
+        \r\n\
+        Subject: Meeting\r\n\
+        From: alice@example.com\r\n\
+        Content-Type: multipart/mixed; boundary=inner\r\n\
+        \r\n\
+        --inner\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        Tomorrow at noon.\r\n\
+        --inner\r\n\
+        Content-Type: application/pdf\r\n\
+        Content-Disposition: attachment; filename=agenda.pdf\r\n\
+        \r\n\
+        PDF\r\n\
+        --inner--\r\n\
+        --outer--\r\n";
+
+    let mail = parse(forwarded)?;
+    let attachments = mail.attachments().collect::<Vec<_>>();
+
+    // The attachments of the embedded mail belong to it.
+    assert_eq!(attachments.len(), 1);
+    assert_eq!(attachments[0].filename.as_deref(), Some("meeting.eml"));
+    assert_eq!(attachments[0].mime_type, "message/rfc822");
+
+    let message = attachments[0].message.expect("the embedded mail was not parsed");
+    assert_eq!(message.subject().map(String::as_str), Some("Meeting"));
+    assert_eq!(message.attachments().next().map(|x| x.content), Some(&b"PDF"[..]));
+    assert_eq!(mail.plain_text().as_deref(), Some("See below."));
+
+    // The incremental parser nests the embedded mails in the same way.
+    for chunk_size in &[1, 7, 64, forwarded.len()] {
+        assert_eq!(stream_shape(forwarded, *chunk_size), shape(&mail));
+    }
+
+    let serialized = parse(&mail.to_bytes())?;
+    assert_eq!(shape(&serialized), shape(&mail));
+
+    // The encoded embedded mails are decoded before being parsed.
+    let encoded = parse(b"Content-Type: message/rfc822\r\n\
+        Content-Transfer-Encoding: base64\r\n\
+        \r\n\
+        U3ViamVjdDogSGVsbG8NCg0KSGkhDQo=\r\n")?;
+
+    match encoded.body() {
+        Body::Message(message) => {
+            assert_eq!(message.subject().map(String::as_str), Some("Hello"));
+            assert_eq!(shape(message), Shape::Content(None, b"Hi!\r\n".to_vec()));
+        },
+        _ => panic!("the embedded mail was not parsed"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn parse_delivery_reports() -> Result<()> {
+    let mail = parse(b"From: Mail Delivery System <MAILER-DAEMON@mail.example.com>\r\n\
+        Subject: Undelivered Mail Returned to Sender\r\n\
+        Content-Type: multipart/report; report-type=Delivery-Status; boundary=b\r\n\
+        \r\n\
+        --b\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        Your message could not be delivered.\r\n\
+        --b\r\n\
+        Content-Type: message/delivery-status\r\n\
+        \r\n\
+        Reporting-MTA: dns; mail.example.com\r\n\
+        Arrival-Date: Fri, 1 Feb 2019 12:00:00 +0100\r\n\
+        \r\n\
+        Final-Recipient: rfc822; unknown@example.org\r\n\
+        Original-Recipient: rfc822; alias@example.org\r\n\
+        Action: failed\r\n\
+        Status: 5.1.1\r\n\
+        Remote-MTA: dns; mx.example.org\r\n\
+        Diagnostic-Code: smtp; 550 5.1.1 <unknown@example.org>:\r\n \
+         Recipient address rejected\r\n\
+        \r\n\
+        Final-Recipient: rfc822; busy@example.org\r\n\
+        Action: Delayed\r\n\
+        Status: 4.2.2 (mailbox full)\r\n\
+        \r\n\
+        Final-Recipient: rfc822; missing-action@example.org\r\n\
+        Status: 5.0.0\r\n\
+        --b\r\n\
+        Content-Type: text/rfc822-headers\r\n\
+        \r\n\
+        Subject: Hello\r\n\
+        --b--\r\n")?;
+
+    let report = mail.delivery_report().expect("the report was not found");
+
+    assert_eq!(report.reporting_mta.as_deref(), Some("mail.example.com"));
+    assert_eq!(report.recipients, vec![
+        report::RecipientStatus {
+            final_recipient: String::from("unknown@example.org"),
+            original_recipient: Some(String::from("alias@example.org")),
+            action: report::Action::Failed,
+            status: report::StatusCode::parse("5.1.1"),
+            diagnostic: Some(String::from("550 5.1.1 <unknown@example.org>: Recipient address rejected")),
+            remote_mta: Some(String::from("mx.example.org")),
+        },
+        report::RecipientStatus {
+            final_recipient: String::from("busy@example.org"),
+            original_recipient: None,
+            action: report::Action::Delayed,
+            status: Some(report::StatusCode { class: 4, subject: 2, detail: 2 }),
+            diagnostic: None,
+            remote_mta: None,
+        },
+    ]);
+
+    let failed = report.failed().map(|x| x.final_recipient.as_str()).collect::<Vec<_>>();
+    assert_eq!(failed, vec!["unknown@example.org"]);
+    assert_eq!(report.recipients[1].status.map(|x| x.to_string()).as_deref(), Some("4.2.2"));
+    assert_eq!(mail.plain_text().as_deref(), Some("Your message could not be delivered."));
+
+    assert_eq!(report::StatusCode::parse("3.1.1"), None);
+    assert_eq!(report::StatusCode::parse("5.1"), None);
+    assert!(parse(b"Subject: Hello\r\n\r\nHi\r\n")?.delivery_report().is_none());
+    Ok(())
+}
+
+#[test]
+fn parse_calendar_invitations() -> Result<()> {
+    let mail = parse(b"Subject: Invitation: Weekly meeting\r\n\
+        Content-Type: multipart/alternative; boundary=b\r\n\
+        \r\n\
+        --b\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        You have been invited.\r\n\
+        --b\r\n\
+        Content-Type: text/calendar; charset=utf-8; method=REQUEST\r\n\
+        \r\n\
+        BEGIN:VCALENDAR\r\n\
+        VERSION:2.0\r\n\
+        METHOD:REQUEST\r\n\
+        BEGIN:VTIMEZONE\r\n\
+        TZID:Europe/Paris\r\n\
+        END:VTIMEZONE\r\n\
+        BEGIN:VEVENT\r\n\
+        UID:meeting-42@example.com\r\n\
+        SEQUENCE:2\r\n\
+        SUMMARY:Weekly meeting\\, with\r\n \
+        \x20the whole team\r\n\
+        DESCRIPTION:Agenda:\\n- news\\n- questions\r\n\
+        LOCATION:Room 1\\; first floor\r\n\
+        DTSTART;TZID=Europe/Paris:20190201T140000\r\n\
+        DTEND;TZID=Europe/Paris:20190201T150000\r\n\
+        ORGANIZER;CN=\"Doe, John\":mailto:john@example.com\r\n\
+        ATTENDEE;CN=Alice;PARTSTAT=NEEDS-ACTION;RSVP=TRUE:mailto:Alice@Example.com\r\n\
+        ATTENDEE;PARTSTAT=accepted:MAILTO:bob@example.com\r\n\
+        BEGIN:VALARM\r\n\
+        DESCRIPTION:Reminder\r\n\
+        END:VALARM\r\n\
+        END:VEVENT\r\n\
+        BEGIN:VEVENT\r\n\
+        SUMMARY:Event without identifier\r\n\
+        END:VEVENT\r\n\
+        END:VCALENDAR\r\n\
+        --b--\r\n")?;
+
+    let invitation = mail.calendar().expect("the calendar was not found");
+    assert_eq!(invitation.method, Some(calendar::Method::Request));
+    assert_eq!(invitation.events.len(), 1);
+
+    let event = &invitation.events[0];
+    assert_eq!(event.uid, "meeting-42@example.com");
+    assert_eq!(event.sequence, 2);
+    assert_eq!(event.summary.as_deref(), Some("Weekly meeting, with the whole team"));
+    assert_eq!(event.description.as_deref(), Some("Agenda:\n- news\n- questions"));
+    assert_eq!(event.location.as_deref(), Some("Room 1; first floor"));
+
+    assert_eq!(event.start, Some(calendar::Time::Local {
+        time: chrono::NaiveDate::from_ymd_opt(2019, 2, 1).unwrap().and_hms_opt(14, 0, 0).unwrap(),
+        timezone: Some(String::from("Europe/Paris")),
+    }));
+
+    let organizer = event.organizer.as_ref().unwrap();
+    assert_eq!((organizer.address.as_str(), organizer.name.as_deref()), ("john@example.com", Some("Doe, John")));
+
+    let alice = event.attendee("alice@example.com").unwrap();
+    assert_eq!(alice.name.as_deref(), Some("Alice"));
+    assert_eq!(alice.status, calendar::ParticipationStatus::NeedsAction);
+    assert_eq!(event.attendee("bob@example.com").map(|x| &x.status), Some(&calendar::ParticipationStatus::Accepted));
+    assert_eq!(mail.plain_text().as_deref(), Some("You have been invited."));
+
+    // The reply can be sent in a mail, and parsed by the organizer.
+    let now = chrono::DateTime::parse_from_rfc3339("2019-01-30T10:00:00Z").unwrap().with_timezone(&chrono::Utc);
+    let reply = calendar::reply(event, "alice@example.com", &calendar::ParticipationStatus::Tentative, now)
+        .expect("the reply was not composed");
+
+    assert!(reply.lines().all(|x| x.len() <= 75));
+    assert!(reply.contains("ORGANIZER;CN=\"Doe, John\":mailto:john@example.com\r\n"));
+    assert!(reply.contains("DTSTAMP:20190130T100000Z\r\n"));
+
+    let reply = MailBuilder::new()
+        .subject("Tentative: Weekly meeting")
+        .text("Alice may attend.")
+        .calendar("REPLY", &reply)
+        .build();
+
+    let reply = parse(&reply.to_bytes())?;
+    let content_type = reply.part("text", "calendar").and_then(|x| x.headers().content_type()).cloned();
+    assert_eq!(content_type.as_ref().and_then(|x| x.parameter("method")), Some("REPLY"));
+
+    let replied = reply.calendar().expect("the calendar was not found");
+    assert_eq!(replied.method, Some(calendar::Method::Reply));
+    assert_eq!(replied.events[0].uid, event.uid);
+    assert_eq!(replied.events[0].start, event.start);
+    assert_eq!(replied.events[0].organizer, event.organizer);
+
+    assert_eq!(replied.events[0].attendees, vec![calendar::Attendee {
+        address: String::from("Alice@Example.com"),
+        name: Some(String::from("Alice")),
+        status: calendar::ParticipationStatus::Tentative,
+    }]);
+
+    // The addresses can't add properties to the reply.
+    let status = calendar::ParticipationStatus::Accepted;
+    assert_eq!(calendar::reply(event, "alice@example.com\r\nATTENDEE:mailto:eve@example.com", &status, now), None);
+
+    let mut forged = event.clone();
+    forged.organizer.as_mut().unwrap().address = String::from("john@example.com\nMETHOD:CANCEL");
+    assert_eq!(calendar::reply(&forged, "alice@example.com", &status, now), None);
+
+    Ok(())
+}
+
+#[test]
+fn parse_list_headers() -> Result<()> {
+    let input = b"Subject: Weekly news\r\n\
+        List-Id: \"Weekly news\" (from Example) <news.example.com>\r\n\
+        List-Unsubscribe: <mailto:leave@example.com?subject=unsubscribe>,\r\n \
+        (one click) <https://example.com/unsub\r\n scribe?id=42>\r\n\
+        List-Unsubscribe-Post: List-Unsubscribe=One-Click\r\n\
+        List-Post: NO (posting not allowed on this list)\r\n\
+        List-Archive: <https://example.com/archives>\r\n\
+        \r\n\
+        Hello";
+
+    let headers = parse_headers(input)?;
+
+    assert_eq!(headers.list_id(), Some(&ListId {
+        description: Some(String::from("Weekly news")),
+        id: String::from("news.example.com"),
+    }));
+
+    assert_eq!(headers.list_unsubscribe(), Some(&[
+        String::from("mailto:leave@example.com?subject=unsubscribe"),
+        String::from("https://example.com/unsubscribe?id=42"),
+    ][..]));
+
+    assert!(headers.is_one_click_unsubscribe());
+    assert_eq!(headers.list_post(), Some(&[][..]));
+    assert_eq!(headers.list_archive(), Some(&[String::from("https://example.com/archives")][..]));
+
+    let borrowed = borrowed::Headers::parse(input)?;
+    assert_eq!(borrowed.list_id().as_ref(), headers.list_id());
+    assert_eq!(borrowed.list_unsubscribe().as_deref(), headers.list_unsubscribe());
+
+    // The list headers are kept when the mail is serialized.
+    let mail = parse(input)?;
+    let serialized = parse(&mail.to_bytes())?;
+    assert_eq!(serialized.headers().list_id(), headers.list_id());
+    assert_eq!(serialized.headers().list_unsubscribe(), headers.list_unsubscribe());
+    assert_eq!(serialized.headers().list_post(), headers.list_post());
+    assert!(serialized.headers().is_one_click_unsubscribe());
+
+    // The malformed list headers are kept as unknown headers, even by the strict parser.
+    let headers = parse_headers(b"List-Id: not a list id\r\nList-Unsubscribe: https://example.com\r\n\r\n")?;
+    assert!(headers.list_id().is_none());
+    assert!(headers.list_unsubscribe().is_none());
+    assert!(matches!(headers.get("List-Unsubscribe"), Some(Header::Unknown { value, .. }) if value == "https://example.com"));
+    assert!(!headers.is_one_click_unsubscribe());
+
+    Ok(())
+}
+
+#[test]
+fn parse_and_compose_pgp_mails() -> Result<()> {
+    // The signed mail is wrapped by a mailing list, and its line breaks are not canonical.
+    let signed = "Content-Type: multipart/signed; boundary=signed; micalg=PGP-SHA256;\n \
+        protocol=\"application/pgp-signature\"\n\
+        \n\
+        --signed\n\
+        Content-Type: text/plain\n\
+        \n\
+        Hello\n\
+        --signed\n\
+        Content-Type: application/pgp-signature\n\
+        \n\
+        -----BEGIN PGP SIGNATURE-----\n\
+        --signed--\n";
+
+    let input = format!("Subject: Signed\nContent-Type: multipart/mixed; boundary=list\n\n--list\n{}--list--\n", signed);
+    let mail = borrowed::Mail::parse(input.as_bytes())?;
+
+    assert_eq!(mail.protection(), Some(pgp::Protection::Signed(pgp::Signed {
+        content: b"Content-Type: text/plain\r\n\r\nHello".to_vec(),
+        signature: b"-----BEGIN PGP SIGNATURE-----".to_vec(),
+        micalg: Some(String::from("pgp-sha256")),
+    })));
+
+    // A signed part next to a part that is not signed doesn't make the mail signed.
+    let input = format!("Content-Type: multipart/mixed; boundary=list\n\n\
+        --list\n\
+        Content-Type: text/plain\n\
+        \n\
+        Forged text\n\
+        --list\n\
+        {}--list--\n", signed);
+
+    assert_eq!(borrowed::Mail::parse(input.as_bytes())?.protection(), None);
+
+    let input = b"Content-Type: multipart/encrypted; boundary=x; protocol=\"application/pgp-encrypted\"\r\n\
+        \r\n\
+        --x\r\n\
+        Content-Type: application/pgp-encrypted\r\n\
+        \r\n\
+        Version: 1\r\n\
+        --x\r\n\
+        Content-Type: application/octet-stream\r\n\
+        Content-Transfer-Encoding: base64\r\n\
+        \r\n\
+        LS0tLS1CRUdJTiBQR1AgTUVTU0FHRS0tLS0t\r\n\
+        --x--\r\n";
+
+    assert_eq!(borrowed::Mail::parse(input)?.protection(), Some(pgp::Protection::Encrypted(pgp::Encrypted {
+        data: b"-----BEGIN PGP MESSAGE-----".to_vec(),
+    })));
+
+    // The multipart mails without the expected protocol are not protected.
+    let input = b"Content-Type: multipart/signed; boundary=x\r\n\r\n--x\r\n\r\nHello\r\n--x\r\n\r\nWorld\r\n--x--\r\n";
+    assert_eq!(borrowed::Mail::parse(input)?.protection(), None);
+
+    // The bytes that are signed are the ones of the first part of the serialized mail.
+    let mut signed = vec![];
+    let mail = MailBuilder::new().subject("Signed").text("Hello").attachment("a.txt", "text/plain", b"World".to_vec());
+
+    let mail = pgp::sign(mail.build(), "pgp-sha256", |content| -> std::result::Result<_, ()> {
+        signed = content.to_vec();
+        Ok(b"-----BEGIN PGP SIGNATURE-----\r\n".to_vec())
+    }).unwrap();
+
+    let bytes = mail.to_bytes();
+    let parsed = borrowed::Mail::parse(&bytes)?;
+    assert_eq!(parsed.subject(), Some(String::from("Signed")));
+
+    match parsed.protection() {
+        Some(pgp::Protection::Signed(x)) => {
+            assert_eq!(x.content, signed);
+            assert_eq!(x.micalg.as_deref(), Some("pgp-sha256"));
+        },
+        x => panic!("the mail is not signed: {:?}", x),
+    }
+
+    // The signed mail is encrypted with its signature, and the other headers stay in clear.
+    let mail = MailBuilder::new().subject("Secret").text("Hello").build();
+    let sign = |_: &[u8]| -> std::result::Result<_, ()> { Ok(b"signature".to_vec()) };
+    let mail = pgp::sign_and_encrypt(mail, "pgp-sha256", sign, |x| Ok(x.to_vec())).unwrap();
+
+    let bytes = mail.to_bytes();
+    let parsed = borrowed::Mail::parse(&bytes)?;
+    assert_eq!(parsed.subject(), Some(String::from("Secret")));
+
+    let data = match parsed.protection() {
+        Some(pgp::Protection::Encrypted(x)) => x.data,
+        x => panic!("the mail is not encrypted: {:?}", x),
+    };
+
+    let decrypted = parse(&data)?;
+    assert!(decrypted.headers().content_type().map(|x| x.is("multipart", "signed")).unwrap_or(false));
+    assert!(decrypted.subject().is_none());
+    assert_eq!(decrypted.part("text", "plain").and_then(Mail::text).as_deref(), Some("Hello\r\n"));
+
+    Ok(())
+}
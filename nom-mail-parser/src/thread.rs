@@ -0,0 +1,373 @@
+//! This module contains the threading of mails into conversations.
+//!
+//! It implements the algorithm described by Jamie Zawinski at https://www.jwz.org/doc/threading.html,
+//! which links mails using their references and in reply to headers, and then groups the
+//! conversations that have the same subject.
+
+use std::collections::HashMap;
+
+use crate::{Date, Headers};
+
+/// The number of references of a mail that are used to thread it.
+///
+/// Each reference is a level of the tree of the conversation, so a mail with a lot of references
+/// would make a tree too deep to be walked. The first reference, which is the start of the
+/// conversation, and the last ones, which are the closest to the mail, are kept, like the clients
+/// do when the references of their replies are too long.
+const MAX_REFERENCES: usize = 64;
+
+/// A conversation, which is a tree of mails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Thread {
+    /// The index of the mail in the headers that were threaded, or `None` if the mail is missing
+    /// but is referenced by its replies.
+    pub message: Option<usize>,
+
+    /// The replies to the mail, sorted by date.
+    pub children: Vec<Thread>,
+}
+
+impl Thread {
+    /// Returns the indices of the mails of the conversation, in depth first order.
+    pub fn messages(&self) -> Vec<usize> {
+        let mut messages = vec![];
+        let mut stack = vec![self];
+
+        while let Some(thread) = stack.pop() {
+            messages.extend(thread.message);
+            stack.extend(thread.children.iter().rev());
+        }
+
+        messages
+    }
+}
+
+/// A node of the tree that is being built, that can be empty if the mail is missing.
+#[derive(Default)]
+struct Container {
+    /// The index of the mail in the headers, if any.
+    message: Option<usize>,
+
+    /// The parent of the container, if any.
+    parent: Option<usize>,
+
+    /// The children of the container.
+    children: Vec<usize>,
+}
+
+/// The state of the threading algorithm.
+struct Threader<'a> {
+    /// The headers of the mails that are threaded.
+    headers: &'a [Headers],
+
+    /// All the containers, that are referenced by their indices.
+    containers: Vec<Container>,
+
+    /// The containers of the message ids.
+    ids: HashMap<String, usize>,
+}
+
+/// Removes the prefixes that mark replies and forwards from a subject, such as `Re: ` or
+/// `Fwd[2]: `, and returns the normalized subject with whether there was such a prefix.
+///
+/// The tags of mailing lists, such as `[list]`, are removed as well.
+fn normalize_subject(subject: &str) -> (String, bool) {
+    const PREFIXES: &[&str] = &["re", "fwd", "fw", "aw", "sv", "tr", "wg"];
+
+    let mut subject = subject.trim();
+    let mut is_reply = false;
+
+    loop {
+        if subject.starts_with('[') {
+            if let Some(end) = subject.find(']') {
+                subject = subject[end + 1..].trim_start();
+                continue;
+            }
+        }
+
+        let prefix = PREFIXES.iter().find(|x| {
+            subject.len() >= x.len() && subject.is_char_boundary(x.len()) && subject[..x.len()].eq_ignore_ascii_case(x)
+        });
+
+        let rest = match prefix {
+            Some(prefix) => subject[prefix.len()..].trim_start(),
+            None => break,
+        };
+
+        // The prefix may contain a counter, such as in `Re[2]:` or `Re(2):`.
+        let rest = match rest.chars().next() {
+            Some(open @ '[') | Some(open @ '(') => {
+                let close = if open == '[' { ']' } else { ')' };
+                match rest.find(close) {
+                    Some(end) if rest[1..end].chars().all(|c| c.is_ascii_digit()) => rest[end + 1..].trim_start(),
+                    _ => break,
+                }
+            },
+            _ => rest,
+        };
+
+        match rest.strip_prefix(':') {
+            Some(rest) => {
+                subject = rest.trim_start();
+                is_reply = true;
+            },
+            None => break,
+        }
+    }
+
+    (subject.to_lowercase(), is_reply)
+}
+
+impl<'a> Threader<'a> {
+    /// Creates a new container and returns its index.
+    fn create(&mut self, message: Option<usize>) -> usize {
+        self.containers.push(Container { message, ..Default::default() });
+        self.containers.len() - 1
+    }
+
+    /// Returns the container of a message id, creating it if needed.
+    fn container(&mut self, id: &str) -> usize {
+        match self.ids.get(id) {
+            Some(container) => *container,
+            None => {
+                let container = self.create(None);
+                self.ids.insert(id.to_string(), container);
+                container
+            },
+        }
+    }
+
+    /// Checks if a container is an ancestor of another container, or the container itself.
+    fn is_ancestor(&self, ancestor: usize, mut container: usize) -> bool {
+        loop {
+            if container == ancestor {
+                return true;
+            }
+
+            match self.containers[container].parent {
+                Some(parent) => container = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// Removes a container from the children of its parent.
+    fn unlink(&mut self, container: usize) {
+        if let Some(parent) = self.containers[container].parent.take() {
+            self.containers[parent].children.retain(|x| *x != container);
+        }
+    }
+
+    /// Makes a container a child of another one, unless it would create a loop.
+    fn link(&mut self, parent: usize, child: usize) {
+        if self.is_ancestor(child, parent) {
+            return;
+        }
+
+        self.unlink(child);
+        self.containers[child].parent = Some(parent);
+        self.containers[parent].children.push(child);
+    }
+
+    /// Adds a mail to the containers, linking it with the mails it references.
+    fn add(&mut self, index: usize) {
+        let headers = &self.headers[index];
+
+        // Mails without message id, or whose message id was already seen, get their own container.
+        let container = match headers.message_id() {
+            Some(id) => match self.container(&id.0) {
+                container if self.containers[container].message.is_none() => {
+                    self.containers[container].message = Some(index);
+                    container
+                },
+                _ => self.create(Some(index)),
+            },
+            None => self.create(Some(index)),
+        };
+
+        let mut references = headers.references().map(<[_]>::to_vec).unwrap_or_default();
+
+        if let Some(id) = headers.in_reply_to().and_then(<[_]>::first) {
+            if references.last() != Some(id) {
+                references.push(id.clone());
+            }
+        }
+
+        if references.len() > MAX_REFERENCES {
+            references.drain(1 .. references.len() - MAX_REFERENCES + 1);
+        }
+
+        // Link the references together, without changing the links that are already known.
+        let references = references.iter().map(|x| self.container(&x.0)).collect::<Vec<_>>();
+
+        for pair in references.windows(2) {
+            if self.containers[pair[1]].parent.is_none() {
+                self.link(pair[0], pair[1]);
+            }
+        }
+
+        // The last reference is the parent of the mail, which overrides what was previously known.
+        match references.last() {
+            Some(parent) => self.link(*parent, container),
+            None => self.unlink(container),
+        }
+    }
+
+    /// Removes the empty containers, and replaces the empty containers that have children by their
+    /// children, except for the roots that have several children, which are kept to group them.
+    fn prune(&mut self, containers: Vec<usize>, parent: Option<usize>) -> Vec<usize> {
+        let mut pruned = vec![];
+
+        for container in containers {
+            let children = std::mem::take(&mut self.containers[container].children);
+            let children = self.prune(children, Some(container));
+
+            if self.containers[container].message.is_none() && (parent.is_some() || children.len() <= 1) {
+                for child in &children {
+                    self.containers[*child].parent = parent;
+                }
+
+                self.containers[container].parent = None;
+                pruned.extend(children);
+            } else {
+                self.containers[container].children = children;
+                pruned.push(container);
+            }
+        }
+
+        pruned
+    }
+
+    /// Returns the normalized subject of a container, with whether it is a reply.
+    ///
+    /// The subject of an empty container is the one of its first child.
+    fn subject(&self, container: usize) -> (String, bool) {
+        let container = &self.containers[container];
+
+        let message = container.message.or_else(|| {
+            container.children.first().and_then(|x| self.containers[*x].message)
+        });
+
+        match message.and_then(|x| self.headers[x].subject()) {
+            Some(subject) => normalize_subject(subject),
+            None => (String::new(), false),
+        }
+    }
+
+    /// Groups the roots that have the same subject, and returns the new roots.
+    fn group_by_subject(&mut self, roots: Vec<usize>) -> Vec<usize> {
+        let mut subjects: HashMap<String, usize> = HashMap::new();
+
+        // Find the best root for each subject, preferring empty containers and then mails that are
+        // not replies.
+        for root in &roots {
+            let (subject, is_reply) = self.subject(*root);
+
+            if subject.is_empty() {
+                continue;
+            }
+
+            let replace = match subjects.get(&subject) {
+                None => true,
+                Some(other) => {
+                    let is_empty = self.containers[*root].message.is_none();
+                    let other_is_empty = self.containers[*other].message.is_none();
+                    (is_empty && !other_is_empty) || (!other_is_empty && self.subject(*other).1 && !is_reply)
+                },
+            };
+
+            if replace {
+                subjects.insert(subject, *root);
+            }
+        }
+
+        let mut grouped = vec![];
+
+        for root in roots {
+            let (subject, is_reply) = self.subject(root);
+
+            let other = match subjects.get(&subject) {
+                Some(other) if !subject.is_empty() && *other != root => *other,
+                _ => {
+                    grouped.push(root);
+                    continue;
+                },
+            };
+
+            let is_empty = self.containers[root].message.is_none();
+            let other_is_empty = self.containers[other].message.is_none();
+
+            if is_empty && other_is_empty {
+                for child in std::mem::take(&mut self.containers[root].children) {
+                    self.containers[child].parent = None;
+                    self.link(other, child);
+                }
+            } else if other_is_empty || (is_reply && !self.subject(other).1) {
+                self.link(other, root);
+            } else {
+                // Neither is a reply to the other, so they become siblings under an empty container.
+                let container = self.create(None);
+                let position = grouped.iter().position(|x| *x == other);
+                self.link(container, other);
+                self.link(container, root);
+                subjects.insert(subject, container);
+
+                match position {
+                    Some(position) => grouped[position] = container,
+                    None => grouped.push(container),
+                }
+            }
+        }
+
+        grouped.retain(|x| self.containers[*x].parent.is_none());
+        grouped
+    }
+
+    /// Returns the date of the first mail of a container, used to sort the conversations.
+    fn date(&self, container: usize) -> (Option<i64>, Option<usize>) {
+        let container = &self.containers[container];
+
+        match container.message {
+            Some(message) => (self.headers[message].date().and_then(Date::timestamp), Some(message)),
+            None => container.children.iter().map(|x| self.date(*x)).min().unwrap_or((None, None)),
+        }
+    }
+
+    /// Converts a container to a thread, sorting the children by date.
+    fn thread(&self, container: usize) -> Thread {
+        let mut children = self.containers[container].children.clone();
+        children.sort_by_key(|x| self.date(*x));
+
+        Thread {
+            message: self.containers[container].message,
+            children: children.into_iter().map(|x| self.thread(x)).collect(),
+        }
+    }
+}
+
+/// Threads some mails into conversations.
+///
+/// The mails are linked using their references and in reply to headers, and the conversations
+/// that have the same subject are grouped. The conversations and the replies are sorted by date,
+/// and the mails are referred to by their index in `headers`.
+pub fn thread(headers: &[Headers]) -> Vec<Thread> {
+    let mut threader = Threader {
+        headers,
+        containers: vec![],
+        ids: HashMap::new(),
+    };
+
+    for index in 0 .. headers.len() {
+        threader.add(index);
+    }
+
+    let roots = (0 .. threader.containers.len())
+        .filter(|x| threader.containers[*x].parent.is_none())
+        .collect::<Vec<_>>();
+
+    let roots = threader.prune(roots, None);
+    let mut roots = threader.group_by_subject(roots);
+    roots.sort_by_key(|x| threader.date(*x));
+
+    roots.into_iter().map(|x| threader.thread(x)).collect()
+}
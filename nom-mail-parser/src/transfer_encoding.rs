@@ -0,0 +1,131 @@
+//! This module contains the functions to encode and decode the content transfer encodings of the
+//! bodies.
+
+/// Converts an hexadecimal digit to its value.
+pub fn hex_value(c: u8) -> Option<u8> {
+    match c {
+        b'0' ..= b'9' => Some(c - b'0'),
+        b'a' ..= b'f' => Some(c - b'a' + 10),
+        b'A' ..= b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Decodes some quoted printable content.
+///
+/// Soft line breaks are removed, as well as the white spaces at the end of the lines, which may
+/// have been added during the transport. Invalid escape sequences are kept as is.
+pub fn decode_quoted_printable(input: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(input.len());
+    let lines = input.split(|c| *c == b'\n').collect::<Vec<_>>();
+
+    for (index, line) in lines.iter().enumerate() {
+        let end = line.iter().rposition(|c| !b" \t\r".contains(c)).map(|x| x + 1).unwrap_or(0);
+
+        // A line that ends with an equal sign is followed by a soft line break.
+        let (line, soft_line_break) = match line[..end].split_last() {
+            Some((b'=', line)) => (line, true),
+            _ => (&line[..end], false),
+        };
+
+        let mut i = 0;
+        while i < line.len() {
+            match line[i] {
+                b'=' => match (line.get(i + 1).and_then(|c| hex_value(*c)), line.get(i + 2).and_then(|c| hex_value(*c))) {
+                    (Some(high), Some(low)) => {
+                        decoded.push(high << 4 | low);
+                        i += 2;
+                    },
+                    _ => decoded.push(b'='),
+                },
+                c => decoded.push(c),
+            }
+
+            i += 1;
+        }
+
+        if !soft_line_break && index + 1 < lines.len() {
+            decoded.extend_from_slice(b"\r\n");
+        }
+    }
+
+    decoded
+}
+
+/// Decodes some base64 content.
+///
+/// The line breaks and the other chars that are not part of the base64 alphabet are ignored, and
+/// the end of truncated content is dropped.
+pub fn decode_base64(input: &[u8]) -> Vec<u8> {
+    let encoded = input
+        .iter()
+        .cloned()
+        .take_while(|c| *c != b'=')
+        .filter(|c| c.is_ascii_alphanumeric() || *c == b'+' || *c == b'/')
+        .collect::<Vec<_>>();
+
+    // Removing the last chars of truncated content is always enough to make it valid.
+    let mut end = encoded.len();
+
+    loop {
+        match base64::decode_config(&encoded[..end], base64::STANDARD_NO_PAD) {
+            Ok(decoded) => return decoded,
+            Err(_) if end > 0 => end -= 1,
+            Err(_) => return vec![],
+        }
+    }
+}
+
+/// Encodes some content in quoted printable.
+///
+/// The line breaks of the content are kept as hard line breaks, and the lines are split by soft
+/// line breaks so that they are at most 76 chars long. The white spaces at the end of the lines are
+/// escaped, since they may be removed during the transport.
+pub fn encode_quoted_printable(input: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(input.len());
+    let mut line_length = 0;
+    let mut i = 0;
+
+    while i < input.len() {
+        if input[i..].starts_with(b"\r\n") {
+            encoded.extend_from_slice(b"\r\n");
+            line_length = 0;
+            i += 2;
+            continue;
+        }
+
+        let c = input[i];
+        let at_line_end = i + 1 == input.len() || input[i + 1..].starts_with(b"\r\n");
+
+        let escape = match c {
+            b' ' | b'\t' => at_line_end,
+            b'=' => true,
+            33 ..= 126 => false,
+            _ => true,
+        };
+
+        let length = if escape { 3 } else { 1 };
+
+        // Keep some room for the equal sign of the soft line break.
+        if line_length + length > 75 {
+            encoded.extend_from_slice(b"=\r\n");
+            line_length = 0;
+        }
+
+        if escape {
+            encoded.extend_from_slice(format!("={:02X}", c).as_bytes());
+        } else {
+            encoded.push(c);
+        }
+
+        line_length += length;
+        i += 1;
+    }
+
+    encoded
+}
+
+/// Encodes some content in base64, with lines of 76 chars.
+pub fn encode_base64(input: &[u8]) -> Vec<u8> {
+    base64::encode(input).into_bytes().chunks(76).collect::<Vec<_>>().join(&b"\r\n"[..])
+}
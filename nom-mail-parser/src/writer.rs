@@ -0,0 +1,369 @@
+//! This module contains the serialization of mails, as specified by RFC 5322 and MIME.
+
+use crate::{Address, Recipient, MessageId, ListId, ContentType, ContentDisposition, DispositionType};
+use crate::{ContentTransferEncoding, Header, Mail, Body, quote, is_atext};
+
+/// The length beyond which the lines of the headers are folded, as recommended by RFC 5322.
+const MAX_LINE_LENGTH: usize = 78;
+
+/// The number of bytes of text in an encoded word, so that the encoded words are 60 chars long and
+/// fit on the first line of a header, after its name.
+const ENCODED_WORD_BYTES: usize = 36;
+
+/// The maximum length of the lines of 7bit content, as specified by RFC 5322.
+const MAX_CONTENT_LINE_LENGTH: usize = 998;
+
+/// Checks if a char is allowed in a token, which is a parameter value that doesn't need quotes.
+fn is_token(c: char) -> bool {
+    c > ' ' && c < '\x7f' && !"()<>@,;:\\\"/[]?=".contains(c)
+}
+
+/// Checks if a char is allowed in a parameter value encoded as specified by RFC 2231.
+fn is_attribute_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&c)
+}
+
+/// Checks if some text must be encoded to appear in a header, because it contains non ASCII chars,
+/// control chars, or something that would be decoded as an encoded word.
+fn needs_encoding(text: &str) -> bool {
+    !text.is_ascii() || text.contains("=?") || text.chars().any(|c| c.is_ascii_control() && c != '\t')
+}
+
+/// Encodes some text with RFC 2047 encoded words, separated by spaces.
+///
+/// The words are split on char boundaries, so that each of them can be decoded on its own.
+fn encode_words(text: &str) -> String {
+    let mut words = vec![];
+    let mut start = 0;
+
+    while start < text.len() {
+        let mut end = (start + ENCODED_WORD_BYTES).min(text.len());
+
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        words.push(format!("=?utf-8?b?{}?=", base64::encode(&text[start..end])));
+        start = end;
+    }
+
+    words.join(" ")
+}
+
+/// Serializes some unstructured text, such as a subject.
+///
+/// The text is encoded if needed, or if it starts or ends with white spaces that would be lost.
+fn unstructured(text: &str) -> String {
+    if needs_encoding(text) || text.trim() != text {
+        encode_words(text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Serializes the display name of an address or a group.
+fn display_name(name: &str) -> String {
+    if needs_encoding(name) {
+        encode_words(name)
+    } else {
+        quote(name, |c| is_atext(c) || c == ' ')
+    }
+}
+
+/// Serializes an address, with its display name if any.
+fn address(address: &Address) -> String {
+    match address.display_name {
+        Some(ref name) => format!("{} <{}>", display_name(name), address.address()),
+        None => address.address(),
+    }
+}
+
+/// Serializes a list of addresses.
+fn addresses<'a>(addresses: impl IntoIterator<Item = &'a Address>) -> String {
+    addresses.into_iter().map(address).collect::<Vec<_>>().join(", ")
+}
+
+/// Serializes a list of recipients, which are addresses or groups.
+fn recipients(recipients: &[Recipient]) -> String {
+    recipients
+        .iter()
+        .map(|recipient| match recipient {
+            Recipient::Address(x) => address(x),
+            Recipient::Group(group) => format!("{}: {};", display_name(&group.name), addresses(&group.addresses)),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Serializes a list of message ids.
+fn message_ids(ids: &[MessageId]) -> String {
+    ids.iter().map(MessageId::to_string).collect::<Vec<_>>().join(" ")
+}
+
+/// Serializes the identifier of a mailing list, with its description if any.
+fn list_id(list_id: &ListId) -> String {
+    match list_id.description {
+        Some(ref description) => format!("{} <{}>", display_name(description), list_id.id),
+        None => format!("<{}>", list_id.id),
+    }
+}
+
+/// Serializes a list of URLs, such as the URLs to unsubscribe from a mailing list.
+fn url_list(urls: &[String]) -> String {
+    urls.iter().map(|x| format!("<{}>", x)).collect::<Vec<_>>().join(", ")
+}
+
+/// Serializes the parameters of a content type or a content disposition.
+///
+/// The values that are not ASCII are encoded as specified by RFC 2231.
+fn parameters(parameters: &[(String, String)]) -> String {
+    let mut serialized = String::new();
+
+    for (name, value) in parameters {
+        if needs_encoding(value) {
+            let encoded = value
+                .bytes()
+                .map(|c| if is_attribute_char(c) { (c as char).to_string() } else { format!("%{:02X}", c) })
+                .collect::<String>();
+
+            serialized.push_str(&format!("; {}*=utf-8''{}", name, encoded));
+        } else {
+            serialized.push_str(&format!("; {}={}", name, quote(value, is_token)));
+        }
+    }
+
+    serialized
+}
+
+/// Serializes a content type.
+fn content_type(content_type: &ContentType) -> String {
+    format!("{}{}", content_type.mime_type(), parameters(&content_type.parameters))
+}
+
+/// Serializes a content disposition.
+fn content_disposition(content_disposition: &ContentDisposition) -> String {
+    let disposition = match content_disposition.disposition {
+        DispositionType::Inline => "inline",
+        DispositionType::Attachment => "attachment",
+        DispositionType::Other(ref other) => other,
+    };
+
+    format!("{}{}", disposition, parameters(&content_disposition.parameters))
+}
+
+/// Folds a header line, which starts with the name of the header, at the white spaces so that its
+/// lines are not longer than 78 chars when possible.
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut length = 0;
+
+    for (index, word) in line.split(' ').enumerate() {
+        if index > 0 {
+            // Folding before an empty word would leave a line made of white spaces, and folding
+            // after the name of the header would add a white space to the value.
+            if index > 1 && !word.is_empty() && length + 1 + word.len() > MAX_LINE_LENGTH {
+                folded.push_str("\r\n");
+                length = 0;
+            }
+
+            folded.push(' ');
+            length += 1;
+        }
+
+        folded.push_str(word);
+        length += word.len();
+    }
+
+    folded
+}
+
+/// Serializes a header with its name and its value, followed by a line break.
+fn field(name: &str, value: &str) -> String {
+    format!("{}\r\n", fold(&format!("{}: {}", name, value)))
+}
+
+/// Serializes a header.
+///
+/// Returns `None` for the lines that are not headers, which are kept by the lenient parsers.
+fn header(header: &Header) -> Option<String> {
+    let value = match header {
+        Header::Subject(subject) => unstructured(subject),
+        Header::Date(date) => date.to_string(),
+        Header::From(from) => addresses(from),
+        Header::Sender(sender) => address(sender),
+        Header::ReplyTo(x) | Header::To(x) | Header::Cc(x) | Header::Bcc(x) => recipients(x),
+        Header::MessageId(id) => id.to_string(),
+        Header::InReplyTo(ids) | Header::References(ids) => message_ids(ids),
+        Header::ContentType(x) => content_type(x),
+        Header::ContentDisposition(x) => content_disposition(x),
+        Header::ContentTransferEncoding(encoding) => encoding.name().to_string(),
+        Header::ListId(x) => list_id(x),
+        Header::ListPost(urls) if urls.is_empty() => String::from("NO"),
+        Header::ListUnsubscribe(urls) | Header::ListPost(urls) | Header::ListArchive(urls) => url_list(urls),
+        Header::Unknown { name, .. } if name.is_empty() => return None,
+        Header::Unknown { value, .. } => unstructured(value),
+    };
+
+    Some(field(header.name(), &value))
+}
+
+/// Checks if some content can be sent without being encoded, which requires it to be made of
+/// short ASCII lines separated by CRLF.
+fn is_seven_bit(content: &[u8]) -> bool {
+    let mut line_length = 0;
+
+    for (i, c) in content.iter().enumerate() {
+        match c {
+            b'\r' if content.get(i + 1) == Some(&b'\n') => (),
+            b'\n' if i > 0 && content[i - 1] == b'\r' => line_length = 0,
+            b'\r' | b'\n' | 0 => return false,
+            c if !c.is_ascii() => return false,
+            _ => {
+                line_length += 1;
+
+                if line_length > MAX_CONTENT_LINE_LENGTH {
+                    return false;
+                }
+            },
+        }
+    }
+
+    true
+}
+
+/// Chooses the content transfer encoding of some content.
+///
+/// Quoted printable is used when there are few bytes to escape, since each of them takes three
+/// chars, while base64 takes four chars for every three bytes.
+fn encoding(content: &[u8]) -> ContentTransferEncoding {
+    if is_seven_bit(content) {
+        return ContentTransferEncoding::SevenBit;
+    }
+
+    let escaped = content
+        .iter()
+        .filter(|c| !(c.is_ascii_graphic() || b" \t\r\n".contains(c)))
+        .count();
+
+    if escaped * 6 < content.len() {
+        ContentTransferEncoding::QuotedPrintable
+    } else {
+        ContentTransferEncoding::Base64
+    }
+}
+
+/// Checks if some bytes contain a pattern.
+fn contains(bytes: &[u8], pattern: &[u8]) -> bool {
+    bytes.windows(pattern.len()).any(|x| x == pattern)
+}
+
+/// Returns the content type of a multipart mail, with a boundary that doesn't appear in its parts.
+///
+/// The boundary of the mail is kept if possible. Otherwise, the generated boundaries start with
+/// `=_`, which can't appear in quoted printable or base64 content.
+fn multipart_content_type(mail: &Mail, subtype: &str, parts: &[Vec<u8>]) -> ContentType {
+    let mut content_type = match mail.headers().content_type() {
+        Some(content_type) if content_type.is_multipart() => content_type.clone(),
+        _ => ContentType {
+            main_type: String::from("multipart"),
+            subtype: subtype.to_string(),
+            parameters: vec![],
+        },
+    };
+
+    let is_valid = |boundary: &str| {
+        let delimiter = format!("--{}", boundary);
+        !boundary.is_empty() && parts.iter().all(|part| !contains(part, delimiter.as_bytes()))
+    };
+
+    if content_type.boundary().map(is_valid).unwrap_or(false) {
+        return content_type;
+    }
+
+    let boundary = (0..).map(|x| format!("=_part_{}", x)).find(|x| is_valid(x)).unwrap_or_default();
+    content_type.parameters.retain(|(name, _)| !name.eq_ignore_ascii_case("boundary"));
+    content_type.parameters.push((String::from("boundary"), boundary));
+    content_type
+}
+
+/// Serializes a mail.
+pub fn write_mail(mail: &Mail) -> Vec<u8> {
+    // The content type of multipart mails, and the encoding of the other mails, replace the ones of
+    // the headers.
+    let (body, multipart, encoding) = match mail.body() {
+        Body::Content(content) => {
+            let encoding = encoding(content);
+            (encoding.encode(content), None, Some(encoding))
+        },
+
+        Body::Multi { subtype, parts } => {
+            let parts = parts.iter().map(write_mail).collect::<Vec<_>>();
+            let content_type = multipart_content_type(mail, subtype, &parts);
+            let delimiter = format!("--{}", content_type.boundary().unwrap_or_default());
+            let mut body = vec![];
+
+            for part in parts {
+                body.extend_from_slice(format!("{}\r\n", delimiter).as_bytes());
+                body.extend(part);
+                body.extend_from_slice(b"\r\n");
+            }
+
+            body.extend_from_slice(format!("{}--\r\n", delimiter).as_bytes());
+            (body, Some(content_type), None)
+        },
+
+        // The embedded mails can't be encoded, and their own parts are encoded instead.
+        Body::Message(message) => (write_mail(message), None, None),
+    };
+
+    let mut output = vec![];
+    let mut has_content_type = false;
+    let mut has_encoding = false;
+
+    for h in &mail.headers().0 {
+        let line = match h {
+            Header::ContentType(_) if multipart.is_some() => {
+                has_content_type = true;
+                multipart.as_ref().map(|x| field("Content-Type", &content_type(x)))
+            },
+
+            Header::ContentTransferEncoding(_) => {
+                has_encoding = true;
+                encoding.map(|x| field("Content-Transfer-Encoding", x.name()))
+            },
+
+            // The blind carbon copy recipients are only given to the SMTP server, otherwise all
+            // the recipients would see them.
+            Header::Bcc(_) => None,
+
+            // The unknown encodings are replaced as well.
+            Header::Unknown { name, .. } if name.eq_ignore_ascii_case("content-transfer-encoding") => {
+                has_encoding = true;
+                encoding.map(|x| field("Content-Transfer-Encoding", x.name()))
+            },
+
+            h => header(h),
+        };
+
+        output.extend(line.unwrap_or_default().into_bytes());
+    }
+
+    if let (Some(content_type), false) = (&multipart, has_content_type) {
+        output.extend(field("Content-Type", &self::content_type(content_type)).into_bytes());
+    }
+
+    if let (Body::Message(_), None) = (mail.body(), mail.headers().content_type()) {
+        output.extend(field("Content-Type", "message/rfc822").into_bytes());
+    }
+
+    match encoding {
+        Some(encoding) if !has_encoding && encoding != ContentTransferEncoding::SevenBit => {
+            output.extend(field("Content-Transfer-Encoding", encoding.name()).into_bytes());
+        },
+        _ => (),
+    }
+
+    output.extend_from_slice(b"\r\n");
+    output.extend(body);
+    output
+}
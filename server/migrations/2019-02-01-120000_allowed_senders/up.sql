@@ -0,0 +1,6 @@
+CREATE TABLE allowed_senders (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES users (id),
+    address VARCHAR NOT NULL,
+    UNIQUE (user_id, address)
+);
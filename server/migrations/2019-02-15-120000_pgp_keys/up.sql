@@ -0,0 +1,6 @@
+CREATE TABLE pgp_keys (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES users (id),
+    armored TEXT NOT NULL,
+    secret BOOLEAN NOT NULL
+);
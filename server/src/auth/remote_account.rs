@@ -1,18 +1,25 @@
 //! This module contains the structures to manipulate imap accounts.
 
 use std::net::TcpStream;
+use std::result;
 
 use diesel::prelude::*;
 
 use native_tls::TlsStream;
 use imap::Session;
-use nom_mail_parser::parse_headers;
+use imap::types::Fetch;
+use lettre::{SmtpClient, Transport, SendableEmail, Envelope, EmailAddress};
+use lettre::smtp::authentication::Credentials;
+use nom_mail_parser::{parse_headers_lenient, parse_lenient, thread, Address, Headers, Mail, Recipient};
 
 use crate::{Error, Result};
 use crate::schema::imap_accounts;
 use crate::schema::smtp_accounts;
 use crate::auth::user::User;
-use crate::mailbox::Mailbox;
+use crate::mailbox::{Mailbox, Conversation, Summary};
+
+/// The number of bytes of the body of the mails that are fetched to make their previews.
+const SNIPPET_BYTES: usize = 2048;
 
 macro_rules! make_account {
     ($queryable_struct: ident, $insertable_struct: ident, $table: expr, $table_name: expr) => {
@@ -122,34 +129,139 @@ impl ImapAccount {
             .map_err(Into::<Error>::into)?)
     }
 
-    /// Fetches all the subjects of mails in a range.
-    pub fn fetch_subjects(&self, mailbox: &str, start: usize, end: usize) -> Result<Vec<String>> {
+    /// Fetches some data items of the mails in a range, and maps them with a function.
+    ///
+    /// The mails that can't be fetched, or for which the function returns none, are skipped.
+    fn fetch_items<T, F>(&self, mailbox: &str, start: usize, end: usize, query: &str, mut f: F) -> Result<Vec<T>>
+    where
+        F: FnMut(&Fetch) -> Option<T>,
+    {
         let mut session = self.login()?;
         session.select(mailbox)?;
-        let mut subjects = vec![];
+        let mut values = vec![];
 
         for i in start .. end {
 
-            if let Ok(message) = session.fetch(i.to_string(), "(FLAGS RFC822.HEADER)") {
+            if let Ok(message) = session.fetch(i.to_string(), query) {
                 let message = if let Some(m) = message.iter().next() {
                     m
                 } else {
                     continue;
                 };
 
-                let headers = message.header().unwrap_or(&[]);
-
-                let headers = match parse_headers(headers) {
-                    Ok(h) => h,
-                    Err(_) => continue,
-                };
-
-                if let Some(subject) = headers.subject() {
-                    subjects.push(subject.clone());
+                if let Some(value) = f(message) {
+                    values.push(value);
                 }
             }
         }
 
-        Ok(subjects)
+        Ok(values)
+    }
+
+    /// Fetches the raw headers of the mails in a range, and maps them with a function.
+    ///
+    /// The mails whose headers can't be fetched, or for which the function returns none, are
+    /// skipped.
+    fn fetch_raw_headers<T, F>(&self, mailbox: &str, start: usize, end: usize, mut f: F) -> Result<Vec<T>>
+    where
+        F: FnMut(&[u8]) -> Option<T>,
+    {
+        self.fetch_items(mailbox, start, end, "(FLAGS RFC822.HEADER)", |m| f(m.header().unwrap_or(&[])))
+    }
+
+    /// Fetches the headers of the mails in a range.
+    ///
+    /// The mails whose headers can't be fetched are skipped, and the malformed headers are ignored.
+    pub fn fetch_headers(&self, mailbox: &str, start: usize, end: usize) -> Result<Vec<Headers>> {
+        self.fetch_raw_headers(mailbox, start, end, |h| Some(parse_headers_lenient(h)))
+    }
+
+    /// Fetches the subjects of the mails in a range, with a preview of their text.
+    ///
+    /// Only the beginning of the body of each mail is fetched, which is enough for the preview,
+    /// without marking the mail as read.
+    pub fn fetch_summaries(&self, mailbox: &str, start: usize, end: usize) -> Result<Vec<Summary>> {
+        let query = format!("(FLAGS BODY.PEEK[HEADER] BODY.PEEK[TEXT]<0.{}>)", SNIPPET_BYTES);
+
+        self.fetch_items(mailbox, start, end, &query, |message| {
+            let mut mail = message.header()?.to_vec();
+            mail.extend_from_slice(message.text().unwrap_or(&[]));
+            Some(Summary::new(&parse_lenient(&mail)))
+        })
+    }
+
+    /// Fetches the raw bytes of a whole mail, given its sequence number in a mailbox.
+    ///
+    /// Returns none if the mail doesn't exist.
+    pub fn fetch_raw_mail(&self, mailbox: &str, index: usize) -> Result<Option<Vec<u8>>> {
+        let mut session = self.login()?;
+        session.select(mailbox)?;
+
+        let messages = session.fetch(index.to_string(), "RFC822")?;
+
+        Ok(messages
+            .iter()
+            .next()
+            .and_then(|m| m.body())
+            .map(|x| x.to_vec()))
+    }
+
+    /// Fetches a whole mail, given its sequence number in a mailbox.
+    ///
+    /// Returns none if the mail doesn't exist, and the malformed parts of the mail are ignored.
+    pub fn fetch_mail(&self, mailbox: &str, index: usize) -> Result<Option<Mail>> {
+        Ok(self.fetch_raw_mail(mailbox, index)?.map(|x| parse_lenient(&x)))
+    }
+
+    /// Fetches the mails in a range and threads them into conversations.
+    pub fn fetch_conversations(&self, mailbox: &str, start: usize, end: usize) -> Result<Vec<Conversation>> {
+        let headers = self.fetch_headers(mailbox, start, end)?;
+
+        Ok(thread(&headers)
+            .iter()
+            .map(|t| Conversation::new(t, &headers))
+            .collect())
+    }
+}
+
+impl SmtpAccount {
+
+    /// Fetches all the smtp accounts of the user with the corresponding id.
+    pub fn from_user_id(user: i32, connection: &PgConnection) -> Result<Vec<SmtpAccount>> {
+        use crate::schema::smtp_accounts::dsl::*;
+        Ok(smtp_accounts
+            .filter(user_id.eq(user))
+            .select((id, user_id, server, username, password))
+            .get_results::<SmtpAccount>(connection)
+            .map_err(Into::<Error>::into)?)
+    }
+
+    /// Returns the address of the smtp account, which is its username.
+    pub fn address(&self) -> Result<Address> {
+        Address::parse(&self.username).ok_or_else(|| Error::InvalidAddress(self.username.clone()))
+    }
+
+    /// Sends a mail through the smtp account, to all the recipients of its headers.
+    pub fn send(&self, mail: &Mail) -> Result<()> {
+        let headers = mail.headers();
+
+        let recipients = vec![headers.to(), headers.cc(), headers.bcc()]
+            .into_iter()
+            .flatten()
+            .flatten()
+            .flat_map(Recipient::addresses)
+            .map(|x| EmailAddress::new(x.address()))
+            .collect::<result::Result<Vec<_>, _>>()?;
+
+        let envelope = Envelope::new(Some(EmailAddress::new(self.address()?.address())?), recipients)?;
+        let message_id = headers.message_id().map(|x| x.0.clone()).unwrap_or_default();
+
+        let mut client = SmtpClient::new_simple(&self.server)?
+            .credentials(Credentials::new(self.username.clone(), self.password.clone()))
+            .transport();
+
+        client.send(SendableEmail::new(envelope, message_id, mail.to_bytes()))?;
+
+        Ok(())
     }
 }
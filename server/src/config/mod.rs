@@ -5,14 +5,16 @@ use std::fs::File;
 use std::{io, result};
 use std::io::Read;
 
-use lettre::{SmtpClient, Transport};
+use lettre::{SmtpClient, Transport, SendableEmail, Envelope, EmailAddress};
 use lettre::smtp::authentication::Credentials;
-use lettre_email::Email;
+use chrono::Local;
+use nom_mail_parser::{MailBuilder, Address};
 use serde_derive::{Serialize, Deserialize};
 use diesel::connection::Connection;
 use diesel::pg::PgConnection;
 
 use crate::Result;
+use crate::utils::message_id;
 
 /// Returns the string localhost.
 fn localhost() -> String {
@@ -161,19 +163,35 @@ impl Mailer {
     /// Uses a mailer to send an email.
     pub fn send_mail(&self, to: &str, subject: String, text: String, html: String) -> Result<()> {
 
-        let email = Email::builder()
-            .from(self.username.clone())
-            .to(to)
-            .subject(subject)
-            .alternative(html, text)
-            .build()?;
+        let from = Address::parse(&self.username)
+            .ok_or_else(|| crate::Error::InvalidAddress(self.username.clone()))?;
+
+        let recipient = Address::parse(to)
+            .ok_or_else(|| crate::Error::InvalidAddress(String::from(to)))?;
+
+        let message_id = message_id(&from.domain);
+
+        let email = MailBuilder::new()
+            .date(Local::now().into())
+            .from(from.clone())
+            .to(recipient.clone())
+            .subject(&subject)
+            .message_id(message_id.clone())
+            .text(&text)
+            .html(&html)
+            .build();
+
+        let envelope = Envelope::new(
+            Some(EmailAddress::new(from.address())?),
+            vec![EmailAddress::new(recipient.address())?],
+        )?;
 
         let mut client = SmtpClient::new_simple(&self.server)
             .expect("Failed to create smtp client")
             .credentials(Credentials::new(self.username.clone(), self.password.clone()))
             .transport();
 
-        client.send(email.into())?;
+        client.send(SendableEmail::new(envelope, message_id.0, email.to_bytes()))?;
 
         Ok(())
 
@@ -79,26 +79,47 @@ pub enum Error {
     /// An argument is missing in a form.
     MissingArgumentInForm(String),
 
+    /// An argument of a form has an invalid value.
+    InvalidArgumentInForm(String),
+
+    /// A mail had to be sent, but the user has no SMTP account.
+    NoSmtpAccount,
+
+    /// An invitation couldn't be answered, with the reason why.
+    InvitationError(String),
+
+    /// An error occured while unsubscribing from a mailing list.
+    UnsubscribeError(String),
+
+    /// Gpg failed to import a key, or to decrypt, sign or encrypt a mail, with its message.
+    PgpError(String),
+
     /// An error occured while computing some bcrypt hash.
     BcryptError(BcryptError),
 
     /// An I/O error occured.
     IoError(io::Error),
 
+    /// The random number generator of the system couldn't be used.
+    RandomError(rand::Error),
+
     /// An error occured while establishing TLS connection.
     TlsError(native_tls::Error),
 
     /// An error occured during an IMAP communication.
     ImapError(imap::error::Error),
 
-    // /// An error occured while parsing the content of an email.
-    // ParseEmailError(nom_mail_parser::Error),
+    /// An error occured while parsing the content of an email.
+    ParseEmailError(nom_mail_parser::ParseError),
 
     /// An error occured during a serde operation.
     SerdeJsonError(serde_json::error::Error),
 
     /// An error occured while trying to create a mail.
-    MailError(failure::Error),
+    MailError(lettre::error::Error),
+
+    /// An email address couldn't be parsed.
+    InvalidAddress(String),
 
     /// An error occured while trying to send a mail.
     SendMailError(lettre::smtp::error::Error),
@@ -111,11 +132,12 @@ impl_from_error!(Error, Error::DatabaseConnectionError, diesel::ConnectionError)
 impl_from_error!(Error, Error::DatabaseRequestError, diesel::result::Error);
 impl_from_error!(Error, Error::BcryptError, BcryptError);
 impl_from_error!(Error, Error::IoError, io::Error);
+impl_from_error!(Error, Error::RandomError, rand::Error);
 impl_from_error!(Error, Error::ImapError, imap::error::Error);
 impl_from_error!(Error, Error::TlsError, native_tls::Error);
 impl_from_error!(Error, Error::SerdeJsonError, serde_json::error::Error);
-// impl_from_error!(Error, Error::ParseEmailError, mailbox::mail::Error);
-impl_from_error!(Error, Error::MailError, failure::Error);
+impl_from_error!(Error, Error::ParseEmailError, nom_mail_parser::ParseError);
+impl_from_error!(Error, Error::MailError, lettre::error::Error);
 impl_from_error!(Error, Error::SendMailError, lettre::smtp::error::Error);
 impl_from_error!(Error, Error::TeraError, tera::Error);
 
@@ -140,6 +162,14 @@ pub fn start() -> LaunchError {
             routes::imap_account::add_imap_account,
             routes::imap_account::fetch_mailboxes,
             routes::imap_account::fetch_subjects,
+            routes::imap_account::fetch_conversations,
+            routes::imap_account::fetch_mail,
+            routes::imap_account::fetch_inline,
+            routes::imap_account::allow_remote_content,
+            routes::imap_account::rsvp,
+            routes::imap_account::unsubscribe,
+            routes::imap_account::send_mail,
+            routes::pgp::add_pgp_key,
         ])
         .launch()
 }
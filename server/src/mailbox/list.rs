@@ -0,0 +1,254 @@
+//! This module contains the unsubscription from the mailing lists, either by sending a mail to the
+//! list or by sending a one-click request to its website (RFC 2369 and RFC 8058).
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use chrono::Local;
+use nom_mail_parser::{Address, Headers, MailBuilder};
+
+use crate::{Error, Result};
+use crate::auth::remote_account::SmtpAccount;
+use crate::utils::{has_control_chars, message_id, percent_decode};
+
+/// The body of the one-click unsubscription requests, as specified by RFC 8058.
+const ONE_CLICK_BODY: &str = "List-Unsubscribe=One-Click";
+
+/// The time after which the one-click unsubscription requests are given up if the website doesn't
+/// answer.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize)]
+/// The mailing list that sent a mail, so that the client can group the mails of the newsletters.
+pub struct MailingList {
+    /// The identifier of the list, e.g. `news.example.com`.
+    id: String,
+
+    /// The description of the list, if any.
+    name: Option<String>,
+
+    /// Whether the list can be unsubscribed from.
+    unsubscribe: bool,
+}
+
+impl MailingList {
+    /// Returns the mailing list that sent a mail, if any.
+    pub fn new(headers: &Headers) -> Option<MailingList> {
+        headers.list_id().map(|list_id| MailingList {
+            id: list_id.id.clone(),
+            name: list_id.description.clone(),
+            unsubscribe: headers.list_unsubscribe().map(|x| !x.is_empty()).unwrap_or(false),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+/// The result of an unsubscription, sent back to the client.
+pub struct Unsubscription {
+    /// The URL that the user has to open to finish unsubscribing, when the list can't be
+    /// unsubscribed from automatically.
+    open: Option<String>,
+}
+
+/// Returns the rest of a URL if it starts with this scheme, whose case is ignored.
+fn strip_scheme<'a>(url: &'a str, scheme: &str) -> Option<&'a str> {
+    match url.get(..scheme.len()) {
+        Some(x) if x.eq_ignore_ascii_case(scheme) => Some(&url[scheme.len()..]),
+        _ => None,
+    }
+}
+
+/// Splits the authority of an HTTPS URL into its host, without the brackets of the IPv6
+/// addresses, and its port, ignoring the user info.
+fn parse_authority(authority: &str) -> Option<(&str, u16)> {
+    let host_port = authority.rsplit('@').next().unwrap_or(authority);
+
+    let (host, port) = if host_port.starts_with('[') {
+        let end = host_port.find(']')?;
+        (&host_port[1..end], &host_port[end + 1..])
+    } else {
+        match host_port.find(':') {
+            Some(index) => (&host_port[..index], &host_port[index..]),
+            None => (host_port, ""),
+        }
+    };
+
+    let port = match port {
+        "" | ":" => 443,
+        _ => port.strip_prefix(':')?.parse().ok()?,
+    };
+
+    if host.is_empty() {
+        None
+    } else {
+        Some((host, port))
+    }
+}
+
+/// Checks if an address can be reached on the internet, so that the mailing lists can't make the
+/// server send requests to itself or to its local network.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+
+            !(ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified()
+                || ip.is_broadcast() || ip.is_multicast() || ip.is_documentation()
+                // The shared address space of the carrier-grade NATs, the IETF protocol
+                // assignments, the benchmarking networks, and the reserved addresses.
+                || (a == 100 && b & 0xc0 == 64) || (a == 192 && b == 0 && c == 0)
+                || (a == 198 && b & 0xfe == 18) || a == 0 || a >= 240)
+        },
+
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(ip));
+            }
+
+            let first = ip.segments()[0];
+
+            // The unique local addresses are in fc00::/7, and the link-local ones in fe80::/10.
+            !(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast()
+                || first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80)
+        },
+    }
+}
+
+/// Sends a one-click unsubscription request to an HTTPS URL.
+///
+/// The redirections are not followed, as required by RFC 8058. Since the URL comes from the mail,
+/// only the default port of HTTPS and the public addresses are allowed, and the request times out
+/// if the website doesn't answer.
+fn post_one_click(url: &str) -> Result<()> {
+    let invalid = || Error::UnsubscribeError(url.to_string());
+    let rest = strip_scheme(url, "https://").ok_or_else(invalid)?;
+
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(index) if rest[index..].starts_with('?') => (&rest[..index], format!("/{}", &rest[index..])),
+        Some(index) => (&rest[..index], rest[index..].to_string()),
+        None => (rest, String::from("/")),
+    };
+
+    let (host, port) = parse_authority(authority).ok_or_else(invalid)?;
+
+    if port != 443 {
+        return Err(invalid());
+    }
+
+    // The address is checked once resolved, and the connection is made to the checked address.
+    let address = (host, port)
+        .to_socket_addrs()?
+        .find(|x| is_public(x.ip()))
+        .ok_or_else(invalid)?;
+
+    let stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let tls = native_tls::TlsConnector::builder().build()?;
+    let mut stream = tls
+        .connect(host, stream)
+        .map_err(|e| Error::UnsubscribeError(e.to_string()))?;
+
+    // The IPv6 addresses keep their brackets in the host header.
+    let host_header = if host.contains(':') { format!("[{}]", host) } else { host.to_string() };
+
+    let request = format!(
+        "POST {} HTTP/1.1\r\n\
+         Host: {}\r\n\
+         Content-Type: application/x-www-form-urlencoded\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {}",
+        path, host_header, ONE_CLICK_BODY.len(), ONE_CLICK_BODY,
+    );
+
+    stream.write_all(request.as_bytes())?;
+
+    // Only the status line of the response matters, and the body may not be text.
+    let mut status = vec![];
+    BufReader::new(stream).take(1024).read_until(b'\n', &mut status)?;
+    let status = String::from_utf8_lossy(&status);
+
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(Error::UnsubscribeError(status.trim().to_string())),
+    }
+}
+
+/// Sends an unsubscription mail to a `mailto:` URL through an smtp account.
+///
+/// The subject and the body of the mail are taken from the URL, and are `unsubscribe` by default.
+fn send_mail(url: &str, smtp_account: &SmtpAccount) -> Result<()> {
+    let rest = strip_scheme(url, "mailto:").ok_or_else(|| Error::UnsubscribeError(url.to_string()))?;
+
+    let (to, query) = match rest.split_once('?') {
+        Some((to, query)) => (to, query),
+        None => (rest, ""),
+    };
+
+    let to = percent_decode(to);
+
+    // The URL comes from the mail, and must not add headers to the mail sent by the user.
+    if has_control_chars(&to) {
+        return Err(Error::InvalidAddress(to));
+    }
+
+    let to = Address::parse(&to).ok_or(Error::InvalidAddress(to))?;
+    let mut subject = String::from("unsubscribe");
+    let mut body = String::from("unsubscribe");
+
+    for parameter in query.split('&') {
+        match parameter.split_once('=') {
+            Some((name, value)) if name.eq_ignore_ascii_case("subject") => subject = percent_decode(value),
+            Some((name, value)) if name.eq_ignore_ascii_case("body") => body = percent_decode(value),
+            _ => (),
+        }
+    }
+
+    if has_control_chars(&subject) {
+        return Err(Error::UnsubscribeError(url.to_string()));
+    }
+
+    let from = smtp_account.address()?;
+
+    let mail = MailBuilder::new()
+        .date(Local::now().into())
+        .from(from.clone())
+        .to(to)
+        .subject(&subject)
+        .message_id(message_id(&from.domain))
+        .text(&body)
+        .build();
+
+    smtp_account.send(&mail)
+}
+
+/// Unsubscribes from the mailing list that sent a mail, given the headers of the mail.
+///
+/// The one-click requests are preferred, then the mails, which need an smtp account. If the list
+/// can only be unsubscribed from on its website, the URL of the website is returned so that the
+/// user can open it.
+pub fn unsubscribe(headers: &Headers, smtp_account: Option<&SmtpAccount>) -> Result<Unsubscription> {
+    let urls = headers.list_unsubscribe().unwrap_or_default();
+    let https = urls.iter().find(|x| strip_scheme(x, "https://").is_some());
+    let mailto = urls.iter().find(|x| strip_scheme(x, "mailto:").is_some());
+
+    if let (Some(url), true) = (https, headers.is_one_click_unsubscribe()) {
+        post_one_click(url)?;
+        return Ok(Unsubscription { open: None });
+    }
+
+    if let (Some(url), Some(smtp_account)) = (mailto, smtp_account) {
+        send_mail(url, smtp_account)?;
+        return Ok(Unsubscription { open: None });
+    }
+
+    match urls.iter().find(|x| strip_scheme(x, "http://").is_some() || strip_scheme(x, "https://").is_some()) {
+        Some(url) => Ok(Unsubscription { open: Some(url.clone()) }),
+        None if mailto.is_some() => Err(Error::NoSmtpAccount),
+        None => Err(Error::UnsubscribeError(String::from("no unsubscribe URL"))),
+    }
+}
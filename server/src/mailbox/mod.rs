@@ -1,6 +1,16 @@
 //! This module contains all the structures for the mail boxes.
 
+pub mod remote_content;
+pub mod list;
+pub mod pgp;
+
 use imap::types::Name;
+use nom_mail_parser::{calendar, flowed, html, report, Body, Headers, Mail, Thread};
+use nom_mail_parser::calendar::Time;
+
+use crate::utils::percent_encode;
+use crate::mailbox::list::MailingList;
+use crate::mailbox::pgp::Security;
 
 #[derive(Serialize, Deserialize)]
 /// A mailbox from an IMAP account.
@@ -20,3 +30,299 @@ impl From<&Name> for Mailbox {
         }
     }
 }
+
+/// The maximum number of chars of the previews of the mails.
+const SNIPPET_LENGTH: usize = 200;
+
+#[derive(Serialize, Deserialize)]
+/// A mail in the list of the mails of a mailbox.
+pub struct Summary {
+    /// The subject of the mail, if any.
+    subject: Option<String>,
+
+    /// A preview of the text of the mail, if it has some text.
+    snippet: Option<String>,
+
+    /// The mailing list that sent the mail, if any.
+    list: Option<MailingList>,
+}
+
+impl Summary {
+    /// Creates the summary of a mail, which may have only the beginning of its body.
+    pub fn new(mail: &Mail) -> Summary {
+        Summary {
+            subject: mail.subject().cloned(),
+            snippet: mail.snippet(SNIPPET_LENGTH),
+            list: MailingList::new(mail.headers()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+/// A conversation, which is a tree of mails.
+pub struct Conversation {
+    /// The subject of the mail, or `None` if the mail is missing or has no subject.
+    subject: Option<String>,
+
+    /// The replies to the mail.
+    children: Vec<Conversation>,
+}
+
+impl Conversation {
+    /// Creates a conversation from a thread and the headers of the mails that were threaded.
+    pub fn new(thread: &Thread, headers: &[Headers]) -> Conversation {
+        Conversation {
+            subject: thread.message.and_then(|i| headers[i].subject().cloned()),
+            children: thread.children.iter().map(|t| Conversation::new(t, headers)).collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+/// A paragraph of the plain text of a mail, that the client can reflow.
+pub struct Paragraph {
+    /// The number of quote markers before the paragraph.
+    quote_depth: usize,
+
+    /// The text of the paragraph.
+    text: String,
+}
+
+#[derive(Serialize, Deserialize)]
+/// A mail forwarded as an attachment of another mail, that the client shows as a block which opens
+/// the forwarded mail.
+pub struct Forwarded {
+    /// The subject of the forwarded mail, if any.
+    subject: Option<String>,
+
+    /// The first author of the forwarded mail, with its display name, if any.
+    from: Option<String>,
+
+    /// The date of the forwarded mail in the RFC 3339 format, if it is valid.
+    date: Option<String>,
+}
+
+impl Forwarded {
+    /// Creates the block of a forwarded mail.
+    pub fn new(mail: &Mail) -> Forwarded {
+        Forwarded {
+            subject: mail.subject().cloned(),
+            from: mail.headers().from().and_then(|x| x.first()).map(|x| x.to_string()),
+            date: mail.headers().date().and_then(|x| x.date_time()).map(|x| x.to_rfc3339()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+/// What happened for one of the recipients of a mail, when the mail is a delivery status
+/// notification, that the client shows as an undeliverable summary.
+pub struct DeliveryStatus {
+    /// The address of the recipient.
+    recipient: String,
+
+    /// The action that the mail server performed, e.g. `failed` or `delayed`.
+    action: String,
+
+    /// The status code of the delivery, e.g. `5.1.1`, if it is valid.
+    status: Option<String>,
+
+    /// The message of the mail server that rejected the mail, if any.
+    diagnostic: Option<String>,
+
+    /// Whether the mail will never reach the recipient, so that its address is flagged as bounced.
+    failed: bool,
+}
+
+impl From<&report::RecipientStatus> for DeliveryStatus {
+    fn from(status: &report::RecipientStatus) -> DeliveryStatus {
+        DeliveryStatus {
+            recipient: status.final_recipient.clone(),
+            action: status.action.name().to_string(),
+            status: status.status.map(|x| x.to_string()),
+            diagnostic: status.diagnostic.clone(),
+            failed: status.is_failed(),
+        }
+    }
+}
+
+/// Formats the start or the end of an event in the RFC 3339 format, without offset for the times
+/// that are in a time zone.
+fn format_time(time: &Time) -> String {
+    match time {
+        Time::Date(date) => date.format("%Y-%m-%d").to_string(),
+        Time::Utc(time) => time.to_rfc3339(),
+        Time::Local { time, .. } => time.format("%Y-%m-%dT%H:%M:%S").to_string(),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+/// An attendee or the organizer of an event.
+pub struct EventAttendee {
+    /// The address of the attendee.
+    address: String,
+
+    /// The name of the attendee, if any.
+    name: Option<String>,
+
+    /// The answer of the attendee to the invitation, e.g. `ACCEPTED`.
+    status: String,
+}
+
+impl From<&calendar::Attendee> for EventAttendee {
+    fn from(attendee: &calendar::Attendee) -> EventAttendee {
+        EventAttendee {
+            address: attendee.address.clone(),
+            name: attendee.name.clone(),
+            status: attendee.status.name().to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+/// An event of the calendar of a mail, such as a meeting invitation, that the client shows with
+/// the buttons to answer it.
+pub struct Event {
+    /// The method of the calendar, e.g. `REQUEST` for an invitation or `CANCEL` for a cancellation.
+    method: Option<String>,
+
+    /// The title of the event, if any.
+    title: Option<String>,
+
+    /// The start of the event, if it is valid.
+    start: Option<String>,
+
+    /// The end of the event, if it is valid.
+    end: Option<String>,
+
+    /// The time zone of the start of the event, e.g. `Europe/Paris`, if any.
+    timezone: Option<String>,
+
+    /// The location of the event, if any.
+    location: Option<String>,
+
+    /// The organizer of the event, if any.
+    organizer: Option<EventAttendee>,
+
+    /// The attendees of the event.
+    attendees: Vec<EventAttendee>,
+}
+
+impl Event {
+    /// Creates an event from the event of a calendar, and the method of the calendar.
+    pub fn new(event: &calendar::Event, method: Option<&calendar::Method>) -> Event {
+        let timezone = match &event.start {
+            Some(Time::Local { timezone, .. }) => timezone.clone(),
+            Some(Time::Utc(_)) => Some(String::from("UTC")),
+            _ => None,
+        };
+
+        Event {
+            method: method.map(|x| x.name().to_string()),
+            title: event.summary.clone(),
+            start: event.start.as_ref().map(format_time),
+            end: event.end.as_ref().map(format_time),
+            timezone,
+            location: event.location.clone(),
+            organizer: event.organizer.as_ref().map(EventAttendee::from),
+            attendees: event.attendees.iter().map(EventAttendee::from).collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+/// The content of a mail, as it is displayed by the client.
+pub struct MailContent {
+    /// The subject of the mail, if any.
+    subject: Option<String>,
+
+    /// The paragraphs of the plain text version of the mail, converted from its HTML version if
+    /// there is no plain text version.
+    text: Vec<Paragraph>,
+
+    /// The sanitized HTML version of the mail, if any.
+    html: Option<String>,
+
+    /// The number of remote images and CSS references that were blocked in the HTML version.
+    blocked: usize,
+
+    /// The mails forwarded as attachments, in the order in which they can be opened.
+    forwarded: Vec<Forwarded>,
+
+    /// The status of the recipients, if the mail is a delivery status notification.
+    delivery: Vec<DeliveryStatus>,
+
+    /// The events of the calendar of the mail, such as a meeting invitation.
+    events: Vec<Event>,
+
+    /// Whether the mail is encrypted, and the status of its signature.
+    security: Security,
+}
+
+impl MailContent {
+    /// Extracts the content of a mail, given the mailbox and the sequence number of the mail, which
+    /// are used in the URLs of its inline attachments.
+    ///
+    /// The remote content of the HTML version is blocked unless `allow_remote` is true, and the
+    /// security of the mail is the one found when it was opened with the keys of the user.
+    ///
+    /// The inline images of the decrypted mails are embedded in the HTML version as data URLs,
+    /// since they can't be fetched without the passphrase of the user, which is not stored.
+    pub fn new(mail: &Mail, inbox: &str, index: usize, allow_remote: bool, security: Security) -> MailContent {
+        let paragraphs = mail
+            .part("text", "plain")
+            .and_then(Mail::paragraphs)
+            .or_else(|| mail.plain_text().map(|x| flowed::decode_fixed(&x)))
+            .unwrap_or_default();
+
+        let embedded = |content_id: &str| {
+            if !security.decrypted() {
+                return None;
+            }
+
+            let part = mail.inline_part(content_id)?;
+            let content_type = part.headers().content_type().filter(|x| x.main_type.eq_ignore_ascii_case("image"))?;
+
+            match part.body() {
+                Body::Content(content) => {
+                    Some(format!("data:{};base64,{}", content_type.mime_type(), base64::encode(content)))
+                },
+                _ => None,
+            }
+        };
+
+        let inline_url = |content_id: &str| match embedded(content_id) {
+            Some(url) => url,
+            None => format!(
+                "/api/get-inline?inbox={}&index={}&cid={}",
+                percent_encode(inbox),
+                index,
+                percent_encode(content_id),
+            ),
+        };
+
+        let sanitized = mail
+            .part("text", "html")
+            .and_then(Mail::text)
+            .map(|x| html::sanitize(&x, inline_url, allow_remote));
+
+        MailContent {
+            subject: mail.subject().cloned(),
+            text: paragraphs
+                .into_iter()
+                .map(|p| Paragraph { quote_depth: p.quote_depth, text: p.text })
+                .collect(),
+            blocked: sanitized.as_ref().map(|x| x.blocked).unwrap_or(0),
+            html: sanitized.map(|x| x.html),
+            forwarded: mail.attachments().filter_map(|x| x.message).map(Forwarded::new).collect(),
+            delivery: mail
+                .delivery_report()
+                .map(|x| x.recipients.iter().map(DeliveryStatus::from).collect())
+                .unwrap_or_default(),
+            events: mail
+                .calendar()
+                .map(|x| x.events.iter().map(|e| Event::new(e, x.method.as_ref())).collect())
+                .unwrap_or_default(),
+            security,
+        }
+    }
+}
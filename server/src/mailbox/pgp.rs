@@ -0,0 +1,447 @@
+//! This module contains the OpenPGP keys of the users, and the verification, the decryption, the
+//! signature and the encryption of their mails (RFC 3156).
+//!
+//! The cryptography is done by the `gpg` command, in a temporary home directory that only contains
+//! the keys of one user and that is removed afterwards, so that the keys are only stored in the
+//! database. The secret keys stay protected by the passphrases of their users, which are never
+//! stored and must be given for each decryption or signature.
+
+use std::ffi::OsStr;
+use std::fs::{self, DirBuilder};
+use std::io::Write;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+use diesel::prelude::*;
+use diesel::pg::PgConnection;
+use rand::Rng;
+use rand::rngs::OsRng;
+use rand::distributions::Alphanumeric;
+use nom_mail_parser::{borrowed, parse_lenient, Address, Mail};
+use nom_mail_parser::pgp::{self, Protection, Signed};
+
+use crate::{Error, Result};
+use crate::schema::pgp_keys;
+use crate::auth::user::User;
+
+/// The hash algorithm of the signatures, as it appears in the micalg parameter of the signed
+/// mails.
+pub const MICALG: &str = "pgp-sha256";
+
+/// An armored OpenPGP key of a user.
+#[derive(Identifiable, Queryable, Associations, PartialEq, Debug)]
+#[belongs_to(User)]
+pub struct PgpKey {
+    /// The id of the key.
+    pub id: i32,
+
+    /// The user that owns the key.
+    pub user_id: i32,
+
+    /// The armored key.
+    pub armored: String,
+
+    /// Whether the key is the secret key of the user, rather than the public key of one of its
+    /// correspondents.
+    pub secret: bool,
+}
+
+impl PgpKey {
+    /// Creates a new key that is not stored in the database yet.
+    pub fn create(user_id: i32, armored: &str, secret: bool) -> NewPgpKey {
+        NewPgpKey {
+            user_id,
+            armored: armored.to_string(),
+            secret,
+        }
+    }
+
+    /// Fetches all the keys of a user, which are its public keyring and its secret key.
+    pub fn from_user_id(user: i32, db: &PgConnection) -> Result<Vec<PgpKey>> {
+        use crate::schema::pgp_keys::dsl::*;
+
+        Ok(pgp_keys
+            .filter(user_id.eq(user))
+            .get_results::<PgpKey>(db)?)
+    }
+}
+
+/// A new key not stored in the database yet.
+#[derive(Debug, Insertable)]
+#[table_name = "pgp_keys"]
+pub struct NewPgpKey {
+    /// The user that owns the key.
+    pub user_id: i32,
+
+    /// The armored key.
+    pub armored: String,
+
+    /// Whether the key is the secret key of the user.
+    pub secret: bool,
+}
+
+impl NewPgpKey {
+    /// Saves the key into the database.
+    ///
+    /// A user has only one secret key, which replaces the previous one.
+    pub fn save(&self, db: &PgConnection) -> Result<()> {
+        if self.secret {
+            let secret_keys = pgp_keys::table
+                .filter(pgp_keys::user_id.eq(self.user_id))
+                .filter(pgp_keys::secret.eq(true));
+
+            diesel::delete(secret_keys).execute(db)?;
+        }
+
+        diesel::insert_into(pgp_keys::table)
+            .values(self)
+            .execute(db)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+/// The status of the signature of a mail.
+pub enum SignatureStatus {
+    /// The signature is valid, and was made by a key of the keyring of the user that belongs to
+    /// the sender of the mail.
+    Valid,
+
+    /// The signature is valid, but was made by a key of the keyring of the user that doesn't belong
+    /// to the sender of the mail.
+    OtherKey,
+
+    /// The key that made the signature is not in the keyring of the user.
+    UnknownKey,
+
+    /// The signature doesn't match the mail, or its key is expired or revoked.
+    Bad,
+
+    /// The mail couldn't be decrypted, so it is not known whether it is signed.
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+/// The OpenPGP protection of a mail, that the client shows along with the mail.
+pub struct Security {
+    /// Whether the mail is encrypted.
+    encrypted: bool,
+
+    /// Whether the mail was decrypted, which requires the passphrase of the user.
+    decrypted: bool,
+
+    /// The status of the signature of the mail, if it is signed.
+    signature: Option<SignatureStatus>,
+
+    /// The message of gpg, if the mail couldn't be decrypted.
+    error: Option<String>,
+}
+
+impl Security {
+    /// Returns whether the mail was decrypted.
+    pub fn decrypted(&self) -> bool {
+        self.decrypted
+    }
+}
+
+/// A temporary home directory for `gpg`, that contains the keys of a user and is removed when it is
+/// dropped.
+pub struct Keyring {
+    /// The path of the directory.
+    home: PathBuf,
+}
+
+impl Keyring {
+    /// Creates a temporary home directory and imports some keys into it.
+    pub fn new(keys: &[PgpKey]) -> Result<Keyring> {
+        let mut rng = OsRng::new()?;
+        let name = rng.sample_iter(&Alphanumeric).take(20).collect::<String>();
+        let home = std::env::temp_dir().join(format!("chouette-gpg-{}", name));
+
+        // Only the server may read the keys.
+        DirBuilder::new().mode(0o700).create(&home)?;
+        let keyring = Keyring { home };
+
+        // The passphrases are not cached by the agent, so that each of them is checked.
+        keyring.file("gpg-agent.conf", b"default-cache-ttl 0\nmax-cache-ttl 0\n")?;
+
+        for key in keys {
+            keyring.import(&key.armored)?;
+        }
+
+        Ok(keyring)
+    }
+
+    /// Imports an armored key into the keyring, which fails if the key is not valid.
+    pub fn import(&self, armored: &str) -> Result<()> {
+        self.run(&[OsStr::new("--import")], armored.as_bytes())?;
+        Ok(())
+    }
+
+    /// Writes a file in the home directory, and returns its path.
+    fn file(&self, name: &str, content: &[u8]) -> Result<PathBuf> {
+        let path = self.home.join(name);
+        fs::write(&path, content)?;
+        Ok(path)
+    }
+
+    /// Runs `gpg` on the home directory with some arguments, writing some input to it.
+    ///
+    /// The input should be short, since it is written before the output is read, and the large data
+    /// is given as files.
+    fn command(&self, args: &[&OsStr], input: &[u8]) -> Result<Output> {
+        let mut child = Command::new("gpg")
+            .arg("--homedir")
+            .arg(&self.home)
+            .args(&["--batch", "--no-tty", "--quiet", "--pinentry-mode", "loopback"])
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // The input is closed once it is written, so that gpg stops waiting for it.
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(input)?;
+        }
+
+        Ok(child.wait_with_output()?)
+    }
+
+    /// Runs `gpg` like `command`, and returns its output if it succeeded.
+    fn run(&self, args: &[&OsStr], input: &[u8]) -> Result<Vec<u8>> {
+        let output = self.command(args, input)?;
+
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            Err(Error::PgpError(String::from_utf8_lossy(&output.stderr).trim().to_string()))
+        }
+    }
+
+    /// Returns the addresses of the user ids of a key of the keyring, given its fingerprint.
+    fn addresses(&self, fingerprint: &str) -> Result<Vec<String>> {
+        let args = [OsStr::new("--with-colons"), OsStr::new("--list-keys"), OsStr::new(fingerprint)];
+        let output = self.run(&args, b"")?;
+
+        // The user ids are the tenth field of the `uid` lines, e.g. `John Doe <john@example.com>`.
+        Ok(String::from_utf8_lossy(&output)
+            .lines()
+            .filter(|x| x.starts_with("uid:"))
+            .filter_map(|x| x.split(':').nth(9))
+            .map(|x| match (x.rfind('<'), x.rfind('>')) {
+                (Some(start), Some(end)) if start < end => x[start + 1 .. end].to_string(),
+                _ => x.trim().to_string(),
+            })
+            .collect())
+    }
+
+    /// Verifies the signature of a signed mail with the public keys of the keyring.
+    ///
+    /// The signature is only valid if one of the user ids of its key has the address of the sender
+    /// of the mail, otherwise any correspondent of the user could sign mails in the name of others.
+    pub fn verify(&self, signed: &Signed, sender: Option<&str>) -> Result<SignatureStatus> {
+        let signature = self.file("signature.asc", &signed.signature)?;
+        let content = self.file("content", &signed.content)?;
+
+        let args = ["--status-fd", "1", "--verify"].iter().map(OsStr::new);
+        let args = args.chain(vec![signature.as_os_str(), content.as_os_str()]).collect::<Vec<_>>();
+        let output = self.command(&args, b"")?;
+
+        // The status lines look like `[GNUPG:] GOODSIG <key id> <user id>`.
+        let status = String::from_utf8_lossy(&output.stdout);
+        let lines = status
+            .lines()
+            .filter_map(|x| x.strip_prefix("[GNUPG:] "))
+            .map(|x| x.split_whitespace().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        let has = |keyword| lines.iter().any(|x| x.first() == Some(&keyword));
+
+        // The last field of `VALIDSIG` is the fingerprint of the primary key, which has the user
+        // ids, even if the signature was made by a subkey.
+        let fingerprint = lines
+            .iter()
+            .find(|x| x.first() == Some(&"VALIDSIG"))
+            .and_then(|x| x.last().filter(|_| x.len() > 2));
+
+        if has("BADSIG") {
+            return Ok(SignatureStatus::Bad);
+        } else if has("NO_PUBKEY") {
+            return Ok(SignatureStatus::UnknownKey);
+        }
+
+        let fingerprint = match fingerprint {
+            Some(fingerprint) if has("GOODSIG") => fingerprint,
+            _ => return Ok(SignatureStatus::Bad),
+        };
+
+        let addresses = self.addresses(fingerprint)?;
+
+        Ok(match sender {
+            Some(sender) if addresses.iter().any(|x| x.eq_ignore_ascii_case(sender)) => SignatureStatus::Valid,
+            _ => SignatureStatus::OtherKey,
+        })
+    }
+
+    /// Decrypts some armored data with the secret key of the keyring and its passphrase.
+    pub fn decrypt(&self, data: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+        let data = self.file("encrypted.asc", data)?;
+        let args = ["--passphrase-fd", "0", "--output", "-", "--decrypt"].iter().map(OsStr::new);
+        self.run(&args.chain(Some(data.as_os_str())).collect::<Vec<_>>(), passphrase.as_bytes())
+    }
+
+    /// Makes an armored detached signature of some content with the secret key of the keyring and
+    /// its passphrase.
+    pub fn sign(&self, content: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+        let content = self.file("content", content)?;
+
+        let args = ["--passphrase-fd", "0", "--digest-algo", "SHA256", "--armor", "--output", "-", "--detach-sign"]
+            .iter()
+            .map(OsStr::new);
+
+        self.run(&args.chain(Some(content.as_os_str())).collect::<Vec<_>>(), passphrase.as_bytes())
+    }
+
+    /// Encrypts some content for some recipients, given their addresses, with the public keys of
+    /// the keyring.
+    ///
+    /// The keys of the keyring were added by the user, so they are trusted.
+    pub fn encrypt(&self, content: &[u8], recipients: &[String]) -> Result<Vec<u8>> {
+        let content = self.file("content", content)?;
+        // The keys are never searched outside of the keyring, such as on the web.
+        let mut args = ["--auto-key-locate", "clear,local", "--trust-model", "always", "--armor", "--output", "-"]
+            .iter()
+            .map(OsStr::new)
+            .collect::<Vec<_>>();
+
+        for recipient in recipients {
+            args.push(OsStr::new("--recipient"));
+            args.push(OsStr::new(recipient));
+        }
+
+        args.push(OsStr::new("--encrypt"));
+        args.push(content.as_os_str());
+
+        self.run(&args, b"")
+    }
+}
+
+impl Drop for Keyring {
+    fn drop(&mut self) {
+        // The agent that gpg started for this home directory is stopped before it is removed.
+        let _ = Command::new("gpgconf")
+            .arg("--homedir")
+            .arg(&self.home)
+            .args(&["--kill", "gpg-agent"])
+            .output();
+
+        let _ = fs::remove_dir_all(&self.home);
+    }
+}
+
+/// Replaces the headers that describe the content of a mail, and its content, by the ones of its
+/// decrypted part, so that the decrypted mail keeps its subject and its recipients.
+fn replace_content(raw: &[u8], decrypted: &[u8]) -> Vec<u8> {
+    let mut mail = vec![];
+    let mut keep = true;
+
+    for line in raw.split(|c| *c == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        if line.is_empty() {
+            break;
+        }
+
+        // The folded lines belong to the previous header.
+        if !line.starts_with(b" ") && !line.starts_with(b"\t") {
+            keep = !line.get(..8).map(|x| x.eq_ignore_ascii_case(b"content-")).unwrap_or(false);
+        }
+
+        if keep {
+            mail.extend_from_slice(line);
+            mail.extend_from_slice(b"\r\n");
+        }
+    }
+
+    mail.extend_from_slice(decrypted);
+    mail
+}
+
+/// Opens a mail with the keys of a user: the mail is decrypted if it is encrypted and the
+/// passphrase of the user is given, and its signature is verified if it is signed.
+///
+/// The encrypted mails are returned as they are when the passphrase is not given or when they
+/// can't be decrypted, e.g. because the passphrase is wrong, so that the client can still show
+/// their headers and ask for the passphrase again.
+pub fn open(raw: &[u8], keys: &[PgpKey], passphrase: Option<&str>) -> Result<(Mail, Security)> {
+    let mut security = Security::default();
+    let parsed = borrowed::Mail::parse_lenient(raw);
+    let mut protection = parsed.protection();
+
+    // Gpg is only run for the protected mails.
+    if protection.is_none() {
+        return Ok((parse_lenient(raw), security));
+    }
+
+    // The sender is the one of the outer headers, which are kept when the mail is decrypted.
+    let sender = parsed.headers().from().and_then(|x| x.first().map(Address::address));
+
+    let keyring = Keyring::new(keys)?;
+    let mut mail = raw.to_vec();
+
+    if let Some(Protection::Encrypted(encrypted)) = &protection {
+        security.encrypted = true;
+
+        let decrypted = match passphrase.map(|x| keyring.decrypt(&encrypted.data, x)) {
+            Some(Ok(decrypted)) => decrypted,
+            Some(Err(Error::PgpError(message))) => {
+                security.signature = Some(SignatureStatus::Unknown);
+                security.error = Some(message);
+                return Ok((parse_lenient(raw), security));
+            },
+            Some(Err(e)) => return Err(e),
+            None => {
+                security.signature = Some(SignatureStatus::Unknown);
+                return Ok((parse_lenient(raw), security));
+            },
+        };
+
+        security.decrypted = true;
+
+        // The signed and encrypted mails are signed inside.
+        protection = borrowed::Mail::parse_lenient(&decrypted).protection();
+        mail = replace_content(raw, &decrypted);
+    }
+
+    if let Some(Protection::Signed(signed)) = &protection {
+        security.signature = Some(keyring.verify(signed, sender.as_deref())?);
+    }
+
+    Ok((parse_lenient(&mail), security))
+}
+
+/// Signs a mail with the secret key of a user if its passphrase is given, and encrypts it for some
+/// recipients if they are given.
+///
+/// The recipients are the addresses whose public keys are used, which should include the sender so
+/// that the sent copy can be read.
+pub fn protect(mail: Mail, keys: &[PgpKey], passphrase: Option<&str>, recipients: Option<&[String]>) -> Result<Mail> {
+    if passphrase.is_none() && recipients.is_none() {
+        return Ok(mail);
+    }
+
+    let keyring = Keyring::new(keys)?;
+    let sign = |content: &[u8]| keyring.sign(content, passphrase.unwrap_or_default());
+    let encrypt = |content: &[u8]| keyring.encrypt(content, recipients.unwrap_or_default());
+
+    match (passphrase, recipients) {
+        (Some(_), Some(_)) => pgp::sign_and_encrypt(mail, MICALG, sign, encrypt),
+        (Some(_), None) => pgp::sign(mail, MICALG, sign),
+        (None, Some(_)) => pgp::encrypt(mail, encrypt),
+        (None, None) => Ok(mail),
+    }
+}
@@ -0,0 +1,68 @@
+//! This module contains the senders whose mails may load remote content.
+
+use diesel::prelude::*;
+use diesel::pg::PgConnection;
+
+use crate::Result;
+use crate::schema::allowed_senders;
+use crate::auth::user::User;
+
+/// A sender whose mails may load remote content, such as images, for a user.
+#[derive(Identifiable, Queryable, Associations, PartialEq, Debug)]
+#[belongs_to(User)]
+pub struct AllowedSender {
+    /// The id of the allowed sender.
+    pub id: i32,
+
+    /// The user that allowed the sender.
+    pub user_id: i32,
+
+    /// The lowercase email address of the sender.
+    pub address: String,
+}
+
+impl AllowedSender {
+    /// Creates a new allowed sender that is not stored in the database yet.
+    pub fn create(user_id: i32, address: &str) -> NewAllowedSender {
+        NewAllowedSender {
+            user_id,
+            address: address.to_lowercase(),
+        }
+    }
+
+    /// Checks if a user allowed the mails of a sender to load remote content.
+    pub fn is_allowed(user: i32, sender: &str, db: &PgConnection) -> Result<bool> {
+        use crate::schema::allowed_senders::dsl::*;
+
+        let count = allowed_senders
+            .filter(user_id.eq(user))
+            .filter(address.eq(sender.to_lowercase()))
+            .count()
+            .get_result::<i64>(db)?;
+
+        Ok(count > 0)
+    }
+}
+
+/// A new allowed sender not stored in the database yet.
+#[derive(Debug, Insertable)]
+#[table_name = "allowed_senders"]
+pub struct NewAllowedSender {
+    /// The user that allows the sender.
+    pub user_id: i32,
+
+    /// The lowercase email address of the sender.
+    pub address: String,
+}
+
+impl NewAllowedSender {
+    /// Saves the allowed sender into the database, unless it was already allowed.
+    pub fn save(&self, db: &PgConnection) -> Result<()> {
+        diesel::insert_into(allowed_senders::table)
+            .values(self)
+            .on_conflict_do_nothing()
+            .execute(db)?;
+
+        Ok(())
+    }
+}
@@ -4,11 +4,19 @@
 use std::io::Cursor;
 use rocket::response::Response;
 use rocket::request::Form;
-use rocket::http::Cookies;
+use rocket::http::{ContentType, Cookies};
+use chrono::{Local, Utc};
+use nom_mail_parser::{calendar, Address, Body, Mail, MailBuilder};
+use nom_mail_parser::calendar::{Method, ParticipationStatus};
 
 use crate::{SERVER_CONFIG, Error, Result};
+use crate::utils::{has_control_chars, message_id};
 use crate::auth::session::Session;
-use crate::auth::remote_account::ImapAccount;
+use crate::auth::remote_account::{ImapAccount, SmtpAccount};
+use crate::mailbox::MailContent;
+use crate::mailbox::list;
+use crate::mailbox::pgp::{self, PgpKey};
+use crate::mailbox::remote_content::AllowedSender;
 
 #[derive(FromForm)]
 /// A struct that serves the purpose of verifying the form.
@@ -88,7 +96,8 @@ pub struct FetchSubjectsForm {
 }
 
 #[post("/get-subjects", data = "<form>")]
-/// A route that feches all the subjects of a mailbox of an IMAP account.
+/// A route that fetches the subjects of the mails of a mailbox of an IMAP account, with a preview
+/// of their text.
 pub fn fetch_subjects<'a>(mut cookies: Cookies, form: Form<FetchSubjectsForm>) -> Result<Response<'a>> {
     let session = cookies
         .get_private("EXAUTH")
@@ -107,9 +116,407 @@ pub fn fetch_subjects<'a>(mut cookies: Cookies, form: Form<FetchSubjectsForm>) -
         },
     };
 
-    let subjects = imap_account.fetch_subjects(&form.inbox, 1, 20)?;
+    let summaries = imap_account.fetch_summaries(&form.inbox, 1, 20)?;
 
     Ok(Response::build()
-        .sized_body(Cursor::new(serde_json::to_string(&subjects)?))
+        .sized_body(Cursor::new(serde_json::to_string(&summaries)?))
+        .finalize())
+}
+
+#[post("/get-conversations", data = "<form>")]
+/// A route that fetches the mails of a mailbox of an IMAP account, threaded into conversations.
+pub fn fetch_conversations<'a>(mut cookies: Cookies, form: Form<FetchSubjectsForm>) -> Result<Response<'a>> {
+    let session = cookies
+        .get_private("EXAUTH")
+        .ok_or(Error::SessionDoesNotExist)?;
+
+    let db = SERVER_CONFIG.database.connect()?;
+    let session = Session::from_secret(session.value(), &db)?;
+    let imap_accounts = ImapAccount::from_user_id(session.user_id, &db)?;
+
+    let imap_account = match imap_accounts.first() {
+        Some(x) => (x),
+        None => {
+            return Ok(Response::build()
+                .sized_body(Cursor::new(""))
+                .finalize())
+        },
+    };
+
+    let conversations = imap_account.fetch_conversations(&form.inbox, 1, 20)?;
+
+    Ok(Response::build()
+        .sized_body(Cursor::new(serde_json::to_string(&conversations)?))
+        .finalize())
+}
+
+#[derive(FromForm)]
+/// A struct that serves the purpose of verifying the fetch mail route.
+pub struct FetchMailForm {
+    /// The name of the mailbox of the mail.
+    inbox: String,
+
+    /// The sequence number of the mail in the mailbox.
+    index: usize,
+
+    /// Whether the remote content of this mail is loaded, even if its sender is not allowed.
+    remote: Option<bool>,
+
+    /// The position of a forwarded mail among the mails forwarded as attachments of the mail, to
+    /// open the forwarded mail instead.
+    forwarded: Option<usize>,
+
+    /// The passphrase of the secret key of the user, to decrypt the mail if it is encrypted.
+    passphrase: Option<String>,
+}
+
+#[post("/get-mail", data = "<form>")]
+/// A route that fetches the content of a mail of an IMAP account.
+pub fn fetch_mail<'a>(mut cookies: Cookies, form: Form<FetchMailForm>) -> Result<Response<'a>> {
+    let session = cookies
+        .get_private("EXAUTH")
+        .ok_or(Error::SessionDoesNotExist)?;
+
+    let db = SERVER_CONFIG.database.connect()?;
+    let session = Session::from_secret(session.value(), &db)?;
+    let imap_accounts = ImapAccount::from_user_id(session.user_id, &db)?;
+    let keys = PgpKey::from_user_id(session.user_id, &db)?;
+
+    let raw = match imap_accounts.first() {
+        Some(x) => x.fetch_raw_mail(&form.inbox, form.index)?,
+        None => None,
+    };
+
+    // The mail is decrypted and its signature is verified with the keys of the user.
+    let opened = raw
+        .map(|raw| pgp::open(&raw, &keys, form.passphrase.as_ref().map(String::as_str)))
+        .transpose()?;
+
+    // The forwarded mails are opened like the other mails, but from the mail that contains them,
+    // whose signature doesn't cover them.
+    let opened = opened.as_ref().and_then(|(mail, security)| match form.forwarded {
+        Some(forwarded) => mail.attachments().filter_map(|x| x.message).nth(forwarded).map(|x| (x, None)),
+        None => Some((mail, Some(security))),
+    });
+
+    let (mail, security) = match opened {
+        Some(opened) => opened,
+        None => {
+            return Ok(Response::build()
+                .sized_body(Cursor::new(""))
+                .finalize())
+        },
+    };
+
+    let sender = mail.headers().from().and_then(|x| x.first()).map(|x| x.address());
+
+    // The remote content is allowed for this mail, or for all the mails of its sender.
+    let allow_remote = match sender {
+        _ if form.remote == Some(true) => true,
+        Some(sender) => AllowedSender::is_allowed(session.user_id, &sender, &db)?,
+        None => false,
+    };
+
+    let security = security.cloned().unwrap_or_default();
+    let content = MailContent::new(mail, &form.inbox, form.index, allow_remote, security);
+
+    Ok(Response::build()
+        .sized_body(Cursor::new(serde_json::to_string(&content)?))
+        .finalize())
+}
+
+#[get("/get-inline?<inbox>&<index>&<cid>")]
+/// A route that fetches an inline attachment of a mail, such as an image of its HTML version.
+pub fn fetch_inline<'a>(mut cookies: Cookies, inbox: String, index: usize, cid: String) -> Result<Response<'a>> {
+    let session = cookies
+        .get_private("EXAUTH")
+        .ok_or(Error::SessionDoesNotExist)?;
+
+    let db = SERVER_CONFIG.database.connect()?;
+    let session = Session::from_secret(session.value(), &db)?;
+    let imap_accounts = ImapAccount::from_user_id(session.user_id, &db)?;
+
+    // The inline attachments of the decrypted mails are embedded in their content instead.
+    let mail = match imap_accounts.first() {
+        Some(x) => x.fetch_mail(&inbox, index)?,
+        None => None,
+    };
+
+    let part = mail.as_ref().and_then(|x| x.inline_part(&cid));
+
+    let (mime_type, content) = match part.map(|x| (x.headers().content_type(), x.body())) {
+        Some((content_type, Body::Content(content))) => {
+            (content_type.map(|x| x.mime_type()), content.clone())
+        },
+        _ => {
+            return Ok(Response::build()
+                .sized_body(Cursor::new(""))
+                .finalize())
+        },
+    };
+
+    let content_type = mime_type
+        .and_then(|x| ContentType::parse_flexible(&x))
+        .unwrap_or(ContentType::Binary);
+
+    Ok(Response::build()
+        .header(content_type)
+        .sized_body(Cursor::new(content))
+        .finalize())
+}
+
+#[derive(FromForm)]
+/// A struct that serves the purpose of verifying the allow remote content route.
+pub struct AllowRemoteContentForm {
+    /// The email address of the sender whose mails may load remote content.
+    sender: String,
+}
+
+#[post("/allow-remote-content", data = "<form>")]
+/// A route that allows the mails of a sender to load remote content.
+pub fn allow_remote_content<'a>(mut cookies: Cookies, form: Form<AllowRemoteContentForm>) -> Result<Response<'a>> {
+    let session = cookies
+        .get_private("EXAUTH")
+        .ok_or(Error::SessionDoesNotExist)?;
+
+    let db = SERVER_CONFIG.database.connect()?;
+    let session = Session::from_secret(session.value(), &db)?;
+
+    AllowedSender::create(session.user_id, &form.sender).save(&db)?;
+
+    Ok(Response::build()
+        .sized_body(Cursor::new(""))
+        .finalize())
+}
+
+#[derive(FromForm)]
+/// A struct that serves the purpose of verifying the rsvp route.
+pub struct RsvpForm {
+    /// The name of the mailbox of the invitation.
+    inbox: String,
+
+    /// The sequence number of the invitation in the mailbox.
+    index: usize,
+
+    /// The answer to the invitation, which is `accepted`, `tentative` or `declined`.
+    response: String,
+}
+
+#[post("/rsvp", data = "<form>")]
+/// A route that answers an invitation, by sending a reply to the organizer of the event through the
+/// SMTP account of the user.
+pub fn rsvp<'a>(mut cookies: Cookies, form: Form<RsvpForm>) -> Result<Response<'a>> {
+    let session = cookies
+        .get_private("EXAUTH")
+        .ok_or(Error::SessionDoesNotExist)?;
+
+    let db = SERVER_CONFIG.database.connect()?;
+    let session = Session::from_secret(session.value(), &db)?;
+
+    let (status, answer, verb) = match ParticipationStatus::parse(&form.response) {
+        ParticipationStatus::Accepted => (ParticipationStatus::Accepted, "Accepted", "accepted"),
+        ParticipationStatus::Tentative => (ParticipationStatus::Tentative, "Tentative", "tentatively accepted"),
+        ParticipationStatus::Declined => (ParticipationStatus::Declined, "Declined", "declined"),
+        _ => return Err(Error::InvalidArgumentInForm(String::from("response"))),
+    };
+
+    let imap_accounts = ImapAccount::from_user_id(session.user_id, &db)?;
+    let smtp_account = SmtpAccount::from_user_id(session.user_id, &db)?
+        .into_iter()
+        .next()
+        .ok_or(Error::NoSmtpAccount)?;
+
+    let mail = match imap_accounts.first() {
+        Some(x) => x.fetch_mail(&form.inbox, form.index)?,
+        None => None,
+    };
+
+    // Only the invitations can be answered, and the cancellations need no answer.
+    let events = mail
+        .as_ref()
+        .and_then(Mail::calendar)
+        .filter(|x| x.method == Some(Method::Request))
+        .map(|x| x.events)
+        .unwrap_or_default();
+
+    // A reply answers a single event, and the other events would not be answered.
+    if events.len() > 1 {
+        return Err(Error::InvitationError(String::from("the invitation has several events")));
+    }
+
+    let (mail, event) = match (mail, events.into_iter().next()) {
+        (Some(mail), Some(event)) => (mail, event),
+        _ => {
+            return Ok(Response::build()
+                .sized_body(Cursor::new(""))
+                .finalize())
+        },
+    };
+
+    let from = smtp_account.address()?;
+
+    let organizer = event
+        .organizer
+        .as_ref()
+        .map(|x| x.address.as_str())
+        .unwrap_or_default();
+
+    // The organizer comes from the invitation, and must not add headers to the reply.
+    let organizer = Some(organizer)
+        .filter(|x| !has_control_chars(x))
+        .and_then(Address::parse)
+        .ok_or_else(|| Error::InvalidAddress(String::from(organizer)))?;
+
+    let title = event.summary.clone().unwrap_or_default();
+    let reply = calendar::reply(&event, &from.address(), &status, Utc::now())
+        .ok_or_else(|| Error::InvitationError(String::from("an address contains control characters")))?;
+
+    let builder = MailBuilder::new()
+        .date(Local::now().into())
+        .from(from.clone())
+        .to(organizer)
+        .subject(&format!("{}: {}", answer, title))
+        .message_id(message_id(&from.domain))
+        .text(&format!("{} has {} the invitation to {}.", from, verb, title))
+        .calendar("REPLY", &reply);
+
+    let builder = match mail.headers().message_id() {
+        Some(id) => builder.in_reply_to(vec![id.clone()]),
+        None => builder,
+    };
+
+    smtp_account.send(&builder.build())?;
+
+    Ok(Response::build()
+        .sized_body(Cursor::new(""))
+        .finalize())
+}
+
+#[derive(FromForm)]
+/// A struct that serves the purpose of verifying the unsubscribe route.
+pub struct UnsubscribeForm {
+    /// The name of the mailbox of a mail sent by the mailing list.
+    inbox: String,
+
+    /// The sequence number of the mail in the mailbox.
+    index: usize,
+}
+
+#[post("/unsubscribe", data = "<form>")]
+/// A route that unsubscribes from the mailing list that sent a mail.
+///
+/// If the list can't be unsubscribed from automatically, the response contains the URL that the
+/// user has to open.
+pub fn unsubscribe<'a>(mut cookies: Cookies, form: Form<UnsubscribeForm>) -> Result<Response<'a>> {
+    let session = cookies
+        .get_private("EXAUTH")
+        .ok_or(Error::SessionDoesNotExist)?;
+
+    let db = SERVER_CONFIG.database.connect()?;
+    let session = Session::from_secret(session.value(), &db)?;
+    let imap_accounts = ImapAccount::from_user_id(session.user_id, &db)?;
+    let smtp_accounts = SmtpAccount::from_user_id(session.user_id, &db)?;
+
+    let headers = match imap_accounts.first() {
+        Some(x) => x.fetch_headers(&form.inbox, form.index, form.index + 1)?.into_iter().next(),
+        None => None,
+    };
+
+    let headers = match headers {
+        Some(headers) => headers,
+        None => {
+            return Ok(Response::build()
+                .sized_body(Cursor::new(""))
+                .finalize())
+        },
+    };
+
+    let unsubscription = list::unsubscribe(&headers, smtp_accounts.first())?;
+
+    Ok(Response::build()
+        .sized_body(Cursor::new(serde_json::to_string(&unsubscription)?))
+        .finalize())
+}
+
+#[derive(FromForm)]
+/// A struct that serves the purpose of verifying the send mail route.
+pub struct SendMailForm {
+    /// The addresses of the primary recipients of the mail, separated by commas.
+    to: String,
+
+    /// The subject of the mail.
+    subject: String,
+
+    /// The plain text of the mail.
+    text: String,
+
+    /// Whether the mail is signed with the secret key of the user.
+    sign: Option<bool>,
+
+    /// Whether the mail is encrypted with the public keys of its recipients.
+    encrypt: Option<bool>,
+
+    /// The passphrase of the secret key of the user, which is needed to sign the mail.
+    passphrase: Option<String>,
+}
+
+#[post("/send-mail", data = "<form>")]
+/// A route that sends a plain text mail through the SMTP account of the user, which may be signed
+/// and encrypted with OpenPGP.
+pub fn send_mail<'a>(mut cookies: Cookies, form: Form<SendMailForm>) -> Result<Response<'a>> {
+    let session = cookies
+        .get_private("EXAUTH")
+        .ok_or(Error::SessionDoesNotExist)?;
+
+    let db = SERVER_CONFIG.database.connect()?;
+    let session = Session::from_secret(session.value(), &db)?;
+
+    let smtp_account = SmtpAccount::from_user_id(session.user_id, &db)?
+        .into_iter()
+        .next()
+        .ok_or(Error::NoSmtpAccount)?;
+
+    let from = smtp_account.address()?;
+
+    let mut builder = MailBuilder::new()
+        .date(Local::now().into())
+        .from(from.clone())
+        .subject(&form.subject)
+        .message_id(message_id(&from.domain))
+        .text(&form.text);
+
+    // The mails are encrypted for their sender as well, so that the sent copies can be read.
+    let mut recipients = vec![from.address()];
+
+    for to in form.to.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+        let to = Some(to)
+            .filter(|x| !has_control_chars(x))
+            .and_then(Address::parse)
+            .ok_or_else(|| Error::InvalidAddress(String::from(to)))?;
+        recipients.push(to.address());
+        builder = builder.to(to);
+    }
+
+    if recipients.len() == 1 {
+        return Err(Error::MissingArgumentInForm(String::from("to")));
+    }
+
+    let passphrase = match (form.sign, &form.passphrase) {
+        (Some(true), Some(passphrase)) => Some(passphrase.as_str()),
+        (Some(true), None) => return Err(Error::MissingArgumentInForm(String::from("passphrase"))),
+        _ => None,
+    };
+
+    let recipients = match form.encrypt {
+        Some(true) => Some(&recipients[..]),
+        _ => None,
+    };
+
+    let keys = PgpKey::from_user_id(session.user_id, &db)?;
+    let mail = pgp::protect(builder.build(), &keys, passphrase, recipients)?;
+    smtp_account.send(&mail)?;
+
+    Ok(Response::build()
+        .sized_body(Cursor::new(""))
         .finalize())
 }
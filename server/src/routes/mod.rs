@@ -3,6 +3,7 @@
 pub mod login;
 pub mod new_user;
 pub mod imap_account;
+pub mod pgp;
 
 use std::fs::File;
 use rocket::response::Response;
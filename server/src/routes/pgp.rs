@@ -0,0 +1,42 @@
+//! This module contains the routes related to the OpenPGP keys of the users.
+
+use std::io::Cursor;
+use rocket::response::Response;
+use rocket::request::Form;
+use rocket::http::Cookies;
+
+use crate::{SERVER_CONFIG, Error, Result};
+use crate::auth::session::Session;
+use crate::mailbox::pgp::{Keyring, PgpKey};
+
+#[derive(FromForm)]
+/// A struct that serves the purpose of verifying the add pgp key route.
+pub struct PgpKeyForm {
+    /// The armored key.
+    armored: String,
+
+    /// Whether the key is the secret key of the user, protected by its passphrase, rather than the
+    /// public key of one of its correspondents.
+    secret: Option<bool>,
+}
+
+#[post("/add-pgp-key", data = "<form>")]
+/// A route that adds a public key to the keyring of a user, or replaces the secret key of the user.
+pub fn add_pgp_key<'a>(mut cookies: Cookies, form: Form<PgpKeyForm>) -> Result<Response<'a>> {
+    let session = cookies
+        .get_private("EXAUTH")
+        .ok_or(Error::SessionDoesNotExist)?;
+
+    let db = SERVER_CONFIG.database.connect()?;
+    let session = Session::from_secret(session.value(), &db)?;
+
+    // The key is imported in an empty keyring first, to check that it is valid.
+    Keyring::new(&[])?.import(&form.armored)?;
+
+    PgpKey::create(session.user_id, &form.armored, form.secret == Some(true))
+        .save(&db)?;
+
+    Ok(Response::build()
+        .sized_body(Cursor::new(""))
+        .finalize())
+}
@@ -1,3 +1,11 @@
+table! {
+    allowed_senders (id) {
+        id -> Int4,
+        user_id -> Int4,
+        address -> Varchar,
+    }
+}
+
 table! {
     imap_accounts (id) {
         id -> Int4,
@@ -8,6 +16,15 @@ table! {
     }
 }
 
+table! {
+    pgp_keys (id) {
+        id -> Int4,
+        user_id -> Int4,
+        armored -> Text,
+        secret -> Bool,
+    }
+}
+
 table! {
     sessions (id) {
         id -> Int4,
@@ -37,12 +54,16 @@ table! {
     }
 }
 
+joinable!(allowed_senders -> users (user_id));
 joinable!(imap_accounts -> users (user_id));
+joinable!(pgp_keys -> users (user_id));
 joinable!(sessions -> users (user_id));
 joinable!(smtp_accounts -> users (user_id));
 
 allow_tables_to_appear_in_same_query!(
+    allowed_senders,
     imap_accounts,
+    pgp_keys,
     sessions,
     smtp_accounts,
     users,
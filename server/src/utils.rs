@@ -1,5 +1,10 @@
 //! This module contains some useful functions and macros.
 
+use rand::Rng;
+use rand::rngs::OsRng;
+use rand::distributions::Alphanumeric;
+use nom_mail_parser::MessageId;
+
 /// Extracts the value of the option and panic if none.
 ///
 /// This should not be used, except for debugging. It produces slightly better output than unwrap.
@@ -24,3 +29,59 @@ macro_rules! extract {
     }
 }
 
+/// Encodes a string so that it can be used as the value of a query parameter.
+pub fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|c| match c {
+            b'a' ..= b'z' | b'A' ..= b'Z' | b'0' ..= b'9' | b'-' | b'.' | b'_' | b'~' => (c as char).to_string(),
+            c => format!("%{:02X}", c),
+        })
+        .collect()
+}
+
+/// Generates a new random message id for a mail sent from this domain.
+pub fn message_id(domain: &str) -> MessageId {
+    let mut rng = OsRng::new().unwrap();
+    let id = rng
+        .sample_iter(&Alphanumeric)
+        .take(40)
+        .collect::<String>();
+
+    MessageId(format!("{}@{}", id, domain))
+}
+
+/// Decodes a percent encoded string, such as the query parameters of a URL.
+///
+/// The invalid escapes are kept as they are, and the invalid UTF-8 is replaced.
+pub fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escaped = value
+            .get(i + 1 .. i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|x| u8::from_str_radix(x, 16).ok());
+
+        match escaped {
+            Some(c) => {
+                decoded.push(c);
+                i += 3;
+            },
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            },
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Checks if a string contains control chars, such as line breaks, which are not allowed in the
+/// values that end up in the headers of a mail, such as its recipients and its subject.
+pub fn has_control_chars(value: &str) -> bool {
+    value.chars().any(char::is_control)
+}